@@ -1,12 +1,20 @@
+use std::collections::HashSet;
 use std::env;
 use std::fmt::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
+use chrono::NaiveDate;
 use cratesfyi::db::{self, add_path_into_database, Pool};
-use cratesfyi::utils::{remove_crate_priority, set_crate_priority};
+use cratesfyi::storage::{StorageKind, StorageMigration};
+use cratesfyi::utils::{
+    queue_rebuilds, remove_crate_priority, set_crate_priority, BuildStatus, DaemonRole,
+    RebuildFilter, RebuildThrottle,
+};
 use cratesfyi::{
-    BuildQueue, Config, DocBuilder, DocBuilderOptions, RustwideBuilder, Server, Storage,
+    check_metadata, BuildQueue, CancelResult, Config, DocBuilder, DocBuilderOptions, ErrorCategory,
+    QueueFilter, RustwideBuilder, Server, Storage,
 };
 use failure::{err_msg, Error, ResultExt};
 use once_cell::sync::OnceCell;
@@ -94,6 +102,15 @@ enum CommandLine {
             possible_values(Toggle::VARIANTS)
         )]
         registry_watcher: Toggle,
+
+        /// Roles to run in this process, to run them in separate processes or hosts
+        #[structopt(
+            long = "roles",
+            use_delimiter = true,
+            default_value = "web,watcher,builder,scheduler",
+            possible_values(DaemonRole::VARIANTS)
+        )]
+        roles: Vec<DaemonRole>,
     },
 
     /// Database operations
@@ -131,17 +148,22 @@ impl CommandLine {
             Self::Daemon {
                 foreground,
                 registry_watcher,
+                mut roles,
             } => {
                 if foreground {
                     log::warn!("--foreground was passed, but there is no need for it anymore");
                 }
 
+                if registry_watcher == Toggle::Disabled {
+                    roles.retain(|&role| role != DaemonRole::Watcher);
+                }
+
                 cratesfyi::utils::start_daemon(
                     ctx.config()?,
                     ctx.pool()?,
                     ctx.build_queue()?,
                     ctx.storage()?,
-                    registry_watcher == Toggle::Enabled,
+                    &roles,
                 )?;
             }
             Self::Database { subcommand } => subcommand.handle_args(ctx)?,
@@ -172,6 +194,99 @@ enum QueueSubcommand {
         build_priority: i32,
     },
 
+    /// List the crates in the build queue
+    List {
+        /// Only list the crates that failed to build too many times to be retried
+        #[structopt(long = "failed")]
+        failed: bool,
+        /// Only list the crates whose name matches this pattern (`%` matches any characters)
+        #[structopt(long = "name")]
+        name_pattern: Option<String>,
+        /// Only list the crates whose last attempt failed for this reason
+        #[structopt(long = "category", possible_values(ErrorCategory::VARIANTS))]
+        error_category: Option<ErrorCategory>,
+    },
+
+    /// Remove a crate from the build queue
+    Remove {
+        /// Name of the queued crate
+        #[structopt(name = "CRATE_NAME")]
+        crate_name: String,
+        /// Version of the queued crate
+        #[structopt(name = "CRATE_VERSION")]
+        crate_version: String,
+    },
+
+    /// Cancel the build of a queued crate, aborting it if it's running
+    Cancel {
+        /// Name of the queued crate
+        #[structopt(name = "CRATE_NAME")]
+        crate_name: String,
+        /// Version of the queued crate
+        #[structopt(name = "CRATE_VERSION")]
+        crate_version: String,
+        /// Reason of the cancellation, recorded as the last error of the crate
+        #[structopt(long = "reason", default_value = "cancelled by an administrator")]
+        reason: String,
+    },
+
+    /// Build again the crates that failed too many times to be retried, or were cancelled
+    Retry {
+        /// Only retry the crates whose name matches this pattern (`%` matches any characters)
+        #[structopt(long = "name")]
+        name_pattern: Option<String>,
+    },
+
+    /// Change the priority of a queued crate
+    SetPriority {
+        /// Name of the queued crate
+        #[structopt(name = "CRATE_NAME")]
+        crate_name: String,
+        /// Version of the queued crate
+        #[structopt(name = "CRATE_VERSION")]
+        crate_version: String,
+        /// New priority of the crate (new crate builds get priority 0)
+        #[structopt(name = "BUILD_PRIORITY", allow_hyphen_values = true)]
+        build_priority: i32,
+    },
+
+    /// Queue rebuilds of the releases matching all the given filters
+    Rebuild {
+        /// Only rebuild releases documented with a nightly older than this date (YYYY-MM-DD)
+        #[structopt(long = "rustc-older-than")]
+        rustc_older_than: Option<NaiveDate>,
+        /// Only rebuild releases whose latest build has this status
+        #[structopt(long = "build-status", possible_values(BuildStatus::VARIANTS))]
+        build_status: Option<BuildStatus>,
+        /// Only rebuild crates whose name matches this pattern (`%` matches any characters)
+        #[structopt(long = "name")]
+        name_pattern: Option<String>,
+        /// Only rebuild releases whose documentation is missing this target
+        #[structopt(long = "missing-target")]
+        missing_target: Option<String>,
+        /// Only rebuild releases published on or after this date (YYYY-MM-DD)
+        #[structopt(long = "released-after")]
+        released_after: Option<NaiveDate>,
+        /// Only rebuild releases published before this date (YYYY-MM-DD)
+        #[structopt(long = "released-before")]
+        released_before: Option<NaiveDate>,
+        /// Priority of the rebuilds (new crate builds get priority 0)
+        #[structopt(short = "p", long = "priority", default_value = "20")]
+        priority: i32,
+        /// Number of releases added to the queue at once
+        #[structopt(long = "batch-size", default_value = "100")]
+        batch_size: usize,
+        /// Seconds to wait between two batches
+        #[structopt(long = "delay", default_value = "5")]
+        delay: u64,
+        /// Wait before adding a batch while the queue has at least this many pending crates
+        #[structopt(long = "max-queued")]
+        max_queued: Option<usize>,
+        /// Only print the releases which would be rebuilt
+        #[structopt(long = "dry-run")]
+        dry_run: bool,
+    },
+
     /// Interactions with build queue priorities
     DefaultPriority {
         #[structopt(subcommand)]
@@ -186,9 +301,162 @@ impl QueueSubcommand {
                 crate_name,
                 crate_version,
                 build_priority,
-            } => ctx
+            } => {
+                if !ctx
+                    .build_queue()?
+                    .add_crate(&crate_name, &crate_version, build_priority)?
+                {
+                    println!("{} {} is already queued", crate_name, crate_version);
+                }
+            }
+
+            Self::List {
+                failed,
+                name_pattern,
+                error_category,
+            } => {
+                let crates = ctx.build_queue()?.list_crates(&QueueFilter {
+                    failed,
+                    name_pattern,
+                    error_category,
+                })?;
+
+                for krate in crates {
+                    print!(
+                        "{} {} (priority: {}, attempts: {})",
+                        krate.name, krate.version, krate.priority, krate.attempt
+                    );
+                    if let Some(category) = krate.error_category {
+                        print!(", {}", category);
+                    }
+                    if let Some(next_attempt_at) = krate.next_attempt_at.filter(|_| !failed) {
+                        print!(", next attempt after {}", next_attempt_at);
+                    }
+                    println!();
+
+                    if let Some(error) = krate.last_error {
+                        for line in error.lines() {
+                            println!("    {}", line);
+                        }
+                    }
+                }
+            }
+
+            Self::Remove {
+                crate_name,
+                crate_version,
+            } => {
+                if !ctx
+                    .build_queue()?
+                    .remove_crate(&crate_name, &crate_version)?
+                {
+                    return Err(queue_not_found(&crate_name, &crate_version));
+                }
+            }
+
+            Self::Cancel {
+                crate_name,
+                crate_version,
+                reason,
+            } => match ctx
                 .build_queue()?
-                .add_crate(&crate_name, &crate_version, build_priority)?,
+                .cancel_crate(&crate_name, &crate_version, &reason)?
+            {
+                CancelResult::NotQueued => {
+                    return Err(queue_not_found(&crate_name, &crate_version))
+                }
+                CancelResult::Cancelled => {
+                    println!("{} {} won't be built", crate_name, crate_version)
+                }
+                CancelResult::Aborting { worker_id } => println!(
+                    "{} {} is being built by {}, the build will be aborted shortly",
+                    crate_name, crate_version, worker_id
+                ),
+            },
+
+            Self::Retry { name_pattern } => {
+                let retried = ctx
+                    .build_queue()?
+                    .retry_failed_crates(name_pattern.as_deref())?;
+                println!("{} crates will be built again", retried);
+            }
+
+            Self::SetPriority {
+                crate_name,
+                crate_version,
+                build_priority,
+            } => {
+                if !ctx
+                    .build_queue()?
+                    .set_priority(&crate_name, &crate_version, build_priority)?
+                {
+                    return Err(queue_not_found(&crate_name, &crate_version));
+                }
+            }
+
+            Self::Rebuild {
+                rustc_older_than,
+                build_status,
+                name_pattern,
+                missing_target,
+                released_after,
+                released_before,
+                priority,
+                batch_size,
+                delay,
+                max_queued,
+                dry_run,
+            } => {
+                let filter = RebuildFilter {
+                    rustc_older_than,
+                    build_status,
+                    name_pattern,
+                    missing_target,
+                    released_after,
+                    released_before,
+                };
+                let releases = filter.select(&*ctx.conn()?)?;
+                println!("{} releases match the filters", releases.len());
+
+                if dry_run {
+                    let queued = ctx
+                        .build_queue()?
+                        .queued_crates()?
+                        .into_iter()
+                        .map(|krate| (krate.name, krate.version))
+                        .collect::<HashSet<_>>();
+                    let already_queued = releases.iter().filter(|r| queued.contains(r)).count();
+                    println!("{} of them are already queued", already_queued);
+                    for (name, version) in releases.iter().take(DRY_RUN_SAMPLE) {
+                        println!("    {} {}", name, version);
+                    }
+                    if releases.len() > DRY_RUN_SAMPLE {
+                        println!("    ...");
+                    }
+                    return Ok(());
+                }
+
+                let throttle = RebuildThrottle {
+                    batch_size,
+                    delay: Duration::from_secs(delay),
+                    max_queued,
+                };
+                let added = queue_rebuilds(
+                    &*ctx.build_queue()?,
+                    &releases,
+                    priority,
+                    &throttle,
+                    |processed, added| {
+                        println!(
+                            "{}/{} releases processed, {} added to the queue",
+                            processed,
+                            releases.len(),
+                            added
+                        )
+                    },
+                )?;
+                println!("{} releases added to the queue", added);
+            }
 
             Self::DefaultPriority { subcommand } => subcommand.handle_args(ctx)?,
         }
@@ -196,6 +464,13 @@ impl QueueSubcommand {
     }
 }
 
+fn queue_not_found(name: &str, version: &str) -> Error {
+    err_msg(format!("{} {} is not in the build queue", name, version))
+}
+
+/// Number of matching releases printed by `queue rebuild --dry-run`.
+const DRY_RUN_SAMPLE: usize = 20;
+
 #[derive(Debug, Clone, PartialEq, Eq, StructOpt)]
 enum PrioritySubcommand {
     /// Set all crates matching a pattern to a priority level
@@ -279,6 +554,11 @@ struct Build {
 
 impl Build {
     pub fn handle_args(self, ctx: Context) -> Result<(), Error> {
+        // Checking a manifest doesn't need the database nor the build directories.
+        if let BuildSubcommand::CheckMetadata { manifest } = &self.subcommand {
+            return print_metadata_check(manifest);
+        }
+
         let docbuilder = {
             let mut doc_options = DocBuilderOptions::from_prefix(self.prefix);
 
@@ -301,6 +581,13 @@ impl Build {
     }
 }
 
+fn print_metadata_check(manifest: &Path) -> Result<(), Error> {
+    let manifest = std::fs::read_to_string(manifest)
+        .with_context(|_| format!("failed to read {}", manifest.display()))?;
+    print!("{}", check_metadata(&manifest));
+    Ok(())
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, StructOpt)]
 enum BuildSubcommand {
     /// Builds documentation of every crate
@@ -325,6 +612,25 @@ enum BuildSubcommand {
         local: Option<PathBuf>,
     },
 
+    /// Prints the command and the environment used to build the documentation of a crate
+    Explain {
+        /// Crate name
+        #[structopt(name = "CRATE_NAME")]
+        crate_name: String,
+
+        /// Version of crate
+        #[structopt(name = "CRATE_VERSION")]
+        crate_version: String,
+
+        /// Target to build the documentation for, instead of the default target of the crate
+        #[structopt(long = "target")]
+        target: Option<String>,
+
+        /// Also run the build, outside of the sandbox. Only use it for trusted crates!
+        #[structopt(long = "run")]
+        run: bool,
+    },
+
     /// update the currently installed rustup toolchain
     UpdateToolchain {
         /// Update the toolchain only if no toolchain is currently installed
@@ -335,10 +641,26 @@ enum BuildSubcommand {
     /// Adds essential files for the installed version of rustc
     AddEssentialFiles,
 
-    /// Locks cratesfyi daemon to stop building new crates
-    Lock,
+    /// Checks the docs.rs metadata of a manifest, and prints how the documentation would be built
+    CheckMetadata {
+        /// Path of the `Cargo.toml` to check
+        #[structopt(name = "MANIFEST")]
+        manifest: PathBuf,
+    },
+
+    /// Pauses the builds of all the daemons, and shows it on the queue page
+    Lock {
+        /// Why the builds are paused
+        #[structopt(long = "reason")]
+        reason: Option<String>,
+
+        /// Who paused the builds, the current user by default
+        #[structopt(long = "by")]
+        by: Option<String>,
+    },
 
-    /// Unlocks cratesfyi daemon to continue building new crates
+    /// Resumes the builds of all the daemons. The lock file created by `lock` is only removed
+    /// on this host
     Unlock,
 
     PrintOptions,
@@ -350,7 +672,8 @@ impl BuildSubcommand {
             Self::World => {
                 docbuilder.load_cache().context("Failed to load cache")?;
 
-                let mut builder = RustwideBuilder::init(ctx.pool()?, ctx.storage()?)?;
+                let mut builder =
+                    RustwideBuilder::init(ctx.config()?, ctx.pool()?, ctx.storage()?)?;
                 builder
                     .build_world(&mut docbuilder)
                     .context("Failed to build world")?;
@@ -364,7 +687,7 @@ impl BuildSubcommand {
                 local,
             } => {
                 docbuilder.load_cache().context("Failed to load cache")?;
-                let mut builder = RustwideBuilder::init(ctx.pool()?, ctx.storage()?)
+                let mut builder = RustwideBuilder::init(ctx.config()?, ctx.pool()?, ctx.storage()?)
                     .context("failed to initialize rustwide")?;
 
                 if let Some(path) = local {
@@ -386,6 +709,20 @@ impl BuildSubcommand {
                 docbuilder.save_cache().context("Failed to save cache")?;
             }
 
+            Self::Explain {
+                crate_name,
+                crate_version,
+                target,
+                run,
+            } => {
+                let mut builder =
+                    RustwideBuilder::init(ctx.config()?, ctx.pool()?, ctx.storage()?)?;
+                let invocation = builder
+                    .explain_build(&crate_name, &crate_version, target.as_deref(), run)
+                    .context("failed to assemble the build invocation")?;
+                print!("{}", invocation);
+            }
+
             Self::UpdateToolchain { only_first_time } => {
                 if only_first_time {
                     let conn = ctx
@@ -401,20 +738,31 @@ impl BuildSubcommand {
                     }
                 }
 
-                let mut builder = RustwideBuilder::init(ctx.pool()?, ctx.storage()?)?;
+                let mut builder =
+                    RustwideBuilder::init(ctx.config()?, ctx.pool()?, ctx.storage()?)?;
                 builder
                     .update_toolchain()
                     .context("failed to update toolchain")?;
             }
 
             Self::AddEssentialFiles => {
-                let mut builder = RustwideBuilder::init(ctx.pool()?, ctx.storage()?)?;
+                let mut builder =
+                    RustwideBuilder::init(ctx.config()?, ctx.pool()?, ctx.storage()?)?;
                 builder
                     .add_essential_files()
                     .context("failed to add essential files")?;
             }
 
-            Self::Lock => docbuilder.lock().context("Failed to lock")?,
+            Self::CheckMetadata { manifest } => print_metadata_check(&manifest)?,
+
+            Self::Lock { reason, by } => {
+                let by = by
+                    .or_else(|| env::var("USER").ok())
+                    .unwrap_or_else(|| "unknown".into());
+                docbuilder
+                    .lock(&by, reason.as_deref())
+                    .context("Failed to lock")?
+            }
             Self::Unlock => docbuilder.unlock().context("Failed to unlock")?,
             Self::PrintOptions => println!("{:?}", docbuilder.options()),
         }
@@ -458,6 +806,28 @@ enum DatabaseSubcommand {
         #[structopt(subcommand)]
         command: BlacklistSubcommand,
     },
+
+    /// Show or change the last registry index commit added to the queue
+    IndexState {
+        #[structopt(subcommand)]
+        command: IndexStateSubcommand,
+    },
+
+    /// Copy the stored documentation and source files to another storage backend
+    MigrateStorage {
+        /// Storage backend to copy the files from (database, s3 or local-fs)
+        #[structopt(long = "from")]
+        from: StorageKind,
+        /// Storage backend to copy the files to (database, s3 or local-fs)
+        #[structopt(long = "to")]
+        to: StorageKind,
+        /// Only copy the files whose path starts with this prefix, like `rustdoc/foo`
+        #[structopt(long = "prefix", default_value = "")]
+        prefix: String,
+        /// Start from the beginning instead of resuming an interrupted migration
+        #[structopt(long = "restart")]
+        restart: bool,
+    },
 }
 
 impl DatabaseSubcommand {
@@ -489,6 +859,22 @@ impl DatabaseSubcommand {
                 command: DeleteSubcommand::Crate { name },
             } => db::delete_crate(&*ctx.conn()?, &name).context("failed to delete the crate")?,
             Self::Blacklist { command } => command.handle_args(ctx)?,
+            Self::IndexState { command } => command.handle_args(ctx)?,
+
+            Self::MigrateStorage {
+                from,
+                to,
+                prefix,
+                restart,
+            } => {
+                let copied = StorageMigration { from, to, prefix }
+                    .run(ctx.pool()?, &*ctx.config()?, restart)
+                    .context("failed to migrate the storage")?;
+                println!(
+                    "copied {} files from the {} storage to {}",
+                    copied, from, to
+                );
+            }
         }
         Ok(())
     }
@@ -519,22 +905,68 @@ impl BlacklistSubcommand {
         let conn = &*ctx.conn()?;
         match self {
             Self::List => {
-                let crates = db::blacklist::list_crates(&conn)
+                let crates = db::blacklist::list_crates(conn)
                     .context("failed to list crates on blacklist")?;
 
                 println!("{}", crates.join("\n"));
             }
 
-            Self::Add { crate_name } => db::blacklist::add_crate(&conn, &crate_name)
+            Self::Add { crate_name } => db::blacklist::add_crate(conn, &crate_name)
                 .context("failed to add crate to blacklist")?,
 
-            Self::Remove { crate_name } => db::blacklist::remove_crate(&conn, &crate_name)
+            Self::Remove { crate_name } => db::blacklist::remove_crate(conn, &crate_name)
                 .context("failed to remove crate from blacklist")?,
         }
         Ok(())
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, StructOpt)]
+enum IndexStateSubcommand {
+    /// Print the last registry index commit added to the queue
+    Show,
+
+    /// Set the last registry index commit added to the queue, only changes after it will be added
+    Set {
+        /// Full hash of the commit
+        #[structopt(name = "COMMIT")]
+        commit: String,
+    },
+
+    /// Forget the stored commit, falling back to the tracking branch of the local index repository
+    Reset,
+}
+
+impl IndexStateSubcommand {
+    fn handle_args(self, ctx: Context) -> Result<(), Error> {
+        let conn = &*ctx.conn()?;
+        match self {
+            Self::Show => {
+                match db::index_state::get_last_seen_commit(conn)
+                    .context("failed to read the index state")?
+                {
+                    Some(commit) => println!("{}", commit),
+                    None => println!("No index commit is stored in the database"),
+                }
+            }
+
+            Self::Set { commit } => db::index_state::set_last_seen_commit(conn, &commit)
+                .context("failed to set the index state")?,
+
+            Self::Reset => {
+                if db::index_state::reset_last_seen_commit(conn)
+                    .context("failed to reset the index state")?
+                {
+                    println!("Removed the stored index commit");
+                } else {
+                    println!("No index commit was stored in the database");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, StructOpt)]
 enum DeleteSubcommand {
     /// Delete a whole crate
@@ -584,7 +1016,9 @@ impl Context {
     fn storage(&self) -> Result<Arc<Storage>, Error> {
         Ok(self
             .storage
-            .get_or_try_init::<_, Error>(|| Ok(Arc::new(Storage::new(self.pool()?))))?
+            .get_or_try_init::<_, Error>(|| {
+                Ok(Arc::new(Storage::new(self.pool()?, &*self.config()?)?))
+            })?
             .clone())
     }
 
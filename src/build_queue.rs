@@ -1,21 +1,169 @@
 use crate::config::Config;
-use crate::db::Pool;
+use crate::db::{Pool, PoolConnection};
 use crate::error::Result;
-use log::error;
+use chrono::{DateTime, Utc};
+use failure::{Context, Error};
+use fallible_iterator::FallibleIterator;
+use log::{error, warn};
+use parking_lot::Mutex;
+use postgres::rows::Row;
+use postgres::types::ToSql;
+use postgres::Connection;
+use rustwide::cmd::CommandError;
+use rustwide::PrepareError;
+use std::fmt::{self, Write};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Channel notified when crates are ready to be built, see `BuildQueue::listen`.
+const QUEUE_CHANNEL: &str = "docsrs_queue";
+
+/// How often the builders check whether the build of their claimed crate was cancelled, at most.
+const CANCELLATION_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Columns needed by `QueuedCrate::from_row`.
+const QUEUED_CRATE_COLUMNS: &str =
+    "id, name, version, priority, attempt, last_error, error_category, next_attempt_at";
 
 #[derive(Debug, Clone, Eq, PartialEq, serde::Serialize)]
-pub(crate) struct QueuedCrate {
+pub struct QueuedCrate {
     #[serde(skip)]
     id: i32,
-    pub(crate) name: String,
-    pub(crate) version: String,
-    pub(crate) priority: i32,
+    pub name: String,
+    pub version: String,
+    pub priority: i32,
+    pub attempt: i32,
+    pub last_error: Option<String>,
+    pub error_category: Option<ErrorCategory>,
+    pub next_attempt_at: Option<DateTime<Utc>>,
+}
+
+impl QueuedCrate {
+    fn from_row(row: Row) -> Self {
+        QueuedCrate {
+            id: row.get("id"),
+            name: row.get("name"),
+            version: row.get("version"),
+            priority: row.get("priority"),
+            attempt: row.get("attempt"),
+            last_error: row.get("last_error"),
+            error_category: row
+                .get::<_, Option<String>>("error_category")
+                .and_then(|category| category.parse().ok()),
+            next_attempt_at: row.get("next_attempt_at"),
+        }
+    }
+}
+
+/// The reason why building a crate from the queue failed.
+///
+/// Code building crates can attach a category to its errors with `ResultExt::context`, otherwise
+/// it's inferred from the errors returned by rustwide.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    strum::EnumString,
+    strum::EnumVariantNames,
+    strum::IntoStaticStr,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ErrorCategory {
+    /// The crate or its dependencies couldn't be downloaded.
+    Fetch,
+    /// The sandbox ran out of memory.
+    SandboxOom,
+    /// A command took longer than the time limit.
+    Timeout,
+    /// Running rustdoc failed before any documentation could be produced.
+    Rustdoc,
+    /// The build was cancelled with `BuildQueue::cancel_crate`.
+    Cancelled,
+    Other,
+}
+
+impl ErrorCategory {
+    /// Categorizes an error returned while building a crate.
+    pub(crate) fn of(err: &Error) -> Self {
+        // Resource limits are checked first, as they're the root cause of any other failure.
+        for cause in err.iter_chain() {
+            match cause.downcast_ref::<CommandError>() {
+                Some(CommandError::SandboxOOM) => return ErrorCategory::SandboxOom,
+                Some(CommandError::Timeout(_)) | Some(CommandError::NoOutputFor(_)) => {
+                    return ErrorCategory::Timeout
+                }
+                _ => {}
+            }
+        }
+
+        for cause in err.iter_chain() {
+            if let Some(context) = cause.downcast_ref::<Context<ErrorCategory>>() {
+                return *context.get_context();
+            }
+            if cause.downcast_ref::<PrepareError>().is_some() {
+                return ErrorCategory::Fetch;
+            }
+        }
+
+        ErrorCategory::Other
+    }
+
+    /// Whether a build failing for this reason is worth retrying. Cancelled builds and rustdoc
+    /// failures would fail the same way again.
+    pub(crate) fn is_retried(self) -> bool {
+        match self {
+            ErrorCategory::Rustdoc | ErrorCategory::Cancelled => false,
+            ErrorCategory::Fetch
+            | ErrorCategory::SandboxOom
+            | ErrorCategory::Timeout
+            | ErrorCategory::Other => true,
+        }
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        self.into()
+    }
+}
+
+impl fmt::Display for ErrorCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ErrorCategory::Fetch => "failed to fetch the crate",
+            ErrorCategory::SandboxOom => "the sandbox ran out of memory",
+            ErrorCategory::Timeout => "the build timed out",
+            ErrorCategory::Rustdoc => "failed to run rustdoc",
+            ErrorCategory::Cancelled => "the build was cancelled",
+            ErrorCategory::Other => "the build failed",
+        })
+    }
+}
+
+/// Filters for `BuildQueue::list_crates`. A crate is listed if it matches all the filters.
+#[derive(Debug, Clone, Default)]
+pub struct QueueFilter {
+    /// List the crates that failed too many times to be retried, instead of the pending ones
+    pub failed: bool,
+    /// Pattern the name of the crates must match, with the `LIKE` syntax
+    pub name_pattern: Option<String>,
+    /// Only list the crates whose last attempt failed for this reason
+    pub error_category: Option<ErrorCategory>,
 }
 
 #[derive(Debug)]
 pub struct BuildQueue {
     db: Pool,
     max_attempts: i32,
+    worker_id: String,
+    lease: Duration,
+    backoff: Duration,
+    /// Cancellation of the build of the crate claimed by this worker, if any
+    claimed: Mutex<Option<Arc<Cancellation>>>,
 }
 
 impl BuildQueue {
@@ -23,15 +171,49 @@ impl BuildQueue {
         BuildQueue {
             db,
             max_attempts: config.build_attempts.into(),
+            worker_id: config.build_worker_id.clone(),
+            lease: Duration::from_secs(config.build_lease_seconds),
+            backoff: Duration::from_secs(config.build_backoff_seconds),
+            claimed: Mutex::new(None),
         }
     }
 
-    pub fn add_crate(&self, name: &str, version: &str, priority: i32) -> Result<()> {
-        self.db.get()?.execute(
-            "INSERT INTO queue (name, version, priority) VALUES ($1, $2, $3);",
+    /// Adds a crate to the build queue. Adding a crate that is already queued is a no-op.
+    ///
+    /// Returns whether the crate was added.
+    pub fn add_crate(&self, name: &str, version: &str, priority: i32) -> Result<bool> {
+        let conn = self.db.get()?;
+        let added = conn.execute(
+            "INSERT INTO queue (name, version, priority)
+             VALUES ($1, $2, $3)
+             ON CONFLICT (name, version) DO NOTHING;",
             &[&name, &version, &priority],
         )?;
-        Ok(())
+        if added > 0 {
+            notify_builders(&conn)?;
+        }
+        Ok(added > 0)
+    }
+
+    /// Adds the releases to the queue with the same priority, skipping the ones already queued.
+    ///
+    /// Returns the number of releases added.
+    pub fn add_crates(&self, releases: &[(String, String)], priority: i32) -> Result<usize> {
+        let (names, versions): (Vec<&str>, Vec<&str>) = releases
+            .iter()
+            .map(|(name, version)| (name.as_str(), version.as_str()))
+            .unzip();
+        let conn = self.db.get()?;
+        let added = conn.execute(
+            "INSERT INTO queue (name, version, priority)
+             SELECT name, version, $3 FROM UNNEST($1::TEXT[], $2::TEXT[]) AS t (name, version)
+             ON CONFLICT (name, version) DO NOTHING;",
+            &[&names, &versions, &priority],
+        )?;
+        if added > 0 {
+            notify_builders(&conn)?;
+        }
+        Ok(added as usize)
     }
 
     pub(crate) fn pending_count(&self) -> Result<usize> {
@@ -58,53 +240,362 @@ impl BuildQueue {
         Ok(res.get(0).get::<_, i64>(0) as usize)
     }
 
-    pub(crate) fn queued_crates(&self) -> Result<Vec<QueuedCrate>> {
+    pub fn queued_crates(&self) -> Result<Vec<QueuedCrate>> {
+        self.list_crates(&QueueFilter::default())
+    }
+
+    /// Returns the crates that failed to build too many times, and won't be retried.
+    pub fn failed_crates(&self) -> Result<Vec<QueuedCrate>> {
+        self.list_crates(&QueueFilter {
+            failed: true,
+            ..QueueFilter::default()
+        })
+    }
+
+    /// Returns the crates matching the filter, in the order they will be built. Failed crates are
+    /// returned in the order they were queued.
+    pub fn list_crates(&self, filter: &QueueFilter) -> Result<Vec<QueuedCrate>> {
+        let mut conditions = vec![if filter.failed {
+            "attempt >= $1"
+        } else {
+            "attempt < $1"
+        }
+        .to_string()];
+        let mut params: Vec<&dyn ToSql> = vec![&self.max_attempts];
+        if let Some(pattern) = &filter.name_pattern {
+            params.push(pattern);
+            conditions.push(format!("name LIKE ${}", params.len()));
+        }
+        let category = filter.error_category.map(ErrorCategory::as_str);
+        if let Some(category) = &category {
+            params.push(category);
+            conditions.push(format!("error_category = ${}", params.len()));
+        }
+
         let query = self.db.get()?.query(
-            "SELECT id, name, version, priority
-             FROM queue
-             WHERE attempt < $1
-             ORDER BY priority ASC, attempt ASC, id ASC",
-            &[&self.max_attempts],
+            &format!(
+                "SELECT {}
+                 FROM queue
+                 WHERE {}
+                 ORDER BY {}",
+                QUEUED_CRATE_COLUMNS,
+                conditions.join(" AND "),
+                if filter.failed {
+                    "id ASC"
+                } else {
+                    "priority ASC, attempt ASC, id ASC"
+                }
+            ),
+            &params,
+        )?;
+
+        Ok(query.into_iter().map(QueuedCrate::from_row).collect())
+    }
+
+    /// Removes a crate from the queue. Returns whether the crate was queued.
+    ///
+    /// If the crate is being built, the build isn't interrupted, but it won't be retried if it
+    /// fails.
+    pub fn remove_crate(&self, name: &str, version: &str) -> Result<bool> {
+        let removed = self.db.get()?.execute(
+            "DELETE FROM queue WHERE name = $1 AND version = $2;",
+            &[&name, &version],
+        )?;
+        Ok(removed > 0)
+    }
+
+    /// Resets the attempts of the crates which failed too many times, so that they're built again
+    /// as soon as possible. Only the crates whose name matches `name_pattern` are reset, if given.
+    ///
+    /// Returns the number of crates put back into the queue.
+    pub fn retry_failed_crates(&self, name_pattern: Option<&str>) -> Result<usize> {
+        let conn = self.db.get()?;
+        let retried = conn.execute(
+            "UPDATE queue
+             SET attempt = 0, next_attempt_at = NULL, cancel_reason = NULL
+             WHERE attempt >= $1 AND ($2::TEXT IS NULL OR name LIKE $2);",
+            &[&self.max_attempts, &name_pattern],
+        )?;
+        if retried > 0 {
+            notify_builders(&conn)?;
+        }
+        Ok(retried as usize)
+    }
+
+    /// Cancels the build of a queued crate, recording `reason` as its last error. The crate stays
+    /// in the queue with the crates which failed too many times, and isn't built again unless it's
+    /// retried.
+    ///
+    /// If the crate is being built, its builder aborts the build the next time it renews its
+    /// lease.
+    pub fn cancel_crate(&self, name: &str, version: &str, reason: &str) -> Result<CancelResult> {
+        let conn = self.db.get()?;
+        // Crates with a cancel reason are never claimed, so a crate which isn't being built at
+        // this point won't be afterwards either
+        let rows = conn.query(
+            "UPDATE queue
+             SET cancel_reason = $3
+             WHERE name = $1 AND version = $2
+             RETURNING id, locked_by;",
+            &[&name, &version, &reason],
+        )?;
+        if rows.is_empty() {
+            return Ok(CancelResult::NotQueued);
+        }
+        let row = rows.get(0);
+        if let Some(worker_id) = row.get::<_, Option<String>>("locked_by") {
+            return Ok(CancelResult::Aborting { worker_id });
+        }
+
+        conn.execute(
+            "UPDATE queue
+             SET attempt = GREATEST(attempt, $2),
+                 last_error = cancel_reason,
+                 error_category = $3,
+                 next_attempt_at = NULL
+             WHERE id = $1;",
+            &[
+                &row.get::<_, i32>("id"),
+                &self.max_attempts,
+                &ErrorCategory::Cancelled.as_str(),
+            ],
+        )?;
+        Ok(CancelResult::Cancelled)
+    }
+
+    /// Changes the priority of a queued crate. Returns whether the crate was queued.
+    pub fn set_priority(&self, name: &str, version: &str, priority: i32) -> Result<bool> {
+        let updated = self.db.get()?.execute(
+            "UPDATE queue SET priority = $3 WHERE name = $1 AND version = $2;",
+            &[&name, &version, &priority],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Starts listening for crates being added to the queue, to wake up the builders as soon as
+    /// there's something to build.
+    pub(crate) fn listen(&self) -> Result<QueueListener> {
+        let conn = self.db.get()?;
+        conn.execute(&format!("LISTEN {};", QUEUE_CHANNEL), &[])?;
+        Ok(QueueListener { conn })
+    }
+
+    /// Aborts the build of the crate claimed by this worker, if any, putting the crate back into
+    /// the queue without counting it as a failed attempt. Used when the builder is shutting down.
+    pub fn interrupt_builds(&self) {
+        if let Some(cancellation) = &*self.claimed.lock() {
+            cancellation.interrupt();
+        }
+    }
+
+    /// Claims the next crate to build for this worker, skipping the crates claimed by others.
+    ///
+    /// The claim is a lease which has to be renewed while the crate is being built: if the worker
+    /// dies, the crate is put back into the queue once the lease expires.
+    fn claim_next_crate(&self) -> Result<Option<QueuedCrate>> {
+        let conn = self.db.get()?;
+
+        // Crates whose build was being cancelled aren't retried
+        let expired = conn.execute(
+            "UPDATE queue
+             SET attempt = CASE
+                     WHEN cancel_reason IS NULL THEN attempt + 1
+                     ELSE GREATEST(attempt + 1, $3)
+                 END,
+                 last_error = COALESCE(cancel_reason, 'the builder stopped renewing its lease'),
+                 error_category = CASE WHEN cancel_reason IS NULL THEN $1 ELSE $4 END,
+                 next_attempt_at = NOW() + make_interval(secs => $2 * power(2, attempt)),
+                 locked_by = NULL,
+                 locked_until = NULL
+             WHERE locked_until < NOW();",
+            &[
+                &ErrorCategory::Other.as_str(),
+                &self.backoff.as_secs_f64(),
+                &self.max_attempts,
+                &ErrorCategory::Cancelled.as_str(),
+            ],
         )?;
+        if expired > 0 {
+            warn!(
+                "requeued {} crates whose builder stopped renewing its lease",
+                expired
+            );
+        }
+
+        let rows = conn.query(
+            &format!(
+                "UPDATE queue
+             SET locked_by = $2, locked_until = NOW() + make_interval(secs => $3)
+             WHERE id = (
+                 SELECT id
+                 FROM queue
+                 WHERE attempt < $1
+                     AND locked_by IS NULL
+                     AND cancel_reason IS NULL
+                     AND (next_attempt_at IS NULL OR next_attempt_at <= NOW())
+                 ORDER BY priority ASC, attempt ASC, id ASC
+                 LIMIT 1
+                 FOR UPDATE SKIP LOCKED
+             )
+             RETURNING {};",
+                QUEUED_CRATE_COLUMNS
+            ),
+            &[
+                &self.max_attempts,
+                &self.worker_id,
+                &self.lease.as_secs_f64(),
+            ],
+        )?;
+
+        Ok(rows.iter().next().map(QueuedCrate::from_row))
+    }
+
+    /// Renews the lease on a claimed crate in the background, until the returned guard is dropped.
+    /// The build is cancelled if requested while renewing the lease, and aborted again on every
+    /// renewal after that.
+    fn keep_lease(
+        &self,
+        krate: &QueuedCrate,
+        cancellation: Arc<Cancellation>,
+    ) -> Result<LeaseGuard> {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let db = self.db.clone();
+        let id = krate.id;
+        let worker_id = self.worker_id.clone();
+        let lease = self.lease;
+        let interval = (lease / 3).min(CANCELLATION_CHECK_INTERVAL);
 
-        Ok(query
-            .into_iter()
-            .map(|row| QueuedCrate {
-                id: row.get("id"),
-                name: row.get("name"),
-                version: row.get("version"),
-                priority: row.get("priority"),
-            })
-            .collect())
+        let thread = thread::Builder::new()
+            .name("build queue lease".to_string())
+            .spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                    let res = db.get().map_err(Into::into).and_then(|conn| {
+                        conn.query(
+                            "UPDATE queue
+                             SET locked_until = NOW() + make_interval(secs => $3)
+                             WHERE id = $1 AND locked_by = $2
+                             RETURNING cancel_reason;",
+                            &[&id, &worker_id, &lease.as_secs_f64()],
+                        )
+                        .map_err(failure::Error::from)
+                    });
+                    match res {
+                        Ok(rows) => {
+                            if let Some(reason) = rows.iter().next().and_then(|row| row.get(0)) {
+                                cancellation.cancel(reason);
+                            }
+                        }
+                        Err(e) => {
+                            error!("Failed to renew the lease on queued crate {}: {}", id, e)
+                        }
+                    }
+                    // Sandboxes started after the cancellation have to be killed too
+                    cancellation.abort_again();
+                }
+            })?;
+
+        Ok(LeaseGuard {
+            stop: Some(stop),
+            thread: Some(thread),
+        })
     }
 
+    #[cfg(test)]
     pub(crate) fn process_next_crate(
         &self,
         f: impl FnOnce(&QueuedCrate) -> Result<()>,
     ) -> Result<()> {
-        let conn = self.db.get()?;
+        self.process_next_crate_cancellable(|krate, _| f(krate))
+    }
 
-        let queued = self.queued_crates()?;
-        let to_process = match queued.get(0) {
+    /// Like `process_next_crate`, but `f` can be notified of the cancellation of the build with
+    /// `Cancellation::on_cancel`.
+    pub(crate) fn process_next_crate_cancellable(
+        &self,
+        f: impl FnOnce(&QueuedCrate, &Cancellation) -> Result<()>,
+    ) -> Result<()> {
+        let to_process = match self.claim_next_crate()? {
             Some(krate) => krate,
             None => return Ok(()),
         };
 
-        let res = f(&to_process);
-        crate::web::metrics::TOTAL_BUILDS.inc();
+        let cancellation = Arc::new(Cancellation::default());
+        *self.claimed.lock() = Some(cancellation.clone());
+        let res = {
+            let _lease = self.keep_lease(&to_process, cancellation.clone())?;
+            f(&to_process, &cancellation)
+        };
+        self.claimed.lock().take();
+
+        let conn = self.db.get()?;
         match res {
             Ok(()) => {
-                conn.execute("DELETE FROM queue WHERE id = $1;", &[&to_process.id])?;
+                crate::web::metrics::TOTAL_BUILDS.inc();
+                let deleted = conn.execute(
+                    "DELETE FROM queue WHERE id = $1 AND locked_by = $2;",
+                    &[&to_process.id, &self.worker_id],
+                )?;
+                if deleted == 0 {
+                    self.warn_lost_lease(&to_process);
+                }
+            }
+            Err(_) if cancellation.is_interrupted() => {
+                // The crate will be built again by the next builder, as if it was never claimed
+                let updated = conn.execute(
+                    "UPDATE queue SET locked_by = NULL, locked_until = NULL
+                     WHERE id = $1 AND locked_by = $2;",
+                    &[&to_process.id, &self.worker_id],
+                )?;
+                if updated == 0 {
+                    self.warn_lost_lease(&to_process);
+                    return Ok(());
+                }
+                warn!(
+                    "Interrupted the build of {}-{}, putting it back into the queue",
+                    to_process.name, to_process.version
+                );
             }
             Err(e) => {
-                // Increase attempt count
+                crate::web::metrics::TOTAL_BUILDS.inc();
+
+                let mut message = e.to_string();
+                for cause in e.iter_causes() {
+                    write!(message, "\n\nCaused by:\n    {}", cause)?;
+                }
+
+                // Increase attempt count, delay the next one exponentially, and give up the
+                // claim on the crate. Cancelled crates and rustdoc failures aren't retried.
+                let category = ErrorCategory::of(&e);
+                let cancelled = category == ErrorCategory::Cancelled;
                 let rows = conn.query(
-                    "UPDATE queue SET attempt = attempt + 1 WHERE id = $1 RETURNING attempt;",
-                    &[&to_process.id],
+                    "UPDATE queue
+                     SET attempt = CASE WHEN $5 THEN attempt + 1 ELSE GREATEST(attempt + 1, $6) END,
+                         last_error = $2,
+                         error_category = $3,
+                         next_attempt_at = NOW() + make_interval(secs => $4 * power(2, attempt)),
+                         locked_by = NULL,
+                         locked_until = NULL
+                     WHERE id = $1 AND locked_by = $7
+                     RETURNING attempt;",
+                    &[
+                        &to_process.id,
+                        &message,
+                        &category.as_str(),
+                        &self.backoff.as_secs_f64(),
+                        &category.is_retried(),
+                        &self.max_attempts,
+                        &self.worker_id,
+                    ],
                 )?;
-                let attempt: i32 = rows.get(0).get(0);
+                let attempt: i32 = match rows.iter().next() {
+                    Some(row) => row.get(0),
+                    None => {
+                        self.warn_lost_lease(&to_process);
+                        return Ok(());
+                    }
+                };
 
-                if attempt >= self.max_attempts {
+                if attempt >= self.max_attempts && !cancelled {
                     crate::web::metrics::FAILED_BUILDS.inc();
                 }
 
@@ -120,6 +611,162 @@ impl BuildQueue {
 
         Ok(())
     }
+
+    /// Logs that the crate was claimed by another builder after the lease of this one expired,
+    /// in which case the queue is left to the new builder.
+    fn warn_lost_lease(&self, krate: &QueuedCrate) {
+        warn!(
+            "The lease on {}-{} expired during its build and it was claimed by another builder, \
+             leaving it in the queue",
+            krate.name, krate.version
+        );
+    }
+}
+
+/// Outcome of `BuildQueue::cancel_crate`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CancelResult {
+    /// The crate isn't in the queue.
+    NotQueued,
+    /// The crate won't be built.
+    Cancelled,
+    /// The crate is being built, the build will be aborted by its builder.
+    Aborting { worker_id: String },
+}
+
+/// The cancellation of the build of a claimed crate, requested with `BuildQueue::cancel_crate`.
+#[derive(Default)]
+pub(crate) struct Cancellation {
+    state: Mutex<CancellationState>,
+}
+
+impl fmt::Debug for Cancellation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state.lock();
+        f.debug_struct("Cancellation")
+            .field("reason", &state.reason)
+            .field("interrupted", &state.interrupted)
+            .finish()
+    }
+}
+
+#[derive(Default)]
+struct CancellationState {
+    reason: Option<String>,
+    /// Whether the build was interrupted by `BuildQueue::interrupt_builds` rather than cancelled
+    interrupted: bool,
+    abort: Option<Box<dyn Fn() + Send>>,
+}
+
+impl Cancellation {
+    /// Sets the function aborting the build, called from another thread when the build is
+    /// cancelled. It's called immediately if the build was already cancelled.
+    ///
+    /// The function can be called multiple times, as the build might start new work between the
+    /// cancellation and its next call to `check`: see `abort_again`.
+    pub(crate) fn on_cancel(&self, abort: impl Fn() + Send + 'static) {
+        let mut state = self.state.lock();
+        if state.reason.is_some() {
+            abort();
+        }
+        state.abort = Some(Box::new(abort));
+    }
+
+    /// Calls the function aborting the build again if the build was cancelled.
+    fn abort_again(&self) {
+        let state = self.state.lock();
+        if state.reason.is_some() {
+            if let Some(abort) = &state.abort {
+                abort();
+            }
+        }
+    }
+
+    /// Returns an error categorized as `ErrorCategory::Cancelled` if the build was cancelled.
+    pub(crate) fn check(&self) -> Result<()> {
+        match &self.state.lock().reason {
+            Some(reason) => Err(failure::err_msg(reason.clone())
+                .context(ErrorCategory::Cancelled)
+                .into()),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn is_interrupted(&self) -> bool {
+        self.state.lock().interrupted
+    }
+
+    fn interrupt(&self) {
+        self.abort_build("the builder is shutting down".into(), true);
+    }
+
+    fn cancel(&self, reason: String) {
+        self.abort_build(reason, false);
+    }
+
+    fn abort_build(&self, reason: String, interrupted: bool) {
+        let mut state = self.state.lock();
+        if state.reason.is_none() {
+            warn!("the build was cancelled: {}", reason);
+            state.reason = Some(reason);
+            state.interrupted = interrupted;
+            if let Some(abort) = &state.abort {
+                abort();
+            }
+        }
+    }
+}
+
+fn notify_builders(conn: &Connection) -> Result<()> {
+    conn.execute(&format!("NOTIFY {};", QUEUE_CHANNEL), &[])?;
+    Ok(())
+}
+
+/// A database connection listening for crates being added to the queue, returned by
+/// `BuildQueue::listen`.
+pub(crate) struct QueueListener {
+    conn: PoolConnection,
+}
+
+impl QueueListener {
+    /// Waits until crates are added to the queue, or until the timeout expires. Returns whether
+    /// crates were added.
+    ///
+    /// Crates added since the previous call wake up the listener immediately, and all the
+    /// notifications received so far are consumed at once.
+    pub(crate) fn wait(&self, timeout: Duration) -> Result<bool> {
+        let notifications = self.conn.notifications();
+        if notifications.timeout_iter(timeout).next()?.is_none() {
+            return Ok(false);
+        }
+        while notifications.iter().next()?.is_some() {}
+        Ok(true)
+    }
+}
+
+impl Drop for QueueListener {
+    fn drop(&mut self) {
+        // The connection goes back to the pool, other users shouldn't receive the notifications
+        if let Err(e) = self.conn.execute("UNLISTEN *;", &[]) {
+            warn!("Failed to stop listening to the build queue: {}", e);
+        }
+    }
+}
+
+/// Stops renewing the lease on a queued crate when dropped.
+struct LeaseGuard {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for LeaseGuard {
+    fn drop(&mut self) {
+        // Dropping the sender wakes up the renewal thread
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
 }
 
 #[cfg(test)]
@@ -200,6 +847,656 @@ mod tests {
         })
     }
 
+    #[test]
+    fn test_add_duplicate_crate() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+
+            assert!(queue.add_crate("foo", "1.0.0", 0)?);
+            assert!(!queue.add_crate("foo", "1.0.0", 0)?);
+            assert!(!queue.add_crate("foo", "1.0.0", -10)?);
+            assert_eq!(queue.pending_count()?, 1);
+
+            // The priority of the first insertion is kept
+            assert_eq!(queue.queued_crates()?[0].priority, 0);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_concurrent_workers_claim_different_crates() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+            let other_worker = BuildQueue {
+                db: env.db().pool(),
+                max_attempts: queue.max_attempts,
+                worker_id: "other-worker".into(),
+                lease: queue.lease,
+                backoff: queue.backoff,
+                claimed: Mutex::new(None),
+            };
+
+            queue.add_crate("foo", "1.0.0", 0)?;
+            queue.add_crate("bar", "1.0.0", 0)?;
+
+            queue.process_next_crate(|krate| {
+                assert_eq!("foo", krate.name);
+
+                let locked_by: Option<String> = env
+                    .db()
+                    .conn()
+                    .query("SELECT locked_by FROM queue WHERE name = 'foo';", &[])?
+                    .get(0)
+                    .get(0);
+                assert_eq!(locked_by.as_deref(), Some(queue.worker_id.as_str()));
+
+                // foo is claimed by the first worker, so the other one has to build bar
+                let mut called = false;
+                other_worker.process_next_crate(|krate| {
+                    called = true;
+                    assert_eq!("bar", krate.name);
+                    Ok(())
+                })?;
+                assert!(called);
+
+                // and nothing is left for a third build
+                other_worker.process_next_crate(|krate| {
+                    panic!("{} was claimed twice", krate.name);
+                })?;
+
+                Ok(())
+            })?;
+            assert_eq!(queue.pending_count()?, 0);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_expired_lease_is_requeued() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+
+            queue.add_crate("foo", "1.0.0", 0)?;
+            // Simulate a builder dying while building the crate
+            env.db().conn().execute(
+                "UPDATE queue
+                 SET locked_by = 'dead-worker', locked_until = NOW() - INTERVAL '1 minute';",
+                &[],
+            )?;
+
+            let mut called = false;
+            queue.process_next_crate(|krate| {
+                called = true;
+                assert_eq!("foo", krate.name);
+                failure::bail!("simulate a failure");
+            })?;
+            assert!(called, "the expired crate was not requeued");
+
+            // The dead builder counts as a failed attempt
+            let attempt: i32 = env
+                .db()
+                .conn()
+                .query("SELECT attempt FROM queue WHERE name = 'foo';", &[])?
+                .get(0)
+                .get(0);
+            assert_eq!(attempt, 2);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_reclaimed_crate_is_left_to_the_new_builder() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+
+            let reclaim = || -> Result<()> {
+                // Simulate the lease expiring during the build, and another builder claiming it
+                env.db().conn().execute(
+                    "UPDATE queue
+                     SET locked_by = 'other-worker', locked_until = NOW() + INTERVAL '1 minute';",
+                    &[],
+                )?;
+                Ok(())
+            };
+            let locked_by = || -> Result<Option<String>> {
+                Ok(env
+                    .db()
+                    .conn()
+                    .query("SELECT locked_by FROM queue WHERE name = 'foo';", &[])?
+                    .get(0)
+                    .get(0))
+            };
+
+            queue.add_crate("foo", "1.0.0", 0)?;
+
+            // A successful build doesn't remove the crate from the other builder
+            queue.process_next_crate(|_| reclaim())?;
+            assert_eq!(queue.pending_count()?, 1);
+            assert_eq!(locked_by()?.as_deref(), Some("other-worker"));
+
+            // A failed build neither releases it nor counts as an attempt
+            env.db().conn().execute(
+                "UPDATE queue SET locked_by = NULL, locked_until = NULL;",
+                &[],
+            )?;
+            queue.process_next_crate(|_| {
+                reclaim()?;
+                failure::bail!("simulate a failure");
+            })?;
+            assert_eq!(locked_by()?.as_deref(), Some("other-worker"));
+            let rows = env
+                .db()
+                .conn()
+                .query("SELECT attempt, last_error FROM queue;", &[])?;
+            assert_eq!(rows.get(0).get::<_, i32>(0), 0);
+            assert_eq!(rows.get(0).get::<_, Option<String>>(1), None);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_lease_is_renewed_during_build() {
+        crate::test::wrapper(|env| {
+            let queue = BuildQueue {
+                lease: Duration::from_secs(1),
+                ..BuildQueue::new(env.db().pool(), &env.config())
+            };
+
+            queue.add_crate("foo", "1.0.0", 0)?;
+            queue.process_next_crate(|_| {
+                thread::sleep(Duration::from_millis(1500));
+
+                let rows = env.db().conn().query(
+                    "SELECT COUNT(*) FROM queue WHERE locked_until > NOW();",
+                    &[],
+                )?;
+                assert_eq!(rows.get(0).get::<_, i64>(0), 1, "the lease expired");
+                Ok(())
+            })?;
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_active_lease_is_not_requeued() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+
+            queue.add_crate("foo", "1.0.0", 0)?;
+            env.db().conn().execute(
+                "UPDATE queue
+                 SET locked_by = 'other-worker', locked_until = NOW() + INTERVAL '1 minute';",
+                &[],
+            )?;
+
+            queue.process_next_crate(|krate| {
+                panic!("{} is being built by another worker", krate.name);
+            })?;
+            assert_eq!(queue.pending_count()?, 1);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_failed_build_is_delayed() {
+        crate::test::wrapper(|env| {
+            env.override_config(|config| {
+                config.build_backoff_seconds = 60;
+            });
+            let queue = env.build_queue();
+
+            queue.add_crate("foo", "1.0.0", 0)?;
+            queue.add_crate("bar", "1.0.0", 0)?;
+
+            queue.process_next_crate(|krate| {
+                assert_eq!("foo", krate.name);
+                Err(failure::err_msg("cargo failed")
+                    .context("failed to build")
+                    .into())
+            })?;
+
+            let queued = queue.queued_crates()?;
+            let foo = queued.iter().find(|krate| krate.name == "foo").unwrap();
+            assert_eq!(foo.attempt, 1);
+            assert_eq!(
+                foo.last_error.as_deref(),
+                Some("failed to build\n\nCaused by:\n    cargo failed")
+            );
+            assert_eq!(foo.error_category, Some(ErrorCategory::Other));
+            let delay = foo.next_attempt_at.expect("missing next attempt") - Utc::now();
+            assert!(delay.num_seconds() > 50 && delay.num_seconds() <= 60);
+
+            // foo isn't retried until the backoff expires, even though it was queued first
+            queue.process_next_crate(|krate| {
+                assert_eq!("bar", krate.name);
+                Ok(())
+            })?;
+            queue.process_next_crate(|krate| {
+                panic!("{} was retried too early", krate.name);
+            })?;
+
+            // The delay doubles after each failure
+            env.db().conn().execute(
+                "UPDATE queue SET next_attempt_at = NOW() - INTERVAL '1 second';",
+                &[],
+            )?;
+            queue.process_next_crate(|_| Err(CommandError::SandboxOOM.into()))?;
+
+            let foo = &queue.queued_crates()?[0];
+            assert_eq!(foo.attempt, 2);
+            assert_eq!(foo.error_category, Some(ErrorCategory::SandboxOom));
+            let delay = foo.next_attempt_at.expect("missing next attempt") - Utc::now();
+            assert!(delay.num_seconds() > 110 && delay.num_seconds() <= 120);
+
+            // Rustdoc failures aren't retried
+            env.db().conn().execute(
+                "UPDATE queue SET next_attempt_at = NOW() - INTERVAL '1 second';",
+                &[],
+            )?;
+            queue.process_next_crate(|_| {
+                Err(failure::err_msg("rustdoc failed")
+                    .context(ErrorCategory::Rustdoc)
+                    .into())
+            })?;
+
+            assert!(queue.queued_crates()?.is_empty());
+            let failed = queue.failed_crates()?;
+            assert_eq!(failed.len(), 1);
+            assert_eq!(failed[0].error_category, Some(ErrorCategory::Rustdoc));
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_error_category() {
+        let categorize = |err: Error| ErrorCategory::of(&err);
+
+        assert_eq!(
+            categorize(failure::err_msg("something")),
+            ErrorCategory::Other
+        );
+        assert_eq!(
+            categorize(
+                failure::err_msg("network down")
+                    .context(ErrorCategory::Fetch)
+                    .into()
+            ),
+            ErrorCategory::Fetch
+        );
+        assert_eq!(
+            categorize(
+                Error::from(CommandError::SandboxOOM)
+                    .context(ErrorCategory::Rustdoc)
+                    .into()
+            ),
+            ErrorCategory::SandboxOom
+        );
+        assert_eq!(
+            categorize(
+                Error::from(CommandError::Timeout(60))
+                    .context("building the crate")
+                    .into()
+            ),
+            ErrorCategory::Timeout
+        );
+        assert_eq!(
+            categorize(PrepareError::MissingCargoToml.into()),
+            ErrorCategory::Fetch
+        );
+
+        assert_eq!(ErrorCategory::SandboxOom.as_str(), "sandbox_oom");
+        assert_eq!("sandbox_oom".parse(), Ok(ErrorCategory::SandboxOom));
+    }
+
+    #[test]
+    fn test_failed_crates() {
+        const MAX_ATTEMPTS: u16 = 2;
+        crate::test::wrapper(|env| {
+            env.override_config(|config| {
+                config.build_attempts = MAX_ATTEMPTS;
+            });
+            let queue = env.build_queue();
+
+            queue.add_crate("foo", "1.0.0", 0)?;
+            for _ in 0..MAX_ATTEMPTS {
+                queue.process_next_crate(|krate| {
+                    assert_eq!("foo", krate.name);
+                    failure::bail!("this failed");
+                })?;
+            }
+            queue.add_crate("bar", "1.0.0", 0)?;
+
+            let failed = queue.failed_crates()?;
+            assert_eq!(failed.len(), 1);
+            assert_eq!(failed[0].name, "foo");
+            assert_eq!(failed[0].attempt, MAX_ATTEMPTS as i32);
+            assert_eq!(failed[0].last_error.as_deref(), Some("this failed"));
+
+            let queued = queue.queued_crates()?;
+            assert_eq!(queued.len(), 1);
+            assert_eq!(queued[0].name, "bar");
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_list_crates() {
+        const MAX_ATTEMPTS: u16 = 1;
+        crate::test::wrapper(|env| {
+            env.override_config(|config| {
+                config.build_attempts = MAX_ATTEMPTS;
+            });
+            let queue = env.build_queue();
+
+            queue.add_crate("foo", "1.0.0", 0)?;
+            queue.add_crate("foo-bar", "1.0.0", 0)?;
+            queue.process_next_crate(|_| {
+                Err(failure::err_msg("oom")
+                    .context(ErrorCategory::SandboxOom)
+                    .into())
+            })?;
+            queue.process_next_crate(|_| {
+                Err(failure::err_msg("fetch")
+                    .context(ErrorCategory::Fetch)
+                    .into())
+            })?;
+            queue.add_crate("foo-baz", "1.0.0", 10)?;
+            queue.add_crate("bar", "1.0.0", 5)?;
+
+            let list = |filter: QueueFilter| -> Result<Vec<String>> {
+                Ok(queue
+                    .list_crates(&filter)?
+                    .into_iter()
+                    .map(|krate| krate.name)
+                    .collect())
+            };
+            assert_eq!(list(QueueFilter::default())?, vec!["bar", "foo-baz"]);
+            assert_eq!(
+                list(QueueFilter {
+                    name_pattern: Some("foo%".into()),
+                    ..QueueFilter::default()
+                })?,
+                vec!["foo-baz"]
+            );
+            assert_eq!(
+                list(QueueFilter {
+                    failed: true,
+                    ..QueueFilter::default()
+                })?,
+                vec!["foo", "foo-bar"]
+            );
+            assert_eq!(
+                list(QueueFilter {
+                    failed: true,
+                    error_category: Some(ErrorCategory::Fetch),
+                    ..QueueFilter::default()
+                })?,
+                vec!["foo-bar"]
+            );
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_listener_wakes_up_on_new_crates() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+            let listener = queue.listen()?;
+
+            // Crates added while the listener isn't waiting aren't missed
+            queue.add_crate("foo", "1.0.0", 0)?;
+            assert!(listener.wait(Duration::from_secs(10))?);
+
+            let adder = {
+                let queue = env.build_queue();
+                thread::spawn(move || {
+                    thread::sleep(Duration::from_millis(100));
+                    queue.add_crate("bar", "1.0.0", 0)
+                })
+            };
+            let start = std::time::Instant::now();
+            assert!(listener.wait(Duration::from_secs(10))?);
+            assert!(start.elapsed() < Duration::from_secs(5));
+            adder.join().unwrap()?;
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_remove_crate() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+
+            queue.add_crate("foo", "1.0.0", 0)?;
+            queue.add_crate("foo", "2.0.0", 0)?;
+            assert!(queue.remove_crate("foo", "1.0.0")?);
+            assert!(!queue.remove_crate("foo", "1.0.0")?);
+
+            let queued = queue.queued_crates()?;
+            assert_eq!(queued.len(), 1);
+            assert_eq!(queued[0].version, "2.0.0");
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_retry_failed_crates() {
+        const MAX_ATTEMPTS: u16 = 1;
+        crate::test::wrapper(|env| {
+            env.override_config(|config| {
+                config.build_attempts = MAX_ATTEMPTS;
+            });
+            let queue = env.build_queue();
+
+            queue.add_crate("foo", "1.0.0", 0)?;
+            queue.add_crate("bar", "1.0.0", 0)?;
+            for _ in 0..2 {
+                queue.process_next_crate(|_| failure::bail!("this failed"))?;
+            }
+            assert_eq!(queue.failed_count()?, 2);
+
+            assert_eq!(queue.retry_failed_crates(Some("fo%"))?, 1);
+            assert_eq!(queue.failed_count()?, 1);
+
+            let queued = queue.queued_crates()?;
+            assert_eq!(queued.len(), 1);
+            assert_eq!(queued[0].name, "foo");
+            assert_eq!(queued[0].attempt, 0);
+            assert_eq!(queued[0].next_attempt_at, None);
+            // The last error is kept until the next attempt
+            assert_eq!(queued[0].last_error.as_deref(), Some("this failed"));
+
+            // foo can be built again right away
+            let mut called = false;
+            queue.process_next_crate(|krate| {
+                called = true;
+                assert_eq!("foo", krate.name);
+                Ok(())
+            })?;
+            assert!(called);
+
+            assert_eq!(queue.retry_failed_crates(None)?, 1);
+            assert_eq!(queue.failed_count()?, 0);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_cancel_queued_crate() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+
+            assert_eq!(
+                queue.cancel_crate("foo", "1.0.0", "wedges the builder")?,
+                CancelResult::NotQueued
+            );
+
+            queue.add_crate("foo", "1.0.0", 0)?;
+            assert_eq!(
+                queue.cancel_crate("foo", "1.0.0", "wedges the builder")?,
+                CancelResult::Cancelled
+            );
+            assert_eq!(queue.pending_count()?, 0);
+
+            let failed = queue.failed_crates()?;
+            assert_eq!(failed.len(), 1);
+            assert_eq!(failed[0].error_category, Some(ErrorCategory::Cancelled));
+            assert_eq!(failed[0].last_error.as_deref(), Some("wedges the builder"));
+
+            // Retrying the crate cancels the cancellation
+            assert_eq!(queue.retry_failed_crates(None)?, 1);
+            let mut called = false;
+            queue.process_next_crate(|krate| {
+                called = true;
+                assert_eq!("foo", krate.name);
+                Ok(())
+            })?;
+            assert!(called);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_cancel_running_build() {
+        crate::test::wrapper(|env| {
+            let queue = BuildQueue {
+                lease: Duration::from_millis(300),
+                ..BuildQueue::new(env.db().pool(), &env.config())
+            };
+
+            queue.add_crate("foo", "1.0.0", 0)?;
+            queue.process_next_crate_cancellable(|krate, cancellation| {
+                let (abort, aborted) = mpsc::channel();
+                cancellation.on_cancel(move || {
+                    let _ = abort.send(());
+                });
+
+                assert_eq!(
+                    env.build_queue().cancel_crate(
+                        &krate.name,
+                        &krate.version,
+                        "wedges the builder"
+                    )?,
+                    CancelResult::Aborting {
+                        worker_id: queue.worker_id.clone()
+                    }
+                );
+                aborted
+                    .recv_timeout(Duration::from_secs(5))
+                    .expect("the build wasn't aborted");
+                cancellation.check()
+            })?;
+
+            // The cancelled crate isn't retried
+            assert_eq!(queue.pending_count()?, 0);
+            let failed = queue.failed_crates()?;
+            assert_eq!(failed.len(), 1);
+            assert_eq!(failed[0].error_category, Some(ErrorCategory::Cancelled));
+            assert_eq!(
+                failed[0].last_error.as_deref(),
+                Some("the build was cancelled\n\nCaused by:\n    wedges the builder")
+            );
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_cancellation_aborts_again() {
+        let cancellation = Cancellation::default();
+        let (abort, aborted) = mpsc::channel();
+        cancellation.on_cancel(move || abort.send(()).unwrap());
+
+        // Nothing to abort before the cancellation
+        cancellation.abort_again();
+        assert!(aborted.try_recv().is_err());
+        assert!(cancellation.check().is_ok());
+
+        cancellation.cancel("wedges the builder".into());
+        assert!(aborted.try_recv().is_ok());
+        assert!(cancellation.check().is_err());
+
+        // The build could have started a new sandbox since then
+        cancellation.abort_again();
+        assert!(aborted.try_recv().is_ok());
+
+        // Setting a new function after the cancellation calls it immediately
+        let (abort, aborted) = mpsc::channel();
+        cancellation.on_cancel(move || abort.send(()).unwrap());
+        assert!(aborted.try_recv().is_ok());
+    }
+
+    #[test]
+    fn test_interrupt_build() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+
+            queue.add_crate("foo", "1.0.0", 0)?;
+            queue.process_next_crate_cancellable(|_, cancellation| {
+                let (abort, aborted) = mpsc::channel();
+                cancellation.on_cancel(move || {
+                    let _ = abort.send(());
+                });
+
+                queue.interrupt_builds();
+                aborted
+                    .recv_timeout(Duration::from_secs(5))
+                    .expect("the build wasn't aborted");
+                cancellation.check()
+            })?;
+
+            // The crate is built again, without counting the interrupted build as an attempt
+            let queued = queue.queued_crates()?;
+            assert_eq!(queued.len(), 1);
+            assert_eq!(queued[0].attempt, 0);
+            assert_eq!(queued[0].last_error, None);
+
+            let mut called = false;
+            queue.process_next_crate(|krate| {
+                called = true;
+                assert_eq!("foo", krate.name);
+                Ok(())
+            })?;
+            assert!(called);
+
+            // Nothing happens if no crate is being built
+            queue.interrupt_builds();
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_set_priority() {
+        crate::test::wrapper(|env| {
+            let queue = env.build_queue();
+
+            queue.add_crate("foo", "1.0.0", 0)?;
+            queue.add_crate("bar", "1.0.0", 0)?;
+            assert!(queue.set_priority("bar", "1.0.0", -10)?);
+            assert!(!queue.set_priority("baz", "1.0.0", -10)?);
+
+            let queued = queue.queued_crates()?;
+            assert_eq!(queued[0].name, "bar");
+            assert_eq!(queued[0].priority, -10);
+
+            Ok(())
+        });
+    }
+
     #[test]
     fn test_pending_count() {
         crate::test::wrapper(|env| {
@@ -298,18 +1595,30 @@ mod tests {
                         name: "foo".into(),
                         version: "1.0.0".into(),
                         priority: -10,
+                        attempt: 0,
+                        last_error: None,
+                        error_category: None,
+                        next_attempt_at: None,
                     },
                     QueuedCrate {
                         id: 2,
                         name: "bar".into(),
                         version: "1.0.0".into(),
                         priority: 0,
+                        attempt: 0,
+                        last_error: None,
+                        error_category: None,
+                        next_attempt_at: None,
                     },
                     QueuedCrate {
                         id: 3,
                         name: "baz".into(),
                         version: "1.0.0".into(),
                         priority: 10,
+                        attempt: 0,
+                        last_error: None,
+                        error_category: None,
+                        next_attempt_at: None,
                     },
                 ],
                 queue.queued_crates()?
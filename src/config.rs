@@ -1,11 +1,18 @@
+use crate::storage::{CompressionAlgorithm, StorageKind};
 use failure::{bail, format_err, Error, Fail, ResultExt};
 use std::env::VarError;
+use std::path::PathBuf;
 use std::str::FromStr;
 
 #[derive(Debug)]
 pub struct Config {
     // Build params
     pub(crate) build_attempts: u16,
+    pub(crate) build_worker_id: String,
+    pub(crate) build_lease_seconds: u64,
+    pub(crate) build_backoff_seconds: u64,
+    // How long the daemon waits for the current build to finish when shutting down
+    pub(crate) shutdown_timeout_seconds: u64,
 
     // Database connection params
     pub(crate) database_url: String,
@@ -19,12 +26,26 @@ pub struct Config {
     // Max size of the files served by the docs.rs frontend
     pub(crate) max_file_size: usize,
     pub(crate) max_file_size_html: usize,
+
+    // Secret used to validate the signature of registry index webhooks
+    pub(crate) index_webhook_secret: Option<String>,
+
+    // Where to store the documentation and source files
+    pub(crate) storage_backend: Option<StorageKind>,
+    pub(crate) local_storage_path: Option<PathBuf>,
+    pub(crate) compression_algorithm: CompressionAlgorithm,
+    // Whether to store the documentation of new builds in one archive per release
+    pub(crate) archive_storage: bool,
 }
 
 impl Config {
     pub fn from_env() -> Result<Self, Error> {
         Ok(Self {
             build_attempts: env("DOCSRS_BUILD_ATTEMPTS", 5)?,
+            build_worker_id: env("DOCSRS_BUILD_WORKER_ID", default_worker_id())?,
+            build_lease_seconds: env("DOCSRS_BUILD_LEASE_SECONDS", 5 * 60)?,
+            build_backoff_seconds: env("DOCSRS_BUILD_BACKOFF_SECONDS", 60)?,
+            shutdown_timeout_seconds: env("DOCSRS_SHUTDOWN_TIMEOUT_SECONDS", 5 * 60)?,
 
             database_url: require_env("CRATESFYI_DATABASE_URL")?,
             max_pool_size: env("DOCSRS_MAX_POOL_SIZE", 90)?,
@@ -35,6 +56,16 @@ impl Config {
 
             max_file_size: env("DOCSRS_MAX_FILE_SIZE", 50 * 1024 * 1024)?,
             max_file_size_html: env("DOCSRS_MAX_FILE_SIZE_HTML", 5 * 1024 * 1024)?,
+
+            index_webhook_secret: maybe_env("DOCSRS_INDEX_WEBHOOK_SECRET")?,
+
+            storage_backend: maybe_env("DOCSRS_STORAGE_BACKEND")?,
+            local_storage_path: maybe_env("DOCSRS_LOCAL_STORAGE_PATH")?,
+            compression_algorithm: env(
+                "DOCSRS_COMPRESSION_ALGORITHM",
+                CompressionAlgorithm::default(),
+            )?,
+            archive_storage: env("DOCSRS_ARCHIVE_STORAGE", false)?,
         })
     }
 
@@ -46,6 +77,15 @@ impl Config {
     }
 }
 
+/// Identifies the builders of this process in the build queue, unique across hosts and restarts.
+fn default_worker_id() -> String {
+    let hostname = std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|hostname| hostname.trim().to_string())
+        .unwrap_or_else(|_| "localhost".into());
+
+    format!("{}-{}", hostname, std::process::id())
+}
+
 fn env<T>(var: &str, default: T) -> Result<T, Error>
 where
     T: FromStr,
@@ -5,7 +5,7 @@ use std::{
 };
 
 use crate::{
-    docbuilder::BuildResult,
+    docbuilder::{BuildResult, FileCoverage},
     error::Result,
     index::api::{CrateOwner, RegistryCrateData},
     storage::CompressionAlgorithm,
@@ -36,9 +36,10 @@ pub(crate) fn add_package_into_database(
     has_docs: bool,
     has_examples: bool,
     compression_algorithms: std::collections::HashSet<CompressionAlgorithm>,
+    archive_storage: bool,
 ) -> Result<i32> {
     debug!("Adding package into database");
-    let crate_id = initialize_package_in_database(&conn, metadata_pkg)?;
+    let crate_id = initialize_package_in_database(conn, metadata_pkg)?;
     let dependencies = convert_dependencies(metadata_pkg);
     let rustdoc = get_rustdoc(metadata_pkg, source_dir).unwrap_or(None);
     let readme = get_readme(metadata_pkg, source_dir).unwrap_or(None);
@@ -52,12 +53,12 @@ pub(crate) fn add_package_into_database(
             homepage_url, description, description_long, readme,
             authors, keywords, have_examples, downloads, files,
             doc_targets, is_library, doc_rustc_version,
-            documentation_url, default_target
+            documentation_url, default_target, archive_storage
          )
          VALUES (
             $1,  $2,  $3,  $4,  $5,  $6,  $7,  $8,  $9,
             $10, $11, $12, $13, $14, $15, $16, $17, $18,
-            $19, $20, $21, $22, $23, $24, $25
+            $19, $20, $21, $22, $23, $24, $25, $26
          )
          ON CONFLICT (crate_id, version) DO UPDATE
             SET release_time = $3,
@@ -82,7 +83,8 @@ pub(crate) fn add_package_into_database(
                 is_library = $22,
                 doc_rustc_version = $23,
                 documentation_url = $24,
-                default_target = $25
+                default_target = $25,
+                archive_storage = $26
          RETURNING id",
         &[
             &crate_id,
@@ -110,15 +112,16 @@ pub(crate) fn add_package_into_database(
             &res.rustc_version,
             &metadata_pkg.documentation,
             &default_target,
+            &archive_storage,
         ],
     )?;
 
     let release_id: i32 = rows.get(0).get(0);
 
-    add_keywords_into_database(&conn, &metadata_pkg, release_id)?;
-    add_authors_into_database(&conn, &metadata_pkg, release_id)?;
-    add_owners_into_database(&conn, &registry_data.owners, crate_id)?;
-    add_compression_into_database(&conn, compression_algorithms.into_iter(), release_id)?;
+    add_keywords_into_database(conn, metadata_pkg, release_id)?;
+    add_authors_into_database(conn, metadata_pkg, release_id)?;
+    add_owners_into_database(conn, &registry_data.owners, crate_id)?;
+    add_compression_into_database(conn, compression_algorithms.into_iter(), release_id)?;
 
     // Update the crates table with the new release
     conn.execute(
@@ -138,11 +141,20 @@ pub(crate) fn add_build_into_database(
     res: &BuildResult,
 ) -> Result<i32> {
     debug!("Adding build into database");
+    let resources = &res.resources;
+    let limits = match &resources.limits {
+        Some(limits) => Some(serde_json::to_value(limits)?),
+        None => None,
+    };
     let rows = conn.query(
         "INSERT INTO builds (rid, rustc_version,
                                                     cratesfyi_version,
-                                                    build_status, output)
-                                VALUES ($1, $2, $3, $4, $5)
+                                                    build_status, output,
+                                                    duration_seconds, limits,
+                                                    peak_memory_bytes, targets_attempted,
+                                                    targets_succeeded, docs_size_bytes,
+                                                    omitted_doc_warnings)
+                                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
                                 RETURNING id",
         &[
             &release_id,
@@ -150,9 +162,58 @@ pub(crate) fn add_build_into_database(
             &res.docsrs_version,
             &res.successful,
             &res.build_log,
+            &resources.duration.map(|duration| duration.as_secs_f64()),
+            &limits,
+            &resources.peak_memory.map(|bytes| bytes as i64),
+            &resources.targets_attempted,
+            &resources.targets_succeeded,
+            &resources.docs_size.map(|bytes| bytes as i64),
+            &res.omitted_doc_warnings,
         ],
     )?;
-    Ok(rows.get(0).get(0))
+    let build_id: i32 = rows.get(0).get(0);
+
+    // The warnings are inserted all at once, as there can be thousands of them.
+    let warnings = &res.doc_warnings;
+    conn.execute(
+        "INSERT INTO doc_warnings (build_id, target, level, lint, message, file, line)
+         SELECT $1, * FROM UNNEST($2::TEXT[], $3::TEXT[], $4::TEXT[], $5::TEXT[], $6::TEXT[], $7::INT[])",
+        &[
+            &build_id,
+            &warnings.iter().map(|w| w.target.as_str()).collect::<Vec<_>>(),
+            &warnings.iter().map(|w| w.level.as_str()).collect::<Vec<_>>(),
+            &warnings.iter().map(|w| w.lint.as_deref()).collect::<Vec<_>>(),
+            &warnings.iter().map(|w| w.message.as_str()).collect::<Vec<_>>(),
+            &warnings.iter().map(|w| w.file.as_deref()).collect::<Vec<_>>(),
+            &warnings.iter().map(|w| w.line).collect::<Vec<_>>(),
+        ],
+    )?;
+
+    Ok(build_id)
+}
+
+/// Adds the documentation coverage of a release into database, replacing the coverage of its
+/// previous builds.
+pub(crate) fn add_doc_coverage(
+    conn: &Connection,
+    release_id: i32,
+    coverage: &[FileCoverage],
+) -> Result<()> {
+    debug!("Adding documentation coverage into database");
+    let transaction = conn.transaction()?;
+    transaction.execute(
+        "DELETE FROM doc_coverage WHERE release_id = $1",
+        &[&release_id],
+    )?;
+    for file in coverage {
+        transaction.execute(
+            "INSERT INTO doc_coverage (release_id, file, documented, total)
+             VALUES ($1, $2, $3, $4)",
+            &[&release_id, &file.file, &file.documented, &file.total],
+        )?;
+    }
+    transaction.commit()?;
+    Ok(())
 }
 
 fn initialize_package_in_database(conn: &Connection, pkg: &MetadataPackage) -> Result<i32> {
@@ -256,7 +317,7 @@ fn add_keywords_into_database(
     release_id: i32,
 ) -> Result<()> {
     for keyword in &pkg.keywords {
-        let slug = slugify(&keyword);
+        let slug = slugify(keyword);
         let keyword_id: i32 = {
             let rows = conn.query("SELECT id FROM keywords WHERE slug = $1", &[&slug])?;
             if !rows.is_empty() {
@@ -300,7 +361,7 @@ fn add_authors_into_database(
                 .map(|m| m.as_str())
                 .unwrap_or("")
                 .trim();
-            let slug = slugify(&author);
+            let slug = slugify(author);
 
             let author_id: i32 = {
                 let rows = conn.query("SELECT id FROM authors WHERE slug = $1", &[&slug])?;
@@ -0,0 +1,88 @@
+//! Pausing the builds of all the builders
+//!
+//! The lock is stored in the database, so that the builders and the registry watchers running on
+//! every host honour it, and the web server can show that the builds are paused.
+
+use chrono::{DateTime, Utc};
+use failure::Error;
+use postgres::Connection;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Name of the row of the `config` table storing the lock.
+const CONFIG_NAME: &str = "builder_lock";
+
+/// Who paused the builds, when and why.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BuilderLock {
+    pub locked_by: String,
+    pub locked_at: DateTime<Utc>,
+    pub reason: Option<String>,
+}
+
+/// Returns the lock pausing the builds, if any.
+pub fn get_builder_lock(conn: &Connection) -> Result<Option<BuilderLock>, Error> {
+    let rows = conn.query("SELECT value FROM config WHERE name = $1;", &[&CONFIG_NAME])?;
+
+    match rows.iter().next() {
+        Some(row) => Ok(Some(serde_json::from_value(row.get::<_, Value>(0))?)),
+        None => Ok(None),
+    }
+}
+
+/// Pauses the builds, replacing the current lock if there is one.
+pub fn set_builder_lock(
+    conn: &Connection,
+    locked_by: &str,
+    reason: Option<&str>,
+) -> Result<(), Error> {
+    let lock = BuilderLock {
+        locked_by: locked_by.into(),
+        locked_at: Utc::now(),
+        reason: reason.map(String::from),
+    };
+
+    conn.execute(
+        "INSERT INTO config (name, value) VALUES ($1, $2)
+         ON CONFLICT (name) DO UPDATE SET value = $2;",
+        &[&CONFIG_NAME, &serde_json::to_value(&lock)?],
+    )?;
+
+    Ok(())
+}
+
+/// Resumes the builds, returning whether they were paused.
+pub fn remove_builder_lock(conn: &Connection) -> Result<bool, Error> {
+    let deleted = conn.execute("DELETE FROM config WHERE name = $1;", &[&CONFIG_NAME])?;
+
+    Ok(deleted > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_and_remove_builder_lock() {
+        crate::test::wrapper(|env| {
+            let db = env.db();
+
+            assert_eq!(get_builder_lock(&db.conn())?, None);
+            set_builder_lock(&db.conn(), "alice", Some("upgrading the toolchain"))?;
+            let lock = get_builder_lock(&db.conn())?.expect("missing lock");
+            assert_eq!(lock.locked_by, "alice");
+            assert_eq!(lock.reason.as_deref(), Some("upgrading the toolchain"));
+
+            set_builder_lock(&db.conn(), "bob", None)?;
+            let lock = get_builder_lock(&db.conn())?.expect("missing lock");
+            assert_eq!(lock.locked_by, "bob");
+            assert_eq!(lock.reason, None);
+
+            assert!(remove_builder_lock(&db.conn())?);
+            assert_eq!(get_builder_lock(&db.conn())?, None);
+            assert!(!remove_builder_lock(&db.conn())?);
+
+            Ok(())
+        });
+    }
+}
@@ -1,3 +1,4 @@
+use crate::storage::rustdoc_archive_path;
 use crate::storage::s3::{s3_client, S3_BUCKET_NAME};
 use failure::{Error, Fail};
 use postgres::Connection;
@@ -5,7 +6,7 @@ use rusoto_s3::{DeleteObjectsRequest, ListObjectsV2Request, ObjectIdentifier, S3
 
 /// List of directories in docs.rs's underlying storage (either the database or S3) containing a
 /// subdirectory named after the crate. Those subdirectories will be deleted.
-static STORAGE_PATHS_TO_DELETE: &[&str] = &["rustdoc", "sources"];
+static STORAGE_PATHS_TO_DELETE: &[&str] = &["rustdoc", "sources", "json"];
 
 #[derive(Debug, Fail)]
 enum CrateDeletionError {
@@ -31,6 +32,8 @@ pub fn delete_version(conn: &Connection, name: &str, version: &str) -> Result<()
         for prefix in STORAGE_PATHS_TO_DELETE {
             delete_prefix_from_s3(&s3, &format!("{}/{}/{}/", prefix, name, version))?;
         }
+        // The archive and its index
+        delete_prefix_from_s3(&s3, &rustdoc_archive_path(name, version))?;
     }
 
     Ok(())
@@ -47,12 +50,13 @@ fn get_id(conn: &Connection, name: &str) -> Result<i32, Error> {
 
 // metaprogramming!
 // WARNING: these must be hard-coded and NEVER user input.
-const METADATA: [(&str, &str); 5] = [
+const METADATA: [(&str, &str); 6] = [
     ("author_rels", "rid"),
     ("owner_rels", "cid"),
     ("keyword_rels", "rid"),
     ("builds", "rid"),
     ("compression_rels", "release"),
+    ("doc_coverage", "release_id"),
 ];
 
 fn delete_version_from_database(conn: &Connection, name: &str, version: &str) -> Result<(), Error> {
@@ -83,6 +87,10 @@ fn delete_version_from_database(conn: &Connection, name: &str, version: &str) ->
             &[&format!("{}/{}/{}/%", prefix, name, version)],
         )?;
     }
+    transaction.execute(
+        "DELETE FROM files WHERE path LIKE $1;",
+        &[&format!("{}%", rustdoc_archive_path(name, version))],
+    )?;
 
     transaction.commit().map_err(Into::into)
 }
@@ -285,4 +293,36 @@ mod tests {
             Ok(())
         })
     }
+
+    #[test]
+    fn test_delete_version_with_archive() {
+        wrapper(|env| {
+            let db = env.db();
+            let files = |version: &str| -> Result<i64, Error> {
+                Ok(db
+                    .conn()
+                    .query(
+                        "SELECT COUNT(*) FROM files WHERE path LIKE $1",
+                        &[&format!("rustdoc/a/{}.zip%", version)],
+                    )?
+                    .get(0)
+                    .get(0))
+            };
+
+            for version in &["1.0.0", "2.0.0"] {
+                env.fake_release()
+                    .name("a")
+                    .version(version)
+                    .archive_storage(true)
+                    .create()?;
+            }
+            assert_eq!(files("1.0.0")?, 2);
+
+            delete_version(&db.conn(), "a", "1.0.0")?;
+            assert_eq!(files("1.0.0")?, 0);
+            assert_eq!(files("2.0.0")?, 2);
+
+            Ok(())
+        })
+    }
 }
@@ -24,7 +24,7 @@ pub fn add_path_into_database<P: AsRef<Path>>(
     prefix: &str,
     path: P,
 ) -> Result<(Value, CompressionAlgorithms)> {
-    let (file_list, algorithms) = storage.store_all(prefix, path.as_ref())?;
+    let (file_list, algorithms, _) = storage.store_all(prefix, path.as_ref())?;
     Ok((
         file_list_to_json(file_list.into_iter().collect())?,
         algorithms,
@@ -0,0 +1,81 @@
+//! Tracking of how far the registry index has been synced
+
+use failure::{Error, ResultExt};
+use postgres::Connection;
+use serde_json::Value;
+
+/// Name of the row of the `config` table storing the last processed index commit.
+const CONFIG_NAME: &str = "last_seen_index_reference";
+
+/// Returns the hash of the last registry index commit whose changes were added to the queue.
+pub fn get_last_seen_commit(conn: &Connection) -> Result<Option<String>, Error> {
+    let rows = conn.query("SELECT value FROM config WHERE name = $1;", &[&CONFIG_NAME])?;
+
+    Ok(rows
+        .iter()
+        .next()
+        .and_then(|row| row.get::<_, Value>(0).as_str().map(String::from)))
+}
+
+/// Stores the hash of the last registry index commit whose changes were added to the queue.
+pub fn set_last_seen_commit(conn: &Connection, commit: &str) -> Result<(), Error> {
+    git2::Oid::from_str(commit)
+        .with_context(|_| format!("'{}' is not a valid commit hash", commit))?;
+
+    conn.execute(
+        "INSERT INTO config (name, value) VALUES ($1, $2)
+         ON CONFLICT (name) DO UPDATE SET value = $2;",
+        &[&CONFIG_NAME, &Value::String(commit.into())],
+    )?;
+
+    Ok(())
+}
+
+/// Forgets the last processed registry index commit, returning whether one was stored.
+///
+/// The next index sync will fall back to the tracking branch of the local index repository.
+pub fn reset_last_seen_commit(conn: &Connection) -> Result<bool, Error> {
+    let deleted = conn.execute("DELETE FROM config WHERE name = $1;", &[&CONFIG_NAME])?;
+
+    Ok(deleted > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMMIT: &str = "8b5c9d7b3c9a6c8e3e2b1f0a4d5c6b7a8f9e0d1c";
+
+    #[test]
+    fn test_set_and_reset_last_seen_commit() {
+        crate::test::wrapper(|env| {
+            let db = env.db();
+
+            assert_eq!(get_last_seen_commit(&db.conn())?, None);
+            set_last_seen_commit(&db.conn(), COMMIT)?;
+            assert_eq!(get_last_seen_commit(&db.conn())?, Some(COMMIT.into()));
+
+            let other = "0000000000000000000000000000000000000001";
+            set_last_seen_commit(&db.conn(), other)?;
+            assert_eq!(get_last_seen_commit(&db.conn())?, Some(other.into()));
+
+            assert!(reset_last_seen_commit(&db.conn())?);
+            assert_eq!(get_last_seen_commit(&db.conn())?, None);
+            assert!(!reset_last_seen_commit(&db.conn())?);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_set_invalid_commit() {
+        crate::test::wrapper(|env| {
+            let db = env.db();
+
+            assert!(set_last_seen_commit(&db.conn(), "not a commit").is_err());
+            assert_eq!(get_last_seen_commit(&db.conn())?, None);
+
+            Ok(())
+        });
+    }
+}
@@ -364,6 +364,185 @@ pub fn migrate(version: Option<Version>, conn: &Connection) -> CratesfyiResult<(
             "DROP TABLE compression_rels;
              ALTER TABLE files DROP COLUMN compression;"
         ),
+        migration!(
+            context,
+            // version
+            15,
+            // description
+            "Allow multiple builders to claim crates from the queue",
+            // upgrade query
+            "
+            -- the builder currently building the crate, and until when its claim is valid
+            ALTER TABLE queue
+                ADD COLUMN locked_by VARCHAR(255),
+                ADD COLUMN locked_until TIMESTAMPTZ;
+            ",
+            // downgrade query
+            "
+            ALTER TABLE queue
+                DROP COLUMN locked_by,
+                DROP COLUMN locked_until;
+            "
+        ),
+        migration!(
+            context,
+            // version
+            16,
+            // description
+            "Record why queued builds failed, and when to try them again",
+            // upgrade query
+            "
+            ALTER TABLE queue
+                ADD COLUMN last_error TEXT,
+                ADD COLUMN error_category VARCHAR(32),
+                ADD COLUMN next_attempt_at TIMESTAMPTZ;
+            ",
+            // downgrade query
+            "
+            ALTER TABLE queue
+                DROP COLUMN last_error,
+                DROP COLUMN error_category,
+                DROP COLUMN next_attempt_at;
+            "
+        ),
+        migration!(
+            context,
+            // version
+            17,
+            // description
+            "Record which releases store their documentation in an archive",
+            // upgrade query
+            "ALTER TABLE releases ADD COLUMN archive_storage BOOL NOT NULL DEFAULT FALSE;",
+            // downgrade query
+            "ALTER TABLE releases DROP COLUMN archive_storage;"
+        ),
+        migration!(
+            context,
+            // version
+            18,
+            // description
+            "Store the warnings emitted while building the documentation",
+            // upgrade query
+            "
+            -- builds.id was never declared as a key, but is needed to reference builds
+            ALTER TABLE builds ADD PRIMARY KEY (id);
+            CREATE TABLE doc_warnings (
+                id SERIAL PRIMARY KEY,
+                build_id INT NOT NULL REFERENCES builds(id) ON DELETE CASCADE,
+                target VARCHAR(100) NOT NULL,
+                level VARCHAR(20) NOT NULL,
+                lint VARCHAR(255),
+                message TEXT NOT NULL,
+                file TEXT,
+                line INT
+            );
+            CREATE INDEX doc_warnings_build_id_idx ON doc_warnings (build_id);
+            ",
+            // downgrade query
+            "
+            DROP TABLE doc_warnings;
+            ALTER TABLE builds DROP CONSTRAINT builds_pkey;
+            "
+        ),
+        migration!(
+            context,
+            // version
+            19,
+            // description
+            "Store the documentation coverage of each file of a release",
+            // upgrade query
+            "
+            CREATE TABLE doc_coverage (
+                release_id INT NOT NULL REFERENCES releases(id),
+                file TEXT NOT NULL,
+                documented INT NOT NULL,
+                total INT NOT NULL,
+                PRIMARY KEY (release_id, file)
+            );
+            ",
+            // downgrade query
+            "DROP TABLE doc_coverage;"
+        ),
+        migration!(
+            context,
+            // version
+            20,
+            // description
+            "Allow building the rustdoc JSON output of crates from the sandbox overrides",
+            // upgrade query
+            "ALTER TABLE sandbox_overrides ADD COLUMN rustdoc_json BOOL NOT NULL DEFAULT FALSE;",
+            // downgrade query
+            "ALTER TABLE sandbox_overrides DROP COLUMN rustdoc_json;"
+        ),
+        migration!(
+            context,
+            // version
+            21,
+            // description
+            "Record the resources used by each build",
+            // upgrade query
+            "
+            ALTER TABLE builds
+                ADD COLUMN duration_seconds FLOAT8,
+                ADD COLUMN limits JSON,
+                ADD COLUMN peak_memory_bytes BIGINT,
+                ADD COLUMN targets_attempted INT,
+                ADD COLUMN targets_succeeded INT,
+                ADD COLUMN docs_size_bytes BIGINT;
+            ",
+            // downgrade query
+            "
+            ALTER TABLE builds
+                DROP COLUMN duration_seconds,
+                DROP COLUMN limits,
+                DROP COLUMN peak_memory_bytes,
+                DROP COLUMN targets_attempted,
+                DROP COLUMN targets_succeeded,
+                DROP COLUMN docs_size_bytes;
+            "
+        ),
+        migration!(
+            context,
+            // version
+            22,
+            // description
+            "Allow cancelling the builds of queued crates",
+            // upgrade query
+            "ALTER TABLE queue ADD COLUMN cancel_reason TEXT;",
+            // downgrade query
+            "ALTER TABLE queue DROP COLUMN cancel_reason;"
+        ),
+        migration!(
+            context,
+            // version
+            23,
+            // description
+            "Record the health of the roles run by the daemons",
+            // upgrade query
+            "
+            CREATE TABLE daemon_roles (
+                worker_id TEXT NOT NULL,
+                role TEXT NOT NULL,
+                status TEXT NOT NULL,
+                status_since TIMESTAMPTZ NOT NULL,
+                last_heartbeat TIMESTAMPTZ NOT NULL,
+                PRIMARY KEY (worker_id, role)
+            );
+            ",
+            // downgrade query
+            "DROP TABLE daemon_roles;"
+        ),
+        migration!(
+            context,
+            // version
+            24,
+            // description
+            "Count the documentation warnings which weren't stored",
+            // upgrade query
+            "ALTER TABLE builds ADD COLUMN omitted_doc_warnings INTEGER NOT NULL DEFAULT 0;",
+            // downgrade query
+            "ALTER TABLE builds DROP COLUMN omitted_doc_warnings;"
+        ),
     ];
 
     for migration in migrations {
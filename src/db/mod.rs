@@ -1,6 +1,7 @@
 //! Database operations
 
 pub(crate) use self::add_package::add_build_into_database;
+pub(crate) use self::add_package::add_doc_coverage;
 pub(crate) use self::add_package::add_package_into_database;
 pub use self::delete::{delete_crate, delete_version};
 pub use self::file::add_path_into_database;
@@ -10,7 +11,9 @@ pub use self::pool::{Pool, PoolError};
 
 mod add_package;
 pub mod blacklist;
+pub mod builder_lock;
 mod delete;
 pub(crate) mod file;
+pub mod index_state;
 mod migrate;
 mod pool;
@@ -75,10 +75,11 @@ impl SetSchema {
 impl r2d2::CustomizeConnection<Connection, postgres::Error> for SetSchema {
     fn on_acquire(&self, conn: &mut Connection) -> Result<(), postgres::Error> {
         if self.schema != DEFAULT_SCHEMA {
-            conn.execute(
-                &format!("SET search_path TO {}, {};", self.schema, DEFAULT_SCHEMA),
-                &[],
-            )?;
+            // The application name allows the tests to find the connections using their schema
+            conn.batch_execute(&format!(
+                "SET search_path TO {0}, {1}; SET application_name TO '{0}';",
+                self.schema, DEFAULT_SCHEMA
+            ))?;
         }
         Ok(())
     }
@@ -0,0 +1,60 @@
+//! Documentation coverage, as reported by rustdoc's `--show-coverage`
+
+use crate::error::Result;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Number of documented items in a file of a crate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FileCoverage {
+    pub(crate) file: String,
+    pub(crate) documented: i32,
+    pub(crate) total: i32,
+}
+
+#[derive(Deserialize)]
+struct RustdocCoverage {
+    total: i32,
+    with_docs: i32,
+}
+
+/// Parses the output of `rustdoc --show-coverage --output-format json`, which is a JSON object
+/// mapping each file to the number of its items.
+pub(super) fn parse_coverage(output: &str) -> Result<Vec<FileCoverage>> {
+    let files: BTreeMap<String, RustdocCoverage> = serde_json::from_str(output)?;
+    Ok(files
+        .into_iter()
+        .map(|(file, coverage)| FileCoverage {
+            file,
+            documented: coverage.with_docs,
+            total: coverage.total,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_coverage() {
+        let output = r#"{"src/lib.rs":{"total":10,"with_docs":7,"total_examples":8,"with_examples":1},"src/foo.rs":{"total":3,"with_docs":3,"total_examples":3,"with_examples":0}}"#;
+        assert_eq!(
+            parse_coverage(output).unwrap(),
+            vec![
+                FileCoverage {
+                    file: "src/foo.rs".into(),
+                    documented: 3,
+                    total: 3,
+                },
+                FileCoverage {
+                    file: "src/lib.rs".into(),
+                    documented: 7,
+                    total: 10,
+                },
+            ]
+        );
+
+        assert!(parse_coverage("Documenting foo v0.1.0").is_err());
+    }
+}
@@ -0,0 +1,206 @@
+//! Warnings emitted while building the documentation of a crate
+//!
+//! Cargo is run with `--message-format=json`, and the diagnostics it reports are extracted from
+//! its output, so that they can be stored and shown on the builds page. The human readable
+//! rendering of the diagnostics is kept in the build log.
+
+use serde::{Deserialize, Serialize};
+
+/// Number of diagnostics stored for each target of a build, the others are only counted. Crates
+/// without any documentation can emit a `missing_docs` warning for each of their items.
+pub(crate) const MAX_DOC_WARNINGS_PER_TARGET: usize = 1000;
+
+/// A diagnostic reported by rustdoc or the compiler while building the documentation of a target.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct DocWarning {
+    pub(crate) target: String,
+    /// Either `warning` or `error`
+    pub(crate) level: String,
+    /// Name of the lint which emitted the diagnostic, like `broken_intra_doc_links`
+    pub(crate) lint: Option<String>,
+    pub(crate) message: String,
+    pub(crate) file: Option<String>,
+    pub(crate) line: Option<i32>,
+}
+
+/// A line of the output of cargo.
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum CargoOutput {
+    /// A diagnostic, along with its human readable rendering. Diagnostics which aren't about a
+    /// specific piece of code, like the number of warnings emitted, are only rendered.
+    Diagnostic {
+        warning: Option<DocWarning>,
+        rendered: String,
+    },
+    /// Any other JSON message, which is not useful in the build log
+    Message,
+    /// A line which is not a JSON message, like the progress reported by cargo
+    Text,
+}
+
+#[derive(Deserialize)]
+struct CargoMessage {
+    reason: String,
+    message: Option<Diagnostic>,
+}
+
+#[derive(Deserialize)]
+struct Diagnostic {
+    message: String,
+    code: Option<DiagnosticCode>,
+    level: String,
+    #[serde(default)]
+    spans: Vec<DiagnosticSpan>,
+    #[serde(default)]
+    children: Vec<Diagnostic>,
+    rendered: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DiagnosticCode {
+    code: String,
+}
+
+#[derive(Deserialize)]
+struct DiagnosticSpan {
+    file_name: String,
+    line_start: i32,
+    is_primary: bool,
+}
+
+impl Diagnostic {
+    fn lint(&self) -> Option<String> {
+        if let Some(code) = &self.code {
+            return Some(code.code.clone());
+        }
+
+        // Rustdoc lints don't always have a code, but mention their name in a note like
+        // "`#[warn(missing_docs)]` on by default".
+        self.children.iter().find_map(|child| {
+            let start = child.message.find("#[")?;
+            let attr = &child.message[start..];
+            let open = attr.find('(')?;
+            let close = attr.find(")]")?;
+            if open < close {
+                Some(attr[open + 1..close].to_string())
+            } else {
+                None
+            }
+        })
+    }
+
+    fn into_warning(self, target: &str) -> Option<DocWarning> {
+        if self.level != "warning" && self.level != "error" {
+            return None;
+        }
+        let span = self
+            .spans
+            .iter()
+            .find(|span| span.is_primary)
+            .or_else(|| self.spans.first())?;
+
+        Some(DocWarning {
+            target: target.to_string(),
+            lint: self.lint(),
+            file: Some(span.file_name.clone()),
+            line: Some(span.line_start),
+            level: self.level,
+            message: self.message,
+        })
+    }
+}
+
+/// Parses a line printed by cargo while building the documentation of `target`.
+pub(super) fn parse_cargo_output(line: &str, target: &str) -> CargoOutput {
+    if !line.starts_with('{') {
+        return CargoOutput::Text;
+    }
+    let message: CargoMessage = match serde_json::from_str(line) {
+        Ok(message) => message,
+        Err(_) => return CargoOutput::Text,
+    };
+
+    match message.message {
+        Some(diagnostic) if message.reason == "compiler-message" => {
+            let rendered = diagnostic
+                .rendered
+                .clone()
+                .unwrap_or_else(|| format!("{}: {}", diagnostic.level, diagnostic.message));
+            CargoOutput::Diagnostic {
+                warning: diagnostic.into_warning(target),
+                rendered,
+            }
+        }
+        _ => CargoOutput::Message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TARGET: &str = "x86_64-unknown-linux-gnu";
+
+    #[test]
+    fn test_parse_diagnostic() {
+        let line = r#"{"reason":"compiler-message","package_id":"foo 0.1.0","message":{"rendered":"warning: unresolved link to `Bar`\n --> src/lib.rs:3:6\n","children":[{"children":[],"code":null,"level":"note","message":"`#[warn(broken_intra_doc_links)]` on by default","rendered":null,"spans":[]}],"code":null,"level":"warning","message":"unresolved link to `Bar`","spans":[{"file_name":"src/lib.rs","is_primary":true,"line_start":3,"line_end":3}]}}"#;
+
+        assert_eq!(
+            parse_cargo_output(line, TARGET),
+            CargoOutput::Diagnostic {
+                warning: Some(DocWarning {
+                    target: TARGET.into(),
+                    level: "warning".into(),
+                    lint: Some("broken_intra_doc_links".into()),
+                    message: "unresolved link to `Bar`".into(),
+                    file: Some("src/lib.rs".into()),
+                    line: Some(3),
+                }),
+                rendered: "warning: unresolved link to `Bar`\n --> src/lib.rs:3:6\n".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_diagnostic_with_code() {
+        let line = r#"{"reason":"compiler-message","message":{"rendered":"warning: missing documentation for a function\n","children":[],"code":{"code":"missing_docs","explanation":null},"level":"warning","message":"missing documentation for a function","spans":[{"file_name":"src/lib.rs","is_primary":false,"line_start":1},{"file_name":"src/foo.rs","is_primary":true,"line_start":10}]}}"#;
+
+        match parse_cargo_output(line, TARGET) {
+            CargoOutput::Diagnostic {
+                warning: Some(warning),
+                ..
+            } => {
+                assert_eq!(warning.lint.as_deref(), Some("missing_docs"));
+                assert_eq!(warning.file.as_deref(), Some("src/foo.rs"));
+                assert_eq!(warning.line, Some(10));
+            }
+            other => panic!("unexpected output: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_other_lines() {
+        // Summaries aren't about a specific piece of code
+        let line = r#"{"reason":"compiler-message","message":{"rendered":"warning: 2 warnings emitted\n\n","children":[],"code":null,"level":"warning","message":"2 warnings emitted","spans":[]}}"#;
+        assert_eq!(
+            parse_cargo_output(line, TARGET),
+            CargoOutput::Diagnostic {
+                warning: None,
+                rendered: "warning: 2 warnings emitted\n\n".into(),
+            }
+        );
+
+        assert_eq!(
+            parse_cargo_output(
+                r#"{"reason":"compiler-artifact","package_id":"foo 0.1.0"}"#,
+                TARGET
+            ),
+            CargoOutput::Message
+        );
+        assert_eq!(
+            parse_cargo_output("   Documenting foo v0.1.0", TARGET),
+            CargoOutput::Text
+        );
+        assert_eq!(parse_cargo_output("{ not json", TARGET), CargoOutput::Text);
+    }
+}
@@ -0,0 +1,133 @@
+//! The invocation of cargo building the documentation of a crate, printed by `build explain`
+//! to reproduce a build outside of docs.rs.
+
+use super::Limits;
+use std::borrow::Cow;
+use std::fmt;
+use std::path::PathBuf;
+
+/// The command and the environment used to build the documentation of a crate for a target,
+/// as assembled by `RustwideBuilder::execute_build`.
+#[derive(Debug, Clone)]
+pub struct BuildInvocation {
+    pub target: String,
+    pub toolchain: String,
+    pub(crate) limits: Limits,
+    /// The program run in the sandbox and its arguments: a shell script running cargo and then
+    /// printing the peak memory usage of the sandbox
+    pub command: Vec<String>,
+    /// Environment variables, in the order they are set
+    pub env: Vec<(String, String)>,
+    /// Result of the build, if it was run outside of the sandbox, with the reason it failed
+    pub run_result: Option<Result<(), String>>,
+    /// Target directory of the build, if it was run outside of the sandbox
+    pub target_dir: Option<PathBuf>,
+}
+
+impl fmt::Display for BuildInvocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# documentation for {}", self.target)?;
+        writeln!(
+            f,
+            "# sandbox: {} bytes of memory, {} seconds timeout, networking {}",
+            self.limits.memory(),
+            self.limits.timeout().as_secs(),
+            if self.limits.networking() {
+                "allowed"
+            } else {
+                "blocked"
+            }
+        )?;
+        writeln!(f, "# toolchain: {}", self.toolchain)?;
+        writeln!(
+            f,
+            "# to run from the source directory of the crate, with $CARGO_HOME set to the cargo \
+             home of the toolchain"
+        )?;
+        for (name, value) in &self.env {
+            writeln!(f, "export {}={}", name, shell_quote(value))?;
+        }
+
+        let quoted = self
+            .command
+            .iter()
+            .map(|arg| shell_quote(arg))
+            .collect::<Vec<_>>();
+        writeln!(f, "{}", quoted.join(" "))?;
+
+        match (&self.run_result, &self.target_dir) {
+            (Some(Ok(())), Some(target_dir)) => writeln!(
+                f,
+                "# the build succeeded, its output is in {}",
+                target_dir.display()
+            ),
+            (Some(Err(reason)), _) => writeln!(f, "# the build failed: {}", reason),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Quotes `arg` for a POSIX shell, if it contains characters interpreted by the shell.
+fn shell_quote(arg: &str) -> Cow<'_, str> {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_=+./:,@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        Cow::Borrowed(arg)
+    } else {
+        Cow::Owned(format!("'{}'", arg.replace('\'', r"'\''")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("--cfg"), "--cfg");
+        assert_eq!(
+            shell_quote("foo=https://docs.rs/foo/1.0.0"),
+            "foo=https://docs.rs/foo/1.0.0"
+        );
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("a b"), "'a b'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+    }
+
+    #[test]
+    fn test_display() {
+        let invocation = BuildInvocation {
+            target: "x86_64-pc-windows-msvc".into(),
+            toolchain: "nightly".into(),
+            limits: Limits::default(),
+            command: vec![
+                "sh".into(),
+                "-c".into(),
+                "\"$CARGO_HOME/bin/cargo\" \"$@\"".into(),
+                "sh".into(),
+                "doc".into(),
+                "--features".into(),
+                "foo bar".into(),
+            ],
+            env: vec![
+                ("RUSTFLAGS".into(), "".into()),
+                ("DOCS_RS".into(), "1".into()),
+                ("RUSTUP_TOOLCHAIN".into(), "nightly".into()),
+            ],
+            run_result: None,
+            target_dir: None,
+        };
+
+        assert_eq!(
+            invocation.to_string(),
+            "# documentation for x86_64-pc-windows-msvc\n\
+             # sandbox: 3221225472 bytes of memory, 900 seconds timeout, networking blocked\n\
+             # toolchain: nightly\n\
+             # to run from the source directory of the crate, with $CARGO_HOME set to the cargo \
+             home of the toolchain\n\
+             export RUSTFLAGS=''\n\
+             export DOCS_RS=1\n\
+             export RUSTUP_TOOLCHAIN=nightly\n\
+             sh -c '\"$CARGO_HOME/bin/cargo\" \"$@\"' sh doc --features 'foo bar'\n"
+        );
+    }
+}
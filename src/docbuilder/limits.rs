@@ -10,6 +10,7 @@ pub(crate) struct Limits {
     timeout: Duration,
     networking: bool,
     max_log_size: usize,
+    rustdoc_json: bool,
 }
 
 impl Default for Limits {
@@ -20,6 +21,7 @@ impl Default for Limits {
             targets: 10,
             networking: false,
             max_log_size: 100 * 1024, // 100 KB
+            rustdoc_json: false,
         }
     }
 }
@@ -43,6 +45,7 @@ impl Limits {
             if let Some(targets) = row.get::<_, Option<i32>>("max_targets") {
                 limits.targets = targets as usize;
             }
+            limits.rustdoc_json = row.get("rustdoc_json");
         }
 
         Ok(limits)
@@ -67,6 +70,12 @@ impl Limits {
     pub(crate) fn targets(&self) -> usize {
         self.targets
     }
+
+    /// Whether to build the documentation in rustdoc's JSON format, even if the crate's metadata
+    /// doesn't ask for it.
+    pub(crate) fn rustdoc_json(&self) -> bool {
+        self.rustdoc_json
+    }
 }
 
 #[cfg(test)]
@@ -112,6 +121,13 @@ mod test {
                 &[&krate, &(limits.memory as i64), &(limits.timeout.as_secs() as i32), &(limits.targets as i32)]
             )?;
             assert_eq!(limits, Limits::for_crate(&db.conn(), krate)?);
+
+            let krate = "serde";
+            db.conn().query(
+                "INSERT INTO sandbox_overrides (crate_name, rustdoc_json) VALUES ($1, TRUE)",
+                &[&krate],
+            )?;
+            assert!(Limits::for_crate(&db.conn(), krate)?.rustdoc_json());
             Ok(())
         });
     }
@@ -1,6 +1,8 @@
+use super::rustwide_builder::{cargo_doc_args, rustdoc_flags};
 use crate::error::Result;
 use failure::err_msg;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
 use std::path::Path;
 use toml::{map::Map, Value};
 
@@ -23,9 +25,17 @@ use toml::{map::Map, Value};
 /// targets = [ "x86_64-apple-darwin", "x86_64-pc-windows-msvc" ]
 /// rustc-args = [ "--example-rustc-arg" ]
 /// rustdoc-args = [ "--example-rustdoc-arg" ]
+/// rustdoc-json = true
+/// cargo-args = [ "-Zunstable-options", "-Zrustdoc-scrape-examples=examples" ]
+/// env = { EXAMPLE_VAR = "value" }
+///
+/// [package.metadata.docs.rs.target.x86_64-pc-windows-msvc]
+/// features = [ "windows" ]
+/// rustdoc-args = [ "--cfg", "windows_docs" ]
 /// ```
 ///
 /// You can define one or more fields in your `Cargo.toml`.
+#[derive(Debug, Clone)]
 pub struct Metadata {
     /// List of features docs.rs will build.
     ///
@@ -62,6 +72,202 @@ pub struct Metadata {
 
     /// List of command line arguments for `rustdoc`.
     pub rustdoc_args: Option<Vec<String>>,
+
+    /// Set `rustdoc-json` to true to also build the documentation in rustdoc's JSON format.
+    pub rustdoc_json: bool,
+
+    /// List of additional command line arguments for `cargo`, like unstable flags.
+    ///
+    /// The flags set by docs.rs itself, like `--target-dir` or `-j`, are ignored.
+    pub cargo_args: Option<Vec<String>>,
+
+    /// Environment variables to set when building the documentation.
+    ///
+    /// The variables used by docs.rs itself, like `CARGO_*` or `RUSTDOCFLAGS`, are ignored.
+    pub env: BTreeMap<String, String>,
+
+    /// Settings replacing the ones above when building the documentation for a target, from the
+    /// `target.<triple>` tables.
+    pub target_overrides: BTreeMap<String, TargetOverrides>,
+}
+
+/// The settings which can be overridden for a single target, in a
+/// `[package.metadata.docs.rs.target.<triple>]` table.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TargetOverrides {
+    pub features: Option<Vec<String>>,
+    pub all_features: Option<bool>,
+    pub no_default_features: Option<bool>,
+    pub rustdoc_args: Option<Vec<String>>,
+}
+
+const METADATA_TABLE: &str = "package.metadata.docs.rs";
+
+/// The type expected for the value of a key of the metadata.
+#[derive(Debug, Copy, Clone)]
+enum KeyType {
+    Bool,
+    String,
+    StringArray,
+    Table,
+}
+
+impl KeyType {
+    fn matches(self, value: &Value) -> bool {
+        match self {
+            KeyType::Bool => value.is_bool(),
+            KeyType::String => value.is_str(),
+            KeyType::StringArray => value
+                .as_array()
+                .map(|values| values.iter().all(Value::is_str))
+                .unwrap_or(false),
+            KeyType::Table => value.is_table(),
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            KeyType::Bool => "a boolean",
+            KeyType::String => "a string",
+            KeyType::StringArray => "an array of strings",
+            KeyType::Table => "a table",
+        }
+    }
+}
+
+const METADATA_KEYS: &[(&str, KeyType)] = &[
+    ("features", KeyType::StringArray),
+    ("all-features", KeyType::Bool),
+    ("no-default-features", KeyType::Bool),
+    ("default-target", KeyType::String),
+    ("targets", KeyType::StringArray),
+    ("rustc-args", KeyType::StringArray),
+    ("rustdoc-args", KeyType::StringArray),
+    ("rustdoc-json", KeyType::Bool),
+    ("cargo-args", KeyType::StringArray),
+    ("env", KeyType::Table),
+    ("target", KeyType::Table),
+];
+
+const TARGET_OVERRIDE_KEYS: &[(&str, KeyType)] = &[
+    ("features", KeyType::StringArray),
+    ("all-features", KeyType::Bool),
+    ("no-default-features", KeyType::Bool),
+    ("rustdoc-args", KeyType::StringArray),
+];
+
+/// The cargo flags set by docs.rs, or changing what is built or where, which are removed from
+/// `cargo-args`. The boolean is whether the flag takes a value.
+const RESERVED_CARGO_ARGS: &[(&str, bool)] = &[
+    ("--target", true),
+    ("--target-dir", true),
+    ("--manifest-path", true),
+    ("--message-format", true),
+    ("--config", true),
+    ("--jobs", true),
+    ("-j", true),
+    ("--package", true),
+    ("-p", true),
+    ("--exclude", true),
+    ("--bin", true),
+    ("--example", true),
+    ("-C", true),
+    ("--workspace", false),
+    ("--all", false),
+    ("--lib", false),
+    ("--bins", false),
+    ("--examples", false),
+    ("--all-targets", false),
+    ("--no-deps", false),
+    ("--open", false),
+];
+
+/// Prefixes of the environment variables read by cargo and rustup, which are removed from `env`.
+const RESERVED_ENV_PREFIXES: &[&str] = &["CARGO_", "RUSTUP_"];
+
+/// The environment variables set by docs.rs, which are removed from `env`.
+const RESERVED_ENV: &[&str] = &[
+    "RUSTFLAGS",
+    "RUSTDOCFLAGS",
+    "DOCS_RS",
+    "DOCSRS_BUILD_WORKER",
+];
+
+fn is_reserved_env(name: &str) -> bool {
+    RESERVED_ENV.contains(&name)
+        || RESERVED_ENV_PREFIXES
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+}
+
+/// Splits `cargo-args` into the arguments passed to cargo and the reserved flags which are
+/// removed, with their values.
+fn split_reserved_cargo_args(args: Vec<String>) -> (Vec<String>, Vec<String>) {
+    let mut allowed = Vec::new();
+    let mut reserved = Vec::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let flag = RESERVED_CARGO_ARGS.iter().find(|(flag, _)| {
+            arg == *flag
+                || arg.starts_with(&format!("{}=", flag))
+                // Short flags can be directly followed by their value, like `-j4`
+                || (flag.len() == 2 && arg.starts_with(flag))
+        });
+        match flag {
+            Some((flag, true)) if arg == *flag => {
+                let value = args.next().unwrap_or_default();
+                reserved.push(format!("{} {}", arg, value).trim_end().to_string());
+            }
+            Some(_) => reserved.push(arg),
+            None => allowed.push(arg),
+        }
+    }
+
+    (allowed, reserved)
+}
+
+fn fetch_manifest_tables(manifest: &Value) -> Option<&Map<String, Value>> {
+    manifest
+        .get("package")?
+        .as_table()?
+        .get("metadata")?
+        .as_table()?
+        .get("docs")?
+        .as_table()?
+        .get("rs")?
+        .as_table()
+}
+
+/// Adds a warning for each key of `table`, named `prefix.<key>`, which isn't in `keys` or
+/// doesn't have the expected type.
+fn check_keys(
+    table: &Map<String, Value>,
+    keys: &[(&str, KeyType)],
+    prefix: &str,
+    warnings: &mut Vec<String>,
+) {
+    for (key, value) in table {
+        match keys.iter().find(|(name, _)| name == key) {
+            Some((_, expected)) if !expected.matches(value) => warnings.push(format!(
+                "`{}.{}` should be {}, found {}",
+                prefix,
+                key,
+                expected.description(),
+                value.type_str()
+            )),
+            Some(_) => {}
+            None => {
+                let mut warning = format!("unknown key `{}.{}`", prefix, key);
+                // Cargo accepts both for its own keys, but docs.rs only reads the dashed ones.
+                let dashed = key.replace('_', "-");
+                if keys.iter().any(|(name, _)| *name == dashed) {
+                    warning.push_str(&format!(", did you mean `{}`?", dashed));
+                }
+                warnings.push(warning);
+            }
+        }
+    }
 }
 
 /// The targets that should be built for a crate.
@@ -114,6 +320,10 @@ impl Metadata {
             rustc_args: None,
             rustdoc_args: None,
             targets: None,
+            rustdoc_json: false,
+            cargo_args: None,
+            env: BTreeMap::new(),
+            target_overrides: BTreeMap::new(),
         }
     }
 
@@ -126,18 +336,6 @@ impl Metadata {
             return metadata;
         };
 
-        fn fetch_manifest_tables<'a>(manifest: &'a Value) -> Option<&'a Map<String, Value>> {
-            manifest
-                .get("package")?
-                .as_table()?
-                .get("metadata")?
-                .as_table()?
-                .get("docs")?
-                .as_table()?
-                .get("rs")?
-                .as_table()
-        }
-
         if let Some(table) = fetch_manifest_tables(&manifest) {
             let collect_into_array =
                 |f: &Vec<Value>| f.iter().map(|v| v.as_str().map(|v| v.to_owned())).collect();
@@ -176,11 +374,167 @@ impl Metadata {
                 .get("rustdoc-args")
                 .and_then(|f| f.as_array())
                 .and_then(collect_into_array);
+
+            metadata.rustdoc_json = table
+                .get("rustdoc-json")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(metadata.rustdoc_json);
+
+            metadata.cargo_args = table
+                .get("cargo-args")
+                .and_then(|f| f.as_array())
+                .and_then(collect_into_array)
+                .map(|args| split_reserved_cargo_args(args).0);
+
+            if let Some(env) = table.get("env").and_then(|v| v.as_table()) {
+                metadata.env = env
+                    .iter()
+                    .filter(|(name, _)| !is_reserved_env(name))
+                    .filter_map(|(name, value)| Some((name.clone(), value.as_str()?.to_owned())))
+                    .collect();
+            }
+
+            if let Some(targets) = table.get("target").and_then(|v| v.as_table()) {
+                for (target, overrides) in targets {
+                    let overrides = match overrides.as_table() {
+                        Some(overrides) => overrides,
+                        None => continue,
+                    };
+                    metadata.target_overrides.insert(
+                        target.clone(),
+                        TargetOverrides {
+                            features: overrides
+                                .get("features")
+                                .and_then(|f| f.as_array())
+                                .and_then(collect_into_array),
+                            all_features: overrides.get("all-features").and_then(|v| v.as_bool()),
+                            no_default_features: overrides
+                                .get("no-default-features")
+                                .and_then(|v| v.as_bool()),
+                            rustdoc_args: overrides
+                                .get("rustdoc-args")
+                                .and_then(|f| f.as_array())
+                                .and_then(collect_into_array),
+                        },
+                    );
+                }
+            }
+        }
+
+        metadata
+    }
+
+    /// Returns warnings about the keys of the `[package.metadata.docs.rs]` table which are unknown
+    /// or have the wrong type, as they are ignored by `from_str`.
+    fn check(manifest: &str) -> Vec<String> {
+        let manifest = match manifest.parse::<Value>() {
+            Ok(manifest) => manifest,
+            Err(err) => return vec![format!("failed to parse the manifest: {}", err)],
+        };
+        let table = match fetch_manifest_tables(&manifest) {
+            Some(table) => table,
+            None => {
+                return vec![format!(
+                    "the manifest has no `[{}]` table, the default settings are used",
+                    METADATA_TABLE
+                )]
+            }
+        };
+
+        let mut warnings = Vec::new();
+        check_keys(table, METADATA_KEYS, METADATA_TABLE, &mut warnings);
+
+        if let Some(args) = table.get("cargo-args").and_then(|v| v.as_array()) {
+            let args = args
+                .iter()
+                .filter_map(|arg| Some(arg.as_str()?.to_owned()))
+                .collect();
+            for reserved in split_reserved_cargo_args(args).1 {
+                warnings.push(format!(
+                    "`{}.cargo-args` can't contain `{}`, which is set by docs.rs, it is ignored",
+                    METADATA_TABLE, reserved
+                ));
+            }
         }
 
+        if let Some(env) = table.get("env").and_then(|v| v.as_table()) {
+            for (name, value) in env {
+                if is_reserved_env(name) {
+                    warnings.push(format!(
+                        "`{}.env` can't contain `{}`, which is used by docs.rs, it is ignored",
+                        METADATA_TABLE, name
+                    ));
+                } else if !value.is_str() {
+                    warnings.push(format!(
+                        "`{}.env.{}` should be a string, found {}",
+                        METADATA_TABLE,
+                        name,
+                        value.type_str()
+                    ));
+                }
+            }
+        }
+
+        if let Some(targets) = table.get("target").and_then(|v| v.as_table()) {
+            for (target, overrides) in targets {
+                let prefix = format!("{}.target.{}", METADATA_TABLE, target);
+                match overrides.as_table() {
+                    Some(overrides) => {
+                        check_keys(overrides, TARGET_OVERRIDE_KEYS, &prefix, &mut warnings)
+                    }
+                    None => warnings.push(format!(
+                        "`{}` should be a table, found {}",
+                        prefix,
+                        overrides.type_str()
+                    )),
+                }
+            }
+        }
+
+        warnings
+    }
+
+    /// Returns the metadata to use when building the documentation for `target`, with the
+    /// settings of its `target.<triple>` table applied.
+    pub(super) fn for_target(&self, target: &str) -> Metadata {
+        let mut metadata = self.clone();
+        if let Some(overrides) = self.target_overrides.get(target) {
+            if let Some(features) = &overrides.features {
+                metadata.features = Some(features.clone());
+            }
+            if let Some(all_features) = overrides.all_features {
+                metadata.all_features = all_features;
+            }
+            if let Some(no_default_features) = overrides.no_default_features {
+                metadata.no_default_features = no_default_features;
+            }
+            if let Some(rustdoc_args) = &overrides.rustdoc_args {
+                metadata.rustdoc_args = Some(rustdoc_args.clone());
+            }
+        }
         metadata
     }
 
+    /// Returns the arguments selecting the features to build, followed by `cargo-args`, to pass
+    /// to cargo.
+    pub(super) fn all_cargo_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(features) = &self.features {
+            args.push("--features".to_string());
+            args.push(features.join(" "));
+        }
+        if self.all_features {
+            args.push("--all-features".to_string());
+        }
+        if self.no_default_features {
+            args.push("--no-default-features".to_string());
+        }
+        if let Some(cargo_args) = &self.cargo_args {
+            args.extend(cargo_args.iter().cloned());
+        }
+        args
+    }
+
     pub(super) fn targets(&self) -> BuildTargets<'_> {
         use super::rustwide_builder::{HOST_TARGET, TARGETS};
 
@@ -210,9 +564,100 @@ impl Metadata {
     }
 }
 
+/// Placeholder for the suffix of the shared rustdoc resources, which depends on the toolchain.
+const RESOURCE_SUFFIX_PLACEHOLDER: &str = "-<rustc version>";
+
+/// The result of checking the `[package.metadata.docs.rs]` table of a manifest, along with how
+/// docs.rs would build the documentation with it.
+#[derive(Debug)]
+pub struct MetadataReport {
+    pub warnings: Vec<String>,
+    pub default_target: String,
+    pub other_targets: Vec<String>,
+    /// How the documentation is built for each target, starting with the default one
+    pub builds: Vec<TargetBuild>,
+}
+
+/// The command and the flags used to build the documentation for a target.
+#[derive(Debug)]
+pub struct TargetBuild {
+    pub target: String,
+    pub cargo_args: Vec<String>,
+    pub rustflags: String,
+    /// The flags linking to the documentation of the dependencies are omitted, as they are only
+    /// known during the build.
+    pub rustdocflags: String,
+}
+
+/// Checks the docs.rs metadata of the manifest `manifest`, returning the warnings about it and
+/// the settings which would be used to build the documentation.
+pub fn check_metadata(manifest: &str) -> MetadataReport {
+    let metadata = Metadata::from_str(manifest);
+    let targets = metadata.targets();
+    let mut other_targets = targets
+        .other_targets
+        .iter()
+        .map(|target| target.to_string())
+        .collect::<Vec<_>>();
+    other_targets.sort();
+
+    let builds = std::iter::once(targets.default_target)
+        .chain(other_targets.iter().map(String::as_str))
+        .map(|target| {
+            let metadata = metadata.for_target(target);
+            TargetBuild {
+                target: target.to_string(),
+                cargo_args: cargo_doc_args(target, &metadata, None),
+                rustflags: metadata
+                    .rustc_args
+                    .as_ref()
+                    .map(|args| args.join(" "))
+                    .unwrap_or_default(),
+                rustdocflags: rustdoc_flags(&metadata, RESOURCE_SUFFIX_PLACEHOLDER, &[]).join(" "),
+            }
+        })
+        .collect();
+
+    MetadataReport {
+        warnings: Metadata::check(manifest),
+        default_target: targets.default_target.to_string(),
+        other_targets,
+        builds,
+    }
+}
+
+impl fmt::Display for MetadataReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for warning in &self.warnings {
+            writeln!(f, "warning: {}", warning)?;
+        }
+        if !self.warnings.is_empty() {
+            writeln!(f)?;
+        }
+
+        writeln!(f, "default target: {}", self.default_target)?;
+        if !self.other_targets.is_empty() {
+            writeln!(f, "other targets: {}", self.other_targets.join(", "))?;
+        }
+        for build in &self.builds {
+            writeln!(f, "\n{}:", build.target)?;
+            writeln!(f, "    cargo {}", build.cargo_args.join(" "))?;
+            writeln!(f, "    RUSTFLAGS={}", build.rustflags)?;
+            writeln!(f, "    RUSTDOCFLAGS={}", build.rustdocflags)?;
+        }
+        writeln!(
+            f,
+            "\n`--extern-html-root-url` flags linking to the documentation of the dependencies \
+             are added to RUSTDOCFLAGS during the build."
+        )
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::Metadata;
+    use super::{cargo_doc_args, Metadata};
+    use crate::docbuilder::rustwide_builder::cargo_env;
+    use std::collections::BTreeMap;
 
     #[test]
     fn test_cratesfyi_metadata() {
@@ -229,6 +674,7 @@ mod test {
             targets = [ "x86_64-apple-darwin", "x86_64-pc-windows-msvc" ]
             rustc-args = [ "--example-rustc-arg" ]
             rustdoc-args = [ "--example-rustdoc-arg" ]
+            rustdoc-json = true
         "#;
 
         let metadata = Metadata::from_str(manifest);
@@ -238,6 +684,7 @@ mod test {
         assert!(metadata.no_default_features);
         assert!(metadata.default_target.is_some());
         assert!(metadata.rustdoc_args.is_some());
+        assert!(metadata.rustdoc_json);
 
         let features = metadata.features.unwrap();
         assert_eq!(features.len(), 2);
@@ -263,6 +710,240 @@ mod test {
         assert_eq!(rustdoc_args[0], "--example-rustdoc-arg".to_owned());
     }
 
+    #[test]
+    fn test_cargo_args_and_env() {
+        let metadata = Metadata::from_str(
+            r#"
+            [package.metadata.docs.rs]
+            features = [ "feature1" ]
+            cargo-args = [ "-Zunstable-options", "-Zrustdoc-scrape-examples=examples" ]
+            env = { FOO = "bar", NOT_A_STRING = 1 }
+        "#,
+        );
+
+        assert_eq!(
+            metadata.all_cargo_args(),
+            vec![
+                "--features",
+                "feature1",
+                "-Zunstable-options",
+                "-Zrustdoc-scrape-examples=examples",
+            ]
+        );
+        assert_eq!(
+            metadata.env.into_iter().collect::<Vec<_>>(),
+            vec![("FOO".to_string(), "bar".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_reserved_cargo_args() {
+        let metadata = Metadata::from_str(
+            r#"
+            [package.metadata.docs.rs]
+            cargo-args = [
+                "-Zunstable-options",
+                "-j", "64",
+                "--target-dir", "/tmp/docs",
+                "--manifest-path=other/Cargo.toml",
+                "-j128",
+                "--config", "build.jobs=64",
+                "--lib",
+                "-Zrustdoc-scrape-examples=examples",
+                "--target",
+            ]
+        "#,
+        );
+        assert_eq!(
+            metadata.all_cargo_args(),
+            vec!["-Zunstable-options", "-Zrustdoc-scrape-examples=examples"]
+        );
+
+        // The `--target-dir` of cargo-args doesn't replace the one of docs.rs
+        let args = cargo_doc_args("x86_64-unknown-linux-gnu", &metadata, Some(2));
+        assert!(!args.iter().any(|arg| arg.contains("/tmp/docs")));
+        assert_eq!(args.iter().filter(|arg| arg.starts_with("-j")).count(), 1);
+
+        assert_eq!(
+            Metadata::check(
+                r#"
+                [package.metadata.docs.rs]
+                cargo-args = [ "-Zunstable-options", "-j", "64", "--target-dir=/tmp/docs" ]
+            "#
+            ),
+            vec![
+                "`package.metadata.docs.rs.cargo-args` can't contain `-j 64`, which is set by docs.rs, it is ignored",
+                "`package.metadata.docs.rs.cargo-args` can't contain `--target-dir=/tmp/docs`, which is set by docs.rs, it is ignored",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reserved_env() {
+        let manifest = r#"
+            [package.metadata.docs.rs]
+            env = { FOO = "bar", CARGO_TARGET_DIR = "/tmp/docs", DOCSRS_BUILD_WORKER = "", RUSTUP_TOOLCHAIN = "stable", RUSTDOCFLAGS = "--cfg foo" }
+        "#;
+        let metadata = Metadata::from_str(manifest);
+        assert_eq!(
+            metadata.env.clone().into_iter().collect::<Vec<_>>(),
+            vec![("FOO".to_string(), "bar".to_string())]
+        );
+
+        // The variables set by docs.rs are the last ones, so they win when the env is applied
+        let env = cargo_env(&metadata, &[], "worker-1", Some("nightly-2020-10-01"))
+            .into_iter()
+            .collect::<BTreeMap<_, _>>();
+        assert!(!env.contains_key("CARGO_TARGET_DIR"));
+        assert_eq!(env["DOCSRS_BUILD_WORKER"], "worker-1");
+        assert_eq!(env["RUSTUP_TOOLCHAIN"], "nightly-2020-10-01");
+        assert_eq!(env["RUSTDOCFLAGS"], "");
+        assert_eq!(env["FOO"], "bar");
+
+        let warning = |name: &str| {
+            format!(
+                "`package.metadata.docs.rs.env` can't contain `{}`, which is used by docs.rs, it is ignored",
+                name
+            )
+        };
+        assert_eq!(
+            Metadata::check(manifest),
+            vec![
+                warning("CARGO_TARGET_DIR"),
+                warning("DOCSRS_BUILD_WORKER"),
+                warning("RUSTDOCFLAGS"),
+                warning("RUSTUP_TOOLCHAIN"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_target_overrides() {
+        let metadata = Metadata::from_str(
+            r#"
+            [package.metadata.docs.rs]
+            features = [ "common" ]
+            rustdoc-args = [ "--cfg", "docsrs" ]
+
+            [package.metadata.docs.rs.target.x86_64-pc-windows-msvc]
+            features = [ "common", "windows" ]
+            no-default-features = true
+
+            [package.metadata.docs.rs.target.x86_64-apple-darwin]
+            rustdoc-args = [ "--cfg", "macos_docs" ]
+        "#,
+        );
+
+        let windows = metadata.for_target("x86_64-pc-windows-msvc");
+        assert_eq!(
+            windows.all_cargo_args(),
+            vec!["--features", "common windows", "--no-default-features"]
+        );
+        assert_eq!(
+            windows.rustdoc_args,
+            Some(vec!["--cfg".to_string(), "docsrs".to_string()])
+        );
+
+        let macos = metadata.for_target("x86_64-apple-darwin");
+        assert_eq!(macos.all_cargo_args(), vec!["--features", "common"]);
+        assert_eq!(
+            macos.rustdoc_args,
+            Some(vec!["--cfg".to_string(), "macos_docs".to_string()])
+        );
+
+        // Targets without a table use the settings of the crate
+        let linux = metadata.for_target("x86_64-unknown-linux-gnu");
+        assert_eq!(linux.all_cargo_args(), vec!["--features", "common"]);
+        assert_eq!(linux.rustdoc_args, metadata.rustdoc_args);
+    }
+
+    #[test]
+    fn test_check() {
+        let warnings = Metadata::check(
+            r#"
+            [package]
+            name = "test"
+
+            [package.metadata.docs.rs]
+            all_features = true
+            features = [ "foo", 1 ]
+            default-target = [ "x86_64-apple-darwin" ]
+            rustdoc-json = true
+            env = { FOO = "bar", BAZ = 1 }
+
+            [package.metadata.docs.rs.target.x86_64-pc-windows-msvc]
+            no-default-features = "yes"
+            rustc-args = [ "--cfg", "windows" ]
+        "#,
+        );
+        assert_eq!(
+            warnings,
+            vec![
+                "unknown key `package.metadata.docs.rs.all_features`, did you mean `all-features`?",
+                "`package.metadata.docs.rs.default-target` should be a string, found array",
+                "`package.metadata.docs.rs.features` should be an array of strings, found array",
+                "`package.metadata.docs.rs.env.BAZ` should be a string, found integer",
+                "`package.metadata.docs.rs.target.x86_64-pc-windows-msvc.no-default-features` should be a boolean, found string",
+                "unknown key `package.metadata.docs.rs.target.x86_64-pc-windows-msvc.rustc-args`",
+            ]
+        );
+
+        assert!(Metadata::check(
+            r#"
+            [package.metadata.docs.rs]
+            features = [ "foo" ]
+            target.x86_64-apple-darwin.all-features = true
+        "#
+        )
+        .is_empty());
+        assert_eq!(
+            Metadata::check("[package]\nname = \"test\""),
+            vec!["the manifest has no `[package.metadata.docs.rs]` table, the default settings are used"]
+        );
+        assert!(Metadata::check("[package")[0].starts_with("failed to parse the manifest"));
+    }
+
+    #[test]
+    fn test_check_metadata_report() {
+        use super::check_metadata;
+
+        let report = check_metadata(
+            r#"
+            [package.metadata.docs.rs]
+            features = [ "common" ]
+            targets = [ "x86_64-unknown-linux-gnu", "x86_64-pc-windows-msvc" ]
+            rustc-args = [ "--cfg", "docs" ]
+
+            [package.metadata.docs.rs.target.x86_64-pc-windows-msvc]
+            features = [ "windows" ]
+        "#,
+        );
+        assert!(report.warnings.is_empty());
+        assert_eq!(report.default_target, "x86_64-unknown-linux-gnu");
+        assert_eq!(report.other_targets, vec!["x86_64-pc-windows-msvc"]);
+
+        let windows = &report.builds[1];
+        assert_eq!(windows.target, "x86_64-pc-windows-msvc");
+        assert_eq!(
+            windows.cargo_args,
+            vec![
+                "doc",
+                "--lib",
+                "--no-deps",
+                "--message-format=json",
+                "--target",
+                "x86_64-pc-windows-msvc",
+                "--features",
+                "windows"
+            ]
+        );
+        assert_eq!(windows.rustflags, "--cfg docs");
+        assert_eq!(
+            windows.rustdocflags,
+            "-Z unstable-options --resource-suffix -<rustc version> --static-root-path / --cap-lints warn"
+        );
+    }
+
     #[test]
     fn test_no_targets() {
         // metadata section but no targets
@@ -1,21 +1,31 @@
+mod coverage;
 mod crates;
+mod doc_warnings;
+mod invocation;
 mod limits;
 mod metadata;
 pub(crate) mod options;
 mod queue;
 mod rustwide_builder;
 
+pub(crate) use self::coverage::FileCoverage;
+pub(crate) use self::doc_warnings::DocWarning;
 pub(crate) use self::limits::Limits;
-pub(self) use self::metadata::Metadata;
+pub use self::metadata::check_metadata;
+use self::metadata::Metadata;
+pub(crate) use self::rustwide_builder::essential_files;
+#[cfg(test)]
+pub(crate) use self::rustwide_builder::BuildResources;
 pub(crate) use self::rustwide_builder::BuildResult;
 pub use self::rustwide_builder::RustwideBuilder;
 
+use crate::db::builder_lock::{get_builder_lock, remove_builder_lock, set_builder_lock};
 use crate::db::Pool;
 use crate::error::Result;
 use crate::index::Index;
 use crate::BuildQueue;
 use crate::DocBuilderOptions;
-use log::debug;
+use log::{debug, error};
 use std::collections::BTreeSet;
 use std::fs;
 use std::io::prelude::*;
@@ -87,7 +97,11 @@ impl DocBuilder {
         debug!("Saving cache");
 
         let path = PathBuf::from(&self.options.prefix).join("cache");
-        let mut file = fs::OpenOptions::new().write(true).create(true).open(path)?;
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
 
         for krate in &self.cache {
             writeln!(file, "{}", krate)?;
@@ -100,18 +114,33 @@ impl DocBuilder {
         self.options.prefix.join("cratesfyi.lock")
     }
 
-    /// Creates a lock file. Daemon will check this lock file and stop operating if its exists.
-    pub fn lock(&self) -> Result<()> {
+    /// Pauses the builds of all the daemons, recording who paused them and why. A lock file is
+    /// also created, for the daemons on this machine to stop even if the database is unavailable.
+    ///
+    /// The lock file is only created on the host running this, and only `unlock` on the same host
+    /// removes it.
+    pub fn lock(&self, locked_by: &str, reason: Option<&str>) -> Result<()> {
+        set_builder_lock(&*self.db.get()?, locked_by, reason)?;
+
         let path = self.lock_path();
         if !path.exists() {
-            fs::OpenOptions::new().write(true).create(true).open(path)?;
+            fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(path)?;
         }
 
         Ok(())
     }
 
-    /// Removes lock file.
+    /// Resumes the builds, removing both the lock in the database and the lock file.
+    ///
+    /// Only the lock file of the host running this is removed: the daemons of another host where
+    /// the builds were paused stay paused until the builds are unlocked there too.
     pub fn unlock(&self) -> Result<()> {
+        remove_builder_lock(&*self.db.get()?)?;
+
         let path = self.lock_path();
         if path.exists() {
             fs::remove_file(path)?;
@@ -120,9 +149,30 @@ impl DocBuilder {
         Ok(())
     }
 
-    /// Checks for the lock file and returns whether it currently exists.
+    /// Returns whether the builds are paused, either in the database or with the lock file.
+    ///
+    /// The builds are considered paused when the lock can't be checked, as they might have been
+    /// paused in the database.
     pub fn is_locked(&self) -> bool {
-        self.lock_path().exists()
+        if self.lock_path().exists() {
+            return true;
+        }
+
+        let lock = self
+            .db
+            .get()
+            .map_err(Into::into)
+            .and_then(|conn| get_builder_lock(&conn));
+        match lock {
+            Ok(lock) => lock.is_some(),
+            Err(e) => {
+                error!(
+                    "Failed to check whether the builds are paused, treating them as paused: {}",
+                    e
+                );
+                true
+            }
+        }
     }
 
     /// Returns a reference of options
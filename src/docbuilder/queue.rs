@@ -1,17 +1,38 @@
 //! Updates registry index and builds new packages
 
 use super::{DocBuilder, RustwideBuilder};
+use crate::db::index_state;
 use crate::error::Result;
 use crate::utils::get_crate_priority;
 use crates_index_diff::ChangeKind;
+use failure::ResultExt;
 use log::{debug, error};
+use parking_lot::{const_mutex, Mutex};
+
+/// Prevents multiple threads of this process from syncing the registry index at the same time,
+/// which would otherwise see the same changes and enqueue them twice.
+static INDEX_SYNC_LOCK: Mutex<()> = const_mutex(());
 
 impl DocBuilder {
     /// Updates registry index repository and adds new crates into build queue.
     /// Returns the number of crates added
+    ///
+    /// The last processed commit is stored in the database, and takes precedence over the
+    /// tracking branch of the local index repository.
     pub fn get_new_crates(&mut self) -> Result<usize> {
+        let _lock = INDEX_SYNC_LOCK.lock();
+
         let conn = self.db.get()?;
         let diff = self.index.diff()?;
+        if let Some(commit) = index_state::get_last_seen_commit(&conn)? {
+            diff.set_last_seen_reference(git2::Oid::from_str(&commit)?)
+                .with_context(|_| {
+                    format!(
+                        "the last seen index commit {} is missing from the local repository",
+                        commit
+                    )
+                })?;
+        }
         let (mut changes, oid) = diff.peek_changes()?;
         let mut crates_added = 0;
 
@@ -48,10 +69,13 @@ impl DocBuilder {
                         .build_queue
                         .add_crate(&krate.name, &krate.version, priority)
                     {
-                        Ok(()) => {
+                        Ok(true) => {
                             debug!("{}-{} added into build queue", krate.name, krate.version);
                             crates_added += 1;
                         }
+                        Ok(false) => {
+                            debug!("{}-{} is already queued", krate.name, krate.version)
+                        }
                         Err(err) => error!(
                             "failed adding {}-{} into build queue: {}",
                             krate.name, krate.version, err
@@ -61,12 +85,14 @@ impl DocBuilder {
             }
         }
 
+        index_state::set_last_seen_commit(&conn, &oid.to_string())?;
         diff.set_last_seen_reference(oid)?;
 
         Ok(crates_added)
     }
 
-    /// Builds the top package from the queue. Returns whether there was a package in the queue.
+    /// Builds the top package from the queue. Returns whether there was a package in the queue
+    /// that wasn't already claimed by another builder.
     ///
     /// Note that this will return `Ok(true)` even if the package failed to build.
     pub(crate) fn build_next_queue_package(
@@ -75,10 +101,16 @@ impl DocBuilder {
     ) -> Result<bool> {
         let mut processed = false;
         let queue = self.build_queue.clone();
-        queue.process_next_crate(|krate| {
+        queue.process_next_crate_cancellable(|krate, cancellation| {
             processed = true;
 
-            builder.build_package(self, &krate.name, &krate.version, None)?;
+            builder.build_package_cancellable(
+                self,
+                &krate.name,
+                &krate.version,
+                None,
+                cancellation,
+            )?;
             Ok(())
         })?;
 
@@ -1,15 +1,21 @@
+use super::coverage::{parse_coverage, FileCoverage};
+use super::doc_warnings::{
+    parse_cargo_output, CargoOutput, DocWarning, MAX_DOC_WARNINGS_PER_TARGET,
+};
+use super::invocation::BuildInvocation;
 use super::DocBuilder;
 use super::Metadata;
+use crate::build_queue::{Cancellation, ErrorCategory};
 use crate::db::blacklist::is_blacklisted;
 use crate::db::file::add_path_into_database;
-use crate::db::{add_build_into_database, add_package_into_database, Pool};
+use crate::db::{add_build_into_database, add_doc_coverage, add_package_into_database, Pool};
 use crate::docbuilder::{crates::crates_from_path, Limits};
 use crate::error::Result;
-use crate::storage::CompressionAlgorithms;
-use crate::storage::Storage;
-use crate::utils::{copy_doc_dir, parse_rustc_version, CargoMetadata};
-use failure::ResultExt;
-use log::{debug, info, warn, LevelFilter};
+use crate::storage::{rustdoc_archive_path, CompressionAlgorithms, Storage};
+use crate::utils::{copy_doc_dir, parse_rustc_version, CargoMetadata, MetadataPackage};
+use crate::Config;
+use failure::{Context, ResultExt};
+use log::{debug, error, info, warn, LevelFilter};
 use rustwide::cmd::{Command, SandboxBuilder};
 use rustwide::logging::{self, LogStorage};
 use rustwide::toolchain::ToolchainError;
@@ -17,8 +23,10 @@ use rustwide::{Build, Crate, Toolchain, Workspace, WorkspaceBuilder};
 use serde_json::Value;
 use std::borrow::Cow;
 use std::collections::HashSet;
+use std::fmt;
 use std::path::Path;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 const USER_AGENT: &str = "docs.rs builder (https://github.com/rust-lang/docs.rs)";
 const DEFAULT_RUSTWIDE_WORKSPACE: &str = ".rustwide";
@@ -62,12 +70,164 @@ const ESSENTIAL_FILES_UNVERSIONED: &[&str] = &[
     "SourceSerifPro-It.ttf.woff",
 ];
 
+/// Returns the names of the files shared by all the documentation built with a rustc version,
+/// given the suffix returned for it by `parse_rustc_version`.
+pub(crate) fn essential_files(rustc_version: &str) -> Vec<String> {
+    let versioned = ESSENTIAL_FILES_VERSIONED.iter().map(|file| {
+        let segments = file.rsplitn(2, '.').collect::<Vec<_>>();
+        format!("{}-{}.{}", segments[1], rustc_version, segments[0])
+    });
+    let unversioned = ESSENTIAL_FILES_UNVERSIONED
+        .iter()
+        .map(|file| file.to_string());
+
+    versioned.chain(unversioned).collect()
+}
+
+/// Returns the arguments of `cargo doc` used to build the documentation for `target`, given the
+/// metadata for that target.
+pub(super) fn cargo_doc_args(
+    target: &str,
+    metadata: &Metadata,
+    cpu_limit: Option<u32>,
+) -> Vec<String> {
+    // The diagnostics are parsed from the JSON messages, see `parse_cargo_output`.
+    let mut args = vec![
+        "doc".to_string(),
+        "--lib".to_string(),
+        "--no-deps".to_string(),
+        "--message-format=json".to_string(),
+    ];
+    args.extend(cargo_target_args(target, metadata, cpu_limit));
+    args
+}
+
+/// Returns the arguments selecting the target, the features and the number of jobs to pass
+/// to cargo.
+fn cargo_target_args(target: &str, metadata: &Metadata, cpu_limit: Option<u32>) -> Vec<String> {
+    let mut args = Vec::new();
+    if target != HOST_TARGET {
+        args.push("--target".to_string());
+        args.push(target.to_string());
+    }
+    if let Some(cpu_limit) = cpu_limit {
+        args.push(format!("-j{}", cpu_limit));
+    }
+    args.extend(metadata.all_cargo_args());
+    args
+}
+
+/// Returns the flags passed to rustdoc through `RUSTDOCFLAGS` when building the documentation,
+/// given the suffix of the shared resources and the dependencies to link to.
+pub(super) fn rustdoc_flags(
+    metadata: &Metadata,
+    resource_suffix: &str,
+    dependencies: &[&MetadataPackage],
+) -> Vec<String> {
+    let mut rustdoc_flags: Vec<String> = vec![
+        "-Z".to_string(),
+        "unstable-options".to_string(),
+        "--resource-suffix".to_string(),
+        resource_suffix.to_string(),
+        "--static-root-path".to_string(),
+        "/".to_string(),
+        "--cap-lints".to_string(),
+        "warn".to_string(),
+    ];
+    for dep in dependencies {
+        rustdoc_flags.push("--extern-html-root-url".to_string());
+        rustdoc_flags.push(format!(
+            "{}=https://docs.rs/{}/{}",
+            dep.name.replace("-", "_"),
+            dep.name,
+            dep.version
+        ));
+    }
+    if let Some(package_rustdoc_args) = &metadata.rustdoc_args {
+        rustdoc_flags.extend(package_rustdoc_args.iter().cloned());
+    }
+    rustdoc_flags
+}
+
+/// Returns the environment of cargo when building the documentation, given the metadata for the
+/// target. The variables set by docs.rs come after the ones of the metadata, so they take
+/// precedence.
+pub(super) fn cargo_env(
+    metadata: &Metadata,
+    rustdoc_flags: &[String],
+    worker_id: &str,
+    toolchain: Option<&str>,
+) -> Vec<(String, String)> {
+    let mut env = metadata
+        .env
+        .iter()
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect::<Vec<_>>();
+    env.push((
+        "RUSTFLAGS".to_string(),
+        metadata
+            .rustc_args
+            .as_ref()
+            .map(|args| args.join(" "))
+            .unwrap_or_default(),
+    ));
+    env.push(("RUSTDOCFLAGS".to_string(), rustdoc_flags.join(" ")));
+    // For docs.rs detection from build script:
+    // https://github.com/rust-lang/docs.rs/issues/147
+    env.push(("DOCS_RS".to_string(), "1".to_string()));
+    env.push((SANDBOX_WORKER_ENV.to_string(), worker_id.to_string()));
+    // The toolchain is otherwise selected by rustwide with a `+toolchain` argument.
+    if let Some(toolchain) = toolchain {
+        env.push(("RUSTUP_TOOLCHAIN".to_string(), toolchain.to_string()));
+    }
+    env
+}
+
+/// Returns the command running cargo with `cargo_args` in the sandbox, which prints the peak
+/// memory usage of the sandbox once cargo exits, see `PEAK_MEMORY_SCRIPT`.
+///
+/// The toolchain is selected by the environment of the invocation, see `cargo_env`.
+fn cargo_with_peak_memory(cargo_args: Vec<String>) -> Vec<String> {
+    let mut command = vec![
+        "sh".to_string(),
+        "-c".to_string(),
+        PEAK_MEMORY_SCRIPT.to_string(),
+        "sh".to_string(),
+    ];
+    command.extend(cargo_args);
+    command
+}
+
 const DUMMY_CRATE_NAME: &str = "empty-library";
+
+/// Directory of the target directory of a build where the rustdoc JSON output of each target is
+/// collected before being uploaded.
+const RUSTDOC_JSON_DIR: &str = "docsrs-json";
 const DUMMY_CRATE_VERSION: &str = "1.0.0";
 
+/// Environment variable set to the worker id in the sandboxes, to find the sandboxes to kill when
+/// a build is cancelled.
+const SANDBOX_WORKER_ENV: &str = "DOCSRS_BUILD_WORKER";
+
+/// Prefix of the line printed by `PEAK_MEMORY_SCRIPT` once cargo exits.
+const PEAK_MEMORY_MARKER: &str = "docsrs-peak-memory-bytes:";
+
+/// Runs cargo with the arguments of the script, and then prints the peak memory usage of the
+/// sandbox, read from its cgroup (v2 first, then v1). The exit status of cargo is preserved.
+const PEAK_MEMORY_SCRIPT: &str = r#""$CARGO_HOME/bin/cargo" "$@"
+status=$?
+for file in /sys/fs/cgroup/memory.peak /sys/fs/cgroup/memory/memory.max_usage_in_bytes; do
+    if [ -r "$file" ]; then
+        echo "docsrs-peak-memory-bytes: $(cat "$file")"
+        break
+    fi
+done
+exit $status"#;
+
 pub struct RustwideBuilder {
     workspace: Workspace,
     toolchain: Toolchain,
+    config: Arc<Config>,
     db: Pool,
     storage: Arc<Storage>,
     rustc_version: String,
@@ -75,7 +235,7 @@ pub struct RustwideBuilder {
 }
 
 impl RustwideBuilder {
-    pub fn init(db: Pool, storage: Arc<Storage>) -> Result<Self> {
+    pub fn init(config: Arc<Config>, db: Pool, storage: Arc<Storage>) -> Result<Self> {
         use rustwide::cmd::SandboxImage;
         let env_workspace_path = ::std::env::var("CRATESFYI_RUSTWIDE_WORKSPACE");
         let workspace_path = env_workspace_path
@@ -109,6 +269,7 @@ impl RustwideBuilder {
         Ok(RustwideBuilder {
             workspace,
             toolchain,
+            config,
             db,
             storage,
             rustc_version: String::new(),
@@ -226,17 +387,7 @@ impl RustwideBuilder {
                     .prefix("essential-files")
                     .tempdir()?;
 
-                let files = ESSENTIAL_FILES_VERSIONED
-                    .iter()
-                    .map(|f| (f, true))
-                    .chain(ESSENTIAL_FILES_UNVERSIONED.iter().map(|f| (f, false)));
-                for (&file, versioned) in files {
-                    let segments = file.rsplitn(2, '.').collect::<Vec<_>>();
-                    let file_name = if versioned {
-                        format!("{}-{}.{}", segments[1], rustc_version, segments[0])
-                    } else {
-                        file.to_string()
-                    };
+                for file_name in essential_files(&rustc_version) {
                     let source_path = source.join(&file_name);
                     let dest_path = dest.path().join(&file_name);
                     ::std::fs::copy(&source_path, &dest_path).with_context(|_| {
@@ -302,6 +453,31 @@ impl RustwideBuilder {
         name: &str,
         version: &str,
         local: Option<&Path>,
+    ) -> Result<bool> {
+        match self.build_package_cancellable(
+            doc_builder,
+            name,
+            version,
+            local,
+            &Cancellation::default(),
+        ) {
+            Err(err) if err.downcast_ref::<Context<BuildFailed>>().is_some() => Ok(false),
+            res => res,
+        }
+    }
+
+    /// Like `build_package`, killing the sandbox and failing with `ErrorCategory::Cancelled` if
+    /// the build is cancelled. Nothing is stored about a cancelled build.
+    ///
+    /// The failed build of a library is stored, and then returned as an error with the
+    /// `BuildFailed` context, categorized with the reason of the failure.
+    pub(crate) fn build_package_cancellable(
+        &mut self,
+        doc_builder: &mut DocBuilder,
+        name: &str,
+        version: &str,
+        local: Option<&Path>,
+        cancellation: &Cancellation,
     ) -> Result<bool> {
         if !doc_builder.should_build(name, version) {
             return Ok(false);
@@ -319,6 +495,7 @@ impl RustwideBuilder {
         }
 
         let limits = Limits::for_crate(&conn, name)?;
+        let start = Instant::now();
 
         let mut build_dir = self.workspace.build_dir(&format!("{}-{}", name, version));
         build_dir.purge()?;
@@ -328,10 +505,19 @@ impl RustwideBuilder {
         } else {
             Crate::crates_io(name, version)
         };
-        krate.fetch(&self.workspace)?;
+        krate.fetch(&self.workspace).context(ErrorCategory::Fetch)?;
 
         let local_storage = tempfile::Builder::new().prefix("docsrs-docs").tempdir()?;
 
+        // The sandboxes are killed again until the build notices the cancellation, in case a new
+        // one was started since the previous call.
+        let worker_id = self.config.build_worker_id.clone();
+        cancellation.on_cancel(move || {
+            if let Err(e) = kill_sandboxes(&worker_id) {
+                error!("failed to kill the sandbox of the cancelled build: {}", e);
+            }
+        });
+
         let res = build_dir
             .build(&self.toolchain, &krate, self.prepare_sandbox(&limits))
             .run(|build| {
@@ -339,6 +525,7 @@ impl RustwideBuilder {
 
                 let mut files_list = None;
                 let mut has_docs = false;
+                let mut doc_coverage = None;
                 let mut algs = CompressionAlgorithms::default();
                 let mut successful_targets = Vec::new();
                 let metadata = Metadata::from_source_dir(&build.host_source_dir())?;
@@ -348,7 +535,10 @@ impl RustwideBuilder {
                 } = metadata.targets();
 
                 // Do an initial build and then copy the sources in the database
-                let res = self.execute_build(default_target, true, &build, &limits, &metadata)?;
+                cancellation.check()?;
+                let res = self.execute_build(default_target, true, build, &limits, &metadata);
+                cancellation.check()?;
+                let mut res = res?;
                 if res.result.successful {
                     debug!("adding sources into database");
                     let prefix = format!("sources/{}/{}", name, version);
@@ -369,29 +559,70 @@ impl RustwideBuilder {
 
                     successful_targets.push(res.target.clone());
 
+                    // The coverage is informative, failing to get it doesn't fail the build.
+                    cancellation.check()?;
+                    doc_coverage = self
+                        .get_coverage(default_target, build, &limits, &metadata)
+                        .unwrap_or_else(|err| {
+                            warn!(
+                                "failed to get the coverage of {} {}: {}",
+                                name, version, err
+                            );
+                            None
+                        });
+
                     // Then build the documentation for all the targets
                     // Limit the number of targets so that no one can try to build all 200000 possible targets
                     for target in other_targets.into_iter().take(limits.targets()) {
                         debug!("building package {} {} for {}", name, version, target);
-                        self.build_target(
+                        cancellation.check()?;
+                        let target_res = self.build_target(
                             target,
-                            &build,
+                            build,
                             &limits,
-                            &local_storage.path(),
+                            local_storage.path(),
                             &mut successful_targets,
                             &metadata,
-                        )?;
+                        );
+                        cancellation.check()?;
+                        let target_res = target_res?;
+                        res.result.doc_warnings.extend(target_res.doc_warnings);
+                        res.result.omitted_doc_warnings += target_res.omitted_doc_warnings;
+                        res.result.resources.targets_attempted += 1;
+                        res.result.resources.peak_memory = res
+                            .result
+                            .resources
+                            .peak_memory
+                            .max(target_res.resources.peak_memory);
                     }
-                    let new_algs = self.upload_docs(name, version, local_storage.path())?;
+                    let (new_algs, mut docs_size) =
+                        self.upload_docs(name, version, local_storage.path())?;
                     algs.extend(new_algs);
+
+                    let json_dir = build.host_target_dir().join(RUSTDOC_JSON_DIR);
+                    if json_dir.is_dir() {
+                        debug!("adding the rustdoc JSON output to the database");
+                        let prefix = format!("json/{}/{}", name, version);
+                        let (_, new_algs, json_size) =
+                            self.storage.store_all(&prefix, &json_dir)?;
+                        algs.extend(new_algs);
+                        docs_size += json_size;
+                    }
+                    res.result.resources.docs_size = Some(docs_size);
                 };
+                cancellation.check()?;
+
+                let resources = &mut res.result.resources;
+                resources.duration = Some(start.elapsed());
+                resources.limits = Some(limits.clone());
+                resources.targets_succeeded = successful_targets.len() as i32;
+                resources.record_metrics();
 
                 let has_examples = build.host_source_dir().join("examples").is_dir();
+                // Failed builds of libraries are counted by the build queue once it gives up on them
                 if res.result.successful {
                     crate::web::metrics::SUCCESSFUL_BUILDS.inc();
-                } else if res.cargo_metadata.root().is_library() {
-                    crate::web::metrics::FAILED_BUILDS.inc();
-                } else {
+                } else if !res.cargo_metadata.root().is_library() {
                     crate::web::metrics::NON_LIBRARY_BUILDS.inc();
                 }
                 let release_id = add_package_into_database(
@@ -406,19 +637,109 @@ impl RustwideBuilder {
                     has_docs,
                     has_examples,
                     algs,
+                    self.config.archive_storage,
                 )?;
                 add_build_into_database(&conn, release_id, &res.result)?;
+                if let Some(coverage) = &doc_coverage {
+                    add_doc_coverage(&conn, release_id, coverage)?;
+                }
 
                 doc_builder.add_to_cache(name, version);
                 Ok(res)
-            })?;
+            });
 
+        // Failed and cancelled builds are cleaned up too
         build_dir.purge()?;
         krate.purge_from_cache(&self.workspace)?;
         local_storage.close()?;
-        Ok(res.result.successful)
+
+        let res = res?;
+        match res.error {
+            Some(err) if res.cargo_metadata.root().is_library() => {
+                Err(err.context(BuildFailed).into())
+            }
+            _ => Ok(res.result.successful),
+        }
+    }
+
+    /// Returns how the documentation of a crate is built for `target`, or for its default target
+    /// if `None`, exactly like `build_package` would.
+    ///
+    /// With `run`, the build is also run outside of the sandbox, printing its output, and the
+    /// build directory is kept to inspect the result.
+    pub fn explain_build(
+        &mut self,
+        name: &str,
+        version: &str,
+        target: Option<&str>,
+        run: bool,
+    ) -> Result<BuildInvocation> {
+        self.update_toolchain()?;
+
+        let conn = self.db.get()?;
+        let limits = Limits::for_crate(&conn, name)?;
+        let mut build_dir = self.workspace.build_dir(&format!("{}-{}", name, version));
+        build_dir.purge()?;
+
+        let krate = Crate::crates_io(name, version);
+        krate.fetch(&self.workspace).context(ErrorCategory::Fetch)?;
+
+        let invocation = build_dir
+            .build(&self.toolchain, &krate, self.prepare_sandbox(&limits))
+            .run(|build| {
+                let metadata = Metadata::from_source_dir(&build.host_source_dir())?;
+                let target = target.unwrap_or_else(|| metadata.targets().default_target);
+                let cargo_metadata = CargoMetadata::load(
+                    &self.workspace,
+                    &self.toolchain,
+                    &build.host_source_dir(),
+                )?;
+                let mut invocation =
+                    self.build_invocation(target, &limits, &metadata, &cargo_metadata)?;
+
+                if run {
+                    self.install_target(target)?;
+                    let (program, args) = invocation.command.split_first().expect("no program");
+                    let mut command =
+                        Command::new(&self.workspace, program.as_str()).cd(build.host_source_dir());
+                    for (name, value) in &invocation.env {
+                        command = command.env(name, value);
+                    }
+                    let result = command
+                        .env("CARGO_TARGET_DIR", build.host_target_dir())
+                        .timeout(Some(limits.timeout()))
+                        .no_output_timeout(None)
+                        .args(args)
+                        .process_lines(&mut |line, actions| {
+                            // The peak memory usage of the host isn't relevant
+                            if line.starts_with(PEAK_MEMORY_MARKER) {
+                                actions.remove_line();
+                                return;
+                            }
+                            match parse_cargo_output(line, target) {
+                                CargoOutput::Diagnostic { rendered, .. } => print!("{}", rendered),
+                                CargoOutput::Message => {}
+                                CargoOutput::Text => println!("{}", line),
+                            }
+                            actions.remove_line();
+                        })
+                        .run()
+                        .map_err(|err| ErrorCategory::of(&categorize_build_error(err)).to_string());
+                    invocation.run_result = Some(result);
+                    invocation.target_dir = Some(build.host_target_dir());
+                }
+
+                Ok(invocation)
+            })?;
+
+        if !run {
+            build_dir.purge()?;
+        }
+        krate.purge_from_cache(&self.workspace)?;
+        Ok(invocation)
     }
 
+    /// Builds the documentation for another target.
     fn build_target(
         &self,
         target: &str,
@@ -427,7 +748,7 @@ impl RustwideBuilder {
         local_storage: &Path,
         successful_targets: &mut Vec<String>,
         metadata: &Metadata,
-    ) -> Result<()> {
+    ) -> Result<BuildResult> {
         let target_res = self.execute_build(target, false, build, limits, metadata)?;
         if target_res.result.successful {
             // Cargo is not giving any error and not generating documentation of some crates
@@ -439,7 +760,7 @@ impl RustwideBuilder {
                 successful_targets.push(target.to_string());
             }
         }
-        Ok(())
+        Ok(target_res.result)
     }
 
     fn execute_build(
@@ -450,85 +771,69 @@ impl RustwideBuilder {
         limits: &Limits,
         metadata: &Metadata,
     ) -> Result<FullBuildResult> {
+        let metadata = &metadata.for_target(target);
         let cargo_metadata =
             CargoMetadata::load(&self.workspace, &self.toolchain, &build.host_source_dir())?;
 
-        let mut rustdoc_flags: Vec<String> = vec![
-            "-Z".to_string(),
-            "unstable-options".to_string(),
-            "--resource-suffix".to_string(),
-            format!("-{}", parse_rustc_version(&self.rustc_version)?),
-            "--static-root-path".to_string(),
-            "/".to_string(),
-            "--cap-lints".to_string(),
-            "warn".to_string(),
-        ];
-        for dep in &cargo_metadata.root_dependencies() {
-            rustdoc_flags.push("--extern-html-root-url".to_string());
-            rustdoc_flags.push(format!(
-                "{}=https://docs.rs/{}/{}",
-                dep.name.replace("-", "_"),
-                dep.name,
-                dep.version
-            ));
-        }
-        if let Some(package_rustdoc_args) = &metadata.rustdoc_args {
-            rustdoc_flags.append(&mut package_rustdoc_args.iter().map(|s| s.to_owned()).collect());
-        }
-        let mut cargo_args = vec!["doc", "--lib", "--no-deps"];
-        if target != HOST_TARGET {
-            // If the explicit target is not a tier one target, we need to install it.
-            if !TARGETS.contains(&target) {
-                // This is a no-op if the target is already installed.
-                self.toolchain.add_target(&self.workspace, target)?;
-            }
-            cargo_args.push("--target");
-            cargo_args.push(target);
-        };
-
-        let tmp_jobs;
-        if let Some(cpu_limit) = self.cpu_limit {
-            tmp_jobs = format!("-j{}", cpu_limit);
-            cargo_args.push(&tmp_jobs);
-        }
-
-        let tmp;
-        if let Some(features) = &metadata.features {
-            cargo_args.push("--features");
-            tmp = features.join(" ");
-            cargo_args.push(&tmp);
-        }
-        if metadata.all_features {
-            cargo_args.push("--all-features");
-        }
-        if metadata.no_default_features {
-            cargo_args.push("--no-default-features");
-        }
+        self.install_target(target)?;
+        let invocation = self.build_invocation(target, limits, metadata, &cargo_metadata)?;
 
         let mut storage = LogStorage::new(LevelFilter::Info);
         storage.set_max_size(limits.max_log_size());
 
-        let successful = logging::capture(&storage, || {
-            build
-                .cargo()
+        let mut doc_warnings = Vec::new();
+        let mut omitted_doc_warnings = 0;
+        let mut peak_memory = None;
+        let build_error = logging::capture(&storage, || {
+            let (program, args) = invocation.command.split_first().expect("no program");
+            let mut command = build.cmd(program.as_str());
+            for (name, value) in &invocation.env {
+                command = command.env(name, value);
+            }
+            command
                 .timeout(Some(limits.timeout()))
                 .no_output_timeout(None)
-                .env(
-                    "RUSTFLAGS",
-                    metadata
-                        .rustc_args
-                        .as_ref()
-                        .map(|args| args.join(" "))
-                        .unwrap_or_default(),
-                )
-                .env("RUSTDOCFLAGS", rustdoc_flags.join(" "))
-                // For docs.rs detection from build script:
-                // https://github.com/rust-lang/docs.rs/issues/147
-                .env("DOCS_RS", "1")
-                .args(&cargo_args)
+                .args(args)
+                .process_lines(&mut |line, actions| {
+                    if let Some(bytes) = line.strip_prefix(PEAK_MEMORY_MARKER) {
+                        peak_memory = bytes.trim().parse().ok();
+                        actions.remove_line();
+                        return;
+                    }
+                    match parse_cargo_output(line, target) {
+                        CargoOutput::Diagnostic { warning, rendered } => {
+                            if let Some(warning) = warning {
+                                if doc_warnings.len() < MAX_DOC_WARNINGS_PER_TARGET {
+                                    doc_warnings.push(warning);
+                                } else {
+                                    omitted_doc_warnings += 1;
+                                }
+                            }
+                            actions.replace_with_lines(rendered.lines());
+                        }
+                        CargoOutput::Message => actions.remove_line(),
+                        CargoOutput::Text => {}
+                    }
+                })
                 .run()
-                .is_ok()
+                .err()
+                .map(categorize_build_error)
         });
+        let successful = build_error.is_none();
+        if successful && (metadata.rustdoc_json || limits.rustdoc_json()) {
+            if let Some(library_name) = cargo_metadata.root().library_name() {
+                // The JSON output is optional, failing to build it doesn't fail the build.
+                if let Err(err) =
+                    self.build_rustdoc_json(target, build, limits, metadata, &library_name)
+                {
+                    warn!(
+                        "failed to build the rustdoc JSON output for {}: {}",
+                        target, err
+                    );
+                }
+            }
+        }
+
         // If we're passed a default_target which requires a cross-compile,
         // cargo will put the output in `target/<target>/doc`.
         // However, if this is the default build, we don't want it there,
@@ -548,12 +853,170 @@ impl RustwideBuilder {
                 rustc_version: self.rustc_version.clone(),
                 docsrs_version: format!("docsrs {}", crate::BUILD_VERSION),
                 successful,
+                doc_warnings,
+                omitted_doc_warnings,
+                resources: BuildResources {
+                    peak_memory,
+                    targets_attempted: 1,
+                    ..BuildResources::default()
+                },
             },
+            error: build_error,
             cargo_metadata,
             target: target.to_string(),
         })
     }
 
+    /// Runs rustdoc's `--show-coverage` for the target, returning the number of documented items
+    /// of each file of the crate.
+    fn get_coverage(
+        &self,
+        target: &str,
+        build: &Build,
+        limits: &Limits,
+        metadata: &Metadata,
+    ) -> Result<Option<Vec<FileCoverage>>> {
+        let mut coverage = None;
+        self.cargo_rustdoc(
+            build,
+            target,
+            limits,
+            metadata,
+            &["--show-coverage", "--output-format", "json"],
+        )
+        .process_lines(&mut |line, _| {
+            if line.starts_with('{') {
+                coverage = parse_coverage(line).ok();
+            }
+        })
+        .log_output(false)
+        .run()?;
+
+        Ok(coverage)
+    }
+
+    /// Builds the documentation of the target in rustdoc's JSON format, and moves it to
+    /// `RUSTDOC_JSON_DIR/<target>/<library name>.json` in the target directory of the build.
+    fn build_rustdoc_json(
+        &self,
+        target: &str,
+        build: &Build,
+        limits: &Limits,
+        metadata: &Metadata,
+        library_name: &str,
+    ) -> Result<()> {
+        self.cargo_rustdoc(
+            build,
+            target,
+            limits,
+            metadata,
+            &["--output-format", "json"],
+        )
+        .run()?;
+
+        let target_dir = build.host_target_dir();
+        let file_name = format!("{}.json", library_name);
+        let source = if target == HOST_TARGET {
+            target_dir.join("doc")
+        } else {
+            target_dir.join(target).join("doc")
+        }
+        .join(&file_name);
+        let dest = target_dir.join(RUSTDOC_JSON_DIR).join(target);
+        std::fs::create_dir_all(&dest)?;
+        std::fs::rename(source, dest.join(file_name))?;
+        Ok(())
+    }
+
+    /// If the explicit target is not a tier one target, we need to install it.
+    fn install_target(&self, target: &str) -> Result<()> {
+        if target != HOST_TARGET && !TARGETS.contains(&target) {
+            // This is a no-op if the target is already installed.
+            self.toolchain.add_target(&self.workspace, target)?;
+        }
+        Ok(())
+    }
+
+    /// Assembles the arguments and the environment of cargo to build the documentation for
+    /// `target`, with the settings of the crate's metadata for it.
+    fn build_invocation(
+        &self,
+        target: &str,
+        limits: &Limits,
+        metadata: &Metadata,
+        cargo_metadata: &CargoMetadata,
+    ) -> Result<BuildInvocation> {
+        let metadata = &metadata.for_target(target);
+        let rustdoc_flags = rustdoc_flags(
+            metadata,
+            &format!("-{}", parse_rustc_version(&self.rustc_version)?),
+            &cargo_metadata.root_dependencies(),
+        );
+
+        let env = cargo_env(
+            metadata,
+            &rustdoc_flags,
+            &self.config.build_worker_id,
+            self.toolchain.as_dist().map(|dist| dist.name()),
+        );
+
+        Ok(BuildInvocation {
+            target: target.to_string(),
+            toolchain: self
+                .toolchain
+                .as_dist()
+                .map(|dist| dist.name())
+                .unwrap_or("nightly")
+                .to_string(),
+            limits: limits.clone(),
+            command: cargo_with_peak_memory(cargo_doc_args(target, metadata, self.cpu_limit)),
+            env,
+            run_result: None,
+            target_dir: None,
+        })
+    }
+
+    /// Prepares `cargo rustdoc` for the library of the crate, passing the arguments from the
+    /// metadata for the target and then `rustdoc_args` to rustdoc.
+    fn cargo_rustdoc<'b>(
+        &self,
+        build: &'b Build,
+        target: &str,
+        limits: &Limits,
+        metadata: &Metadata,
+        rustdoc_args: &[&str],
+    ) -> Command<'b, 'b> {
+        let metadata = &metadata.for_target(target);
+        let mut cargo_args = vec!["rustdoc".to_string(), "--lib".to_string()];
+        cargo_args.extend(cargo_target_args(target, metadata, self.cpu_limit));
+        cargo_args.push("--".to_string());
+        if let Some(package_rustdoc_args) = &metadata.rustdoc_args {
+            cargo_args.extend(package_rustdoc_args.iter().cloned());
+        }
+        cargo_args.push("-Z".to_string());
+        cargo_args.push("unstable-options".to_string());
+        cargo_args.extend(rustdoc_args.iter().map(|arg| arg.to_string()));
+
+        let mut command = build.cargo();
+        for (name, value) in &metadata.env {
+            command = command.env(name, value);
+        }
+        command
+            .timeout(Some(limits.timeout()))
+            .no_output_timeout(None)
+            .env(
+                "RUSTFLAGS",
+                metadata
+                    .rustc_args
+                    .as_ref()
+                    .map(|args| args.join(" "))
+                    .unwrap_or_default(),
+            )
+            .env("DOCS_RS", "1")
+            .env(SANDBOX_WORKER_ENV, &self.config.build_worker_id)
+            .args(&cargo_args)
+    }
+
     fn copy_docs(
         &self,
         target_dir: &Path,
@@ -582,19 +1045,34 @@ impl RustwideBuilder {
         name: &str,
         version: &str,
         local_storage: &Path,
-    ) -> Result<CompressionAlgorithms> {
+    ) -> Result<(CompressionAlgorithms, u64)> {
         debug!("Adding documentation into database");
-        add_path_into_database(
-            &self.storage,
-            &format!("rustdoc/{}/{}", name, version),
-            local_storage,
-        )
-        .map(|t| t.1)
+        if self.config.archive_storage {
+            self.storage
+                .store_all_in_archive(&rustdoc_archive_path(name, version), local_storage)
+                .map(|(_, algs, size)| (algs, size))
+        } else {
+            self.storage
+                .store_all(&format!("rustdoc/{}/{}", name, version), local_storage)
+                .map(|(_, algs, size)| (algs, size))
+        }
+    }
+}
+
+/// Context of the error returned when the documentation of a library failed to build.
+#[derive(Debug)]
+pub(crate) struct BuildFailed;
+
+impl fmt::Display for BuildFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("failed to build the documentation")
     }
 }
 
 struct FullBuildResult {
     result: BuildResult,
+    /// Why the build failed, categorized with `ErrorCategory`
+    error: Option<failure::Error>,
     target: String,
     cargo_metadata: CargoMetadata,
 }
@@ -604,4 +1082,86 @@ pub(crate) struct BuildResult {
     pub(crate) docsrs_version: String,
     pub(crate) build_log: String,
     pub(crate) successful: bool,
+    /// Diagnostics emitted while building the documentation of all the targets
+    pub(crate) doc_warnings: Vec<DocWarning>,
+    /// Number of diagnostics which weren't stored, over `MAX_DOC_WARNINGS_PER_TARGET`
+    pub(crate) omitted_doc_warnings: i32,
+    pub(crate) resources: BuildResources,
+}
+
+/// Resources used by a build, recorded to tune the limits of the crates.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BuildResources {
+    /// Wall-clock time of the whole build, from fetching the crate to uploading its documentation
+    pub(crate) duration: Option<Duration>,
+    pub(crate) limits: Option<Limits>,
+    /// Highest memory usage of the sandbox among the targets, in bytes
+    pub(crate) peak_memory: Option<u64>,
+    pub(crate) targets_attempted: i32,
+    pub(crate) targets_succeeded: i32,
+    /// Total size of the uploaded documentation and rustdoc JSON output, in bytes, as stored
+    /// (compressed) by the storage backend
+    pub(crate) docs_size: Option<u64>,
+}
+
+impl BuildResources {
+    fn record_metrics(&self) {
+        use crate::web::metrics;
+
+        if let Some(duration) = self.duration {
+            metrics::BUILD_DURATION.observe(duration.as_secs_f64());
+        }
+        if let Some(peak_memory) = self.peak_memory {
+            metrics::BUILD_PEAK_MEMORY.observe(peak_memory as f64);
+        }
+        if let Some(docs_size) = self.docs_size {
+            metrics::BUILD_DOCS_SIZE.observe(docs_size as f64);
+        }
+        metrics::BUILD_TARGETS.observe(f64::from(self.targets_succeeded));
+    }
+}
+
+/// Categorizes the error of a cargo invocation building the documentation: running out of the
+/// resource limits is recognized from the error itself, anything else is a failure of rustdoc.
+fn categorize_build_error(err: failure::Error) -> failure::Error {
+    match ErrorCategory::of(&err) {
+        ErrorCategory::SandboxOom | ErrorCategory::Timeout => err,
+        _ => err.context(ErrorCategory::Rustdoc).into(),
+    }
+}
+
+/// Kills the running sandboxes started by the worker, found with `SANDBOX_WORKER_ENV`.
+fn kill_sandboxes(worker_id: &str) -> Result<()> {
+    let expected_env = format!("{}={}", SANDBOX_WORKER_ENV, worker_id);
+
+    let running = docker(&["ps", "--quiet", "--no-trunc"])?;
+    let mut args = vec!["inspect", "--format", "{{.Id}} {{json .Config.Env}}"];
+    args.extend(running.lines());
+    if args.len() == 3 {
+        return Ok(());
+    }
+
+    for line in docker(&args)?.lines() {
+        let mut parts = line.splitn(2, ' ');
+        if let (Some(id), Some(env)) = (parts.next(), parts.next()) {
+            let env: Vec<String> = serde_json::from_str(env)?;
+            if env.contains(&expected_env) {
+                info!("killing the sandbox {}", id);
+                docker(&["kill", id])?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn docker(args: &[&str]) -> Result<String> {
+    let output = std::process::Command::new("docker").args(args).output()?;
+    if !output.status.success() {
+        failure::bail!(
+            "docker {} failed: {}",
+            args[0],
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8(output.stdout)?)
 }
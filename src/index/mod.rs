@@ -16,10 +16,12 @@ pub(crate) struct Index {
 #[derive(serde::Deserialize, Clone)]
 #[serde(rename_all = "kebab-case")]
 struct IndexConfig {
+    #[allow(dead_code)]
     dl: String,
     #[serde(default)]
     api: Option<Url>,
     #[serde(default)]
+    #[allow(dead_code)]
     allowed_registries: Vec<String>,
 }
 
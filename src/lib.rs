@@ -2,8 +2,9 @@
 //! documentation of crates for the Rust Programming Language.
 #![allow(clippy::cognitive_complexity)]
 
-pub use self::build_queue::BuildQueue;
+pub use self::build_queue::{BuildQueue, CancelResult, ErrorCategory, QueueFilter};
 pub use self::config::Config;
+pub use self::docbuilder::check_metadata;
 pub use self::docbuilder::options::DocBuilderOptions;
 pub use self::docbuilder::DocBuilder;
 pub use self::docbuilder::RustwideBuilder;
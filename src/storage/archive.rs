@@ -0,0 +1,182 @@
+//! Archives storing all the documentation files of a release in a single blob
+//!
+//! The files are packed in a zip archive, next to which an index of the position of each file in
+//! the archive is stored. Serving a file then only requires fetching the index and the bytes of
+//! that file, instead of the whole archive. The most recently used indexes are kept in memory.
+
+use super::{detect_mime, get_file_list};
+use crate::utils::LruCache;
+use chrono::{DateTime, Utc};
+use failure::{Error, Fail};
+use path_slash::PathExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+#[derive(Debug, Fail)]
+#[fail(display = "the path is not present in the archive")]
+pub(super) struct PathNotInArchiveError;
+
+#[derive(Debug, Fail)]
+#[fail(display = "the content of the file doesn't match its checksum")]
+struct ChecksumMismatchError;
+
+/// Number of archive indexes kept in memory by `ArchiveIndexCache`.
+pub(super) const CACHED_INDEXES: usize = 50;
+
+/// Returns the path of the index of the archive stored at `archive_path`.
+pub(super) fn index_path(archive_path: &str) -> String {
+    format!("{}.index", archive_path)
+}
+
+/// Position of the deflated content of a file in an archive.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct ArchiveEntry {
+    pub(super) mime: String,
+    pub(super) start: u64,
+    pub(super) end: u64,
+    /// Size of the content once decompressed
+    pub(super) size: u64,
+    /// CRC-32 of the content once decompressed, missing from the indexes of older archives
+    #[serde(default)]
+    pub(super) crc32: Option<u32>,
+}
+
+impl ArchiveEntry {
+    pub(super) fn range(&self) -> Range<u64> {
+        self.start..self.end
+    }
+
+    /// Decompresses the content of the file, read from the archive at `self.range()`, and checks
+    /// it wasn't read from another version of the archive.
+    pub(super) fn decompress(&self, content: &[u8], max_size: usize) -> Result<Vec<u8>, Error> {
+        let mut buffer = crate::utils::sized_buffer::SizedBuffer::new(max_size);
+        buffer.reserve(self.size as usize);
+        io::copy(&mut flate2::read::DeflateDecoder::new(content), &mut buffer)?;
+        let content = buffer.into_inner();
+
+        if let Some(expected) = self.crc32 {
+            let mut crc = flate2::Crc::new();
+            crc.update(&content);
+            if crc.sum() != expected {
+                return Err(ChecksumMismatchError.into());
+            }
+        }
+        Ok(content)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct ArchiveIndex {
+    pub(super) date_updated: DateTime<Utc>,
+    pub(super) files: HashMap<String, ArchiveEntry>,
+}
+
+impl ArchiveIndex {
+    pub(super) fn find(&self, path: &str) -> Result<&ArchiveEntry, Error> {
+        self.files
+            .get(path)
+            .ok_or_else(|| PathNotInArchiveError.into())
+    }
+}
+
+/// The most recently used archive indexes, so that serving a file doesn't require fetching and
+/// parsing the whole index of its archive again.
+///
+/// A cached index becomes stale when its archive is replaced by another process, which is
+/// detected by the checksums of the entries or by missing files: see `Storage::get_from_archive`.
+pub(super) type ArchiveIndexCache = LruCache<String, Arc<ArchiveIndex>>;
+
+/// Packs all the files in `root_dir` in a zip archive written to a temporary file, returning the
+/// file and the index of the archive.
+pub(super) fn create(root_dir: &Path) -> Result<(fs::File, ArchiveIndex), Error> {
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut zip = ZipWriter::new(tempfile::tempfile()?);
+    for file_path in get_file_list(root_dir)? {
+        // Some files have insufficient permissions (like .lock file created by cargo in
+        // documentation directory). Skip these files.
+        let mut file = match fs::File::open(root_dir.join(&file_path)) {
+            Ok(file) => file,
+            Err(_) => continue,
+        };
+        zip.start_file(file_path.to_slash().unwrap(), options)?;
+        io::copy(&mut file, &mut zip)?;
+    }
+    let mut content = zip.finish()?;
+
+    // The positions of the files are only known once the archive is written.
+    let mut archive = ZipArchive::new(&content)?;
+    let mut files = HashMap::with_capacity(archive.len());
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+        files.insert(
+            file.name().to_string(),
+            ArchiveEntry {
+                mime: detect_mime(Path::new(file.name()))?.into(),
+                start: file.data_start(),
+                end: file.data_start() + file.compressed_size(),
+                size: file.size(),
+                crc32: Some(file.crc32()),
+            },
+        );
+    }
+    drop(archive);
+    content.seek(SeekFrom::Start(0))?;
+
+    Ok((
+        content,
+        ArchiveIndex {
+            date_updated: Utc::now(),
+            files,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create() -> Result<(), Error> {
+        let dir = tempfile::tempdir()?;
+        fs::create_dir_all(dir.path().join("foo/struct"))?;
+        fs::write(dir.path().join("foo/index.html"), "<html>foo</html>")?;
+        fs::write(dir.path().join("foo/struct/Bar.html"), "<html>Bar</html>")?;
+        fs::write(dir.path().join("search-index.js"), "var searchIndex = {};")?;
+
+        let (mut file, index) = create(dir.path())?;
+        let mut content = Vec::new();
+        io::Read::read_to_end(&mut file, &mut content)?;
+        assert_eq!(index.files.len(), 3);
+
+        let entry = index.find("foo/struct/Bar.html")?;
+        assert_eq!(entry.mime, "text/html");
+        let range = entry.start as usize..entry.end as usize;
+        assert_eq!(
+            entry.decompress(&content[range], usize::MAX)?,
+            b"<html>Bar</html>"
+        );
+
+        let entry = index.find("search-index.js")?;
+        assert_eq!(entry.mime, "application/javascript");
+        let range = entry.start as usize..entry.end as usize;
+        assert!(entry.decompress(&content[range], 4).is_err());
+
+        assert!(index.find("foo/struct/Baz.html").is_err());
+
+        // Reading the content of another file, like after the archive was replaced, is detected
+        let stale = ArchiveEntry {
+            crc32: index.find("foo/index.html")?.crc32,
+            ..index.find("foo/struct/Bar.html")?.clone()
+        };
+        let range = stale.start as usize..stale.end as usize;
+        assert!(stale.decompress(&content[range], usize::MAX).is_err());
+
+        Ok(())
+    }
+}
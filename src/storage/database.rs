@@ -1,8 +1,16 @@
-use super::{Blob, StorageTransaction};
-use crate::db::Pool;
+use super::{Blob, StorageBackend, StorageTransaction};
+use crate::db::{Pool, PoolConnection};
 use chrono::{DateTime, NaiveDateTime, Utc};
-use failure::{Error, Fail};
-use postgres::transaction::Transaction;
+use failure::{bail, Error, Fail};
+use log::error;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::ops::Range;
+
+/// The maximum size of a BYTEA, the type used for `content`.
+const MAX_CONTENT_SIZE: u64 = 1 << 30;
 
 #[derive(Debug, Fail)]
 #[fail(display = "the path is not present in the database")]
@@ -16,13 +24,21 @@ impl DatabaseBackend {
     pub(crate) fn new(pool: Pool) -> Self {
         Self { pool }
     }
+}
+
+impl fmt::Debug for DatabaseBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "database-backed storage")
+    }
+}
 
-    pub(super) fn get(&self, path: &str, max_size: usize) -> Result<Blob, Error> {
+impl StorageBackend for DatabaseBackend {
+    fn get(&self, path: &str, max_size: usize) -> Result<Blob, Error> {
         use std::convert::TryInto;
 
         // The maximum size for a BYTEA (the type used for `content`) is 1GB, so this cast is safe:
         // https://www.postgresql.org/message-id/162867790712200946i7ba8eb92v908ac595c0c35aee%40mail.gmail.com
-        let max_size = max_size.min(std::i32::MAX as usize) as i32;
+        let max_size = max_size.min(i32::MAX as usize) as i32;
 
         // The size limit is checked at the database level, to avoid receiving data altogether if
         // the limit is exceeded.
@@ -63,52 +79,139 @@ impl DatabaseBackend {
         }
     }
 
-    pub(super) fn start_connection(&self) -> Result<DatabaseConnection, Error> {
-        Ok(DatabaseConnection {
-            conn: self.pool.get()?,
-        })
+    fn list_paths(
+        &self,
+        prefix: &str,
+        start_after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>, Error> {
+        let pattern = format!(
+            "{}%",
+            prefix
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        );
+        let rows = self.pool.get()?.query(
+            "SELECT path
+             FROM files
+             WHERE path LIKE $1 AND ($2::TEXT IS NULL OR path > $2)
+             ORDER BY path
+             LIMIT $3;",
+            &[&pattern, &start_after, &(limit as i64)],
+        )?;
+
+        Ok(rows.into_iter().map(|row| row.get(0)).collect())
     }
-}
 
-pub(super) struct DatabaseConnection {
-    conn: crate::db::PoolConnection,
-}
+    fn get_range(&self, path: &str, range: Range<u64>) -> Result<Vec<u8>, Error> {
+        // BYTEA values can't be bigger than 1GB, so valid positions in them fit in an i32.
+        let (start, len) = match (
+            i32::try_from(range.start + 1),
+            i32::try_from(range.end - range.start),
+        ) {
+            (Ok(start), Ok(len)) => (start, len),
+            _ => bail!(
+                "{:?} is out of the bounds of {} in the database",
+                range,
+                path
+            ),
+        };
+        let rows = self.pool.get()?.query(
+            "SELECT SUBSTRING(content FROM $2 FOR $3) FROM files WHERE path = $1;",
+            &[&path, &start, &len],
+        )?;
 
-impl DatabaseConnection {
-    pub(super) fn start_storage_transaction(
-        &self,
-    ) -> Result<DatabaseStorageTransaction<'_>, Error> {
-        Ok(DatabaseStorageTransaction {
-            transaction: self.conn.transaction()?,
-        })
+        match rows.iter().next() {
+            Some(row) => Ok(row.get(0)),
+            None => Err(PathNotFoundError.into()),
+        }
+    }
+
+    fn start_storage_transaction(&self) -> Result<Box<dyn StorageTransaction + '_>, Error> {
+        // The transaction is managed manually, as `postgres::transaction::Transaction` can't
+        // outlive a borrow of the connection.
+        let conn = self.pool.get()?;
+        conn.batch_execute("BEGIN;")?;
+
+        Ok(Box::new(DatabaseStorageTransaction {
+            conn,
+            completed: false,
+        }))
     }
 }
 
-pub(super) struct DatabaseStorageTransaction<'a> {
-    transaction: Transaction<'a>,
+pub(super) struct DatabaseStorageTransaction {
+    conn: PoolConnection,
+    completed: bool,
 }
 
-impl<'a> StorageTransaction for DatabaseStorageTransaction<'a> {
+impl StorageTransaction for DatabaseStorageTransaction {
     fn store_batch(&mut self, batch: &[Blob]) -> Result<(), Error> {
         for blob in batch {
+            check_content_size(&blob.path, blob.content.len() as u64)?;
             let compression = blob.compression.map(|alg| alg as i32);
-            self.transaction.query(
-                "INSERT INTO files (path, mime, content, compression)
-                 VALUES ($1, $2, $3, $4)
+            self.conn.query(
+                "INSERT INTO files (path, mime, content, compression, date_updated)
+                 VALUES ($1, $2, $3, $4, $5)
                  ON CONFLICT (path) DO UPDATE
-                    SET mime = EXCLUDED.mime, content = EXCLUDED.content, compression = EXCLUDED.compression",
-                &[&blob.path, &blob.mime, &blob.content, &compression],
+                    SET mime = EXCLUDED.mime, content = EXCLUDED.content, compression = EXCLUDED.compression,
+                        date_updated = EXCLUDED.date_updated",
+                &[
+                    &blob.path,
+                    &blob.mime,
+                    &blob.content,
+                    &compression,
+                    &blob.date_updated.naive_utc(),
+                ],
             )?;
         }
         Ok(())
     }
 
-    fn complete(self: Box<Self>) -> Result<(), Error> {
-        self.transaction.commit()?;
+    fn store_file(&mut self, path: &str, mime: &str, mut file: fs::File) -> Result<(), Error> {
+        // The file is only read if it can be stored
+        check_content_size(path, file.metadata()?.len())?;
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)?;
+        self.store_batch(&[Blob {
+            path: path.into(),
+            mime: mime.into(),
+            content,
+            compression: None,
+            date_updated: Utc::now(),
+        }])
+    }
+
+    fn complete(mut self: Box<Self>) -> Result<(), Error> {
+        self.conn.batch_execute("COMMIT;")?;
+        self.completed = true;
         Ok(())
     }
 }
 
+fn check_content_size(path: &str, size: u64) -> Result<(), Error> {
+    if size >= MAX_CONTENT_SIZE {
+        bail!(
+            "{} is too large to be stored in the database: {} bytes, the limit is {} bytes",
+            path,
+            size,
+            MAX_CONTENT_SIZE - 1
+        );
+    }
+    Ok(())
+}
+
+impl Drop for DatabaseStorageTransaction {
+    fn drop(&mut self) {
+        if !self.completed {
+            if let Err(err) = self.conn.batch_execute("ROLLBACK;") {
+                error!("failed to roll back a storage transaction: {}", err);
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -142,17 +245,17 @@ mod tests {
                     content: "Hello world!".bytes().collect(),
                     compression: None,
                 },
-                backend.get("dir/foo.txt", std::usize::MAX)?
+                backend.get("dir/foo.txt", usize::MAX)?
             );
 
             // Test that other files are not returned
             assert!(backend
-                .get("dir/bar.txt", std::usize::MAX)
+                .get("dir/bar.txt", usize::MAX)
                 .unwrap_err()
                 .downcast_ref::<PathNotFoundError>()
                 .is_some());
             assert!(backend
-                .get("foo.txt", std::usize::MAX)
+                .get("foo.txt", usize::MAX)
                 .unwrap_err()
                 .downcast_ref::<PathNotFoundError>()
                 .is_some());
@@ -184,8 +287,7 @@ mod tests {
                 compression: None,
             };
 
-            let conn = backend.start_connection()?;
-            let mut transaction = Box::new(conn.start_storage_transaction()?);
+            let mut transaction = backend.start_storage_transaction()?;
             transaction.store_batch(std::slice::from_ref(&small_blob))?;
             transaction.store_batch(std::slice::from_ref(&big_blob))?;
             transaction.complete()?;
@@ -204,4 +306,27 @@ mod tests {
             Ok(())
         });
     }
+
+    #[test]
+    fn test_too_large_for_database() {
+        crate::test::wrapper(|env| {
+            let backend = DatabaseBackend::new(env.db().pool());
+
+            // The file is sparse, nothing is actually written on disk
+            let file = tempfile::tempfile()?;
+            file.set_len(MAX_CONTENT_SIZE)?;
+            let mut transaction = backend.start_storage_transaction()?;
+            let err = transaction
+                .store_file("huge.zip", "application/zip", file)
+                .unwrap_err();
+            assert!(err.to_string().contains("too large to be stored"));
+            drop(transaction);
+
+            assert!(backend
+                .get_range("huge.zip", MAX_CONTENT_SIZE * 2..MAX_CONTENT_SIZE * 2 + 1)
+                .is_err());
+
+            Ok(())
+        });
+    }
 }
@@ -0,0 +1,453 @@
+use super::{Blob, CompressionAlgorithm, StorageBackend, StorageTransaction};
+use chrono::{DateTime, Utc};
+use failure::{bail, Error, Fail, ResultExt};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Debug, Fail)]
+#[fail(display = "the path is not present in the local storage")]
+struct PathNotFoundError;
+
+/// Metadata of a blob, stored in a JSON file next to its content.
+#[derive(Debug, Serialize, Deserialize)]
+struct BlobMetadata {
+    mime: String,
+    compression: Option<String>,
+    date_updated: DateTime<Utc>,
+}
+
+/// Stores blobs as files in a local directory.
+///
+/// The content of the blob at `path` is stored in `{root}/content/{path}.blob`, and its metadata
+/// in `{root}/metadata/{path}.json`. The suffixes allow storing both `a/b` and `a/b/c`, as the
+/// file of a blob never has the name of a directory. Both are written to a temporary file first
+/// and then renamed, so readers never see a partially written blob.
+#[derive(Debug)]
+pub struct LocalFsBackend {
+    root: PathBuf,
+}
+
+impl LocalFsBackend {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn content_path(&self, path: &str) -> Result<PathBuf, Error> {
+        self.file_path("content", path, ".blob")
+    }
+
+    fn metadata_path(&self, path: &str) -> Result<PathBuf, Error> {
+        self.file_path("metadata", path, ".json")
+    }
+
+    fn file_path(&self, dir: &str, path: &str, suffix: &str) -> Result<PathBuf, Error> {
+        let mut file_path = self.root.join(dir).join(checked_path(path)?);
+        let mut file_name = file_path.file_name().unwrap().to_os_string();
+        file_name.push(suffix);
+        file_path.set_file_name(file_name);
+        Ok(file_path)
+    }
+}
+
+impl StorageBackend for LocalFsBackend {
+    fn get(&self, path: &str, max_size: usize) -> Result<Blob, Error> {
+        let metadata: BlobMetadata = match fs::read(self.metadata_path(path)?) {
+            Ok(metadata) => serde_json::from_slice(&metadata)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(PathNotFoundError.into())
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let file = fs::File::open(self.content_path(path)?)?;
+        if file.metadata()?.len() > max_size as u64 {
+            return Err(
+                io::Error::new(io::ErrorKind::Other, crate::error::SizeLimitReached).into(),
+            );
+        }
+        let mut content = Vec::new();
+        file.take(max_size as u64).read_to_end(&mut content)?;
+
+        let compression = match metadata.compression {
+            Some(alg) => Some(
+                alg.parse::<CompressionAlgorithm>()
+                    .with_context(|_| format!("invalid metadata stored for {}", path))?,
+            ),
+            None => None,
+        };
+
+        Ok(Blob {
+            path: path.into(),
+            mime: metadata.mime,
+            date_updated: metadata.date_updated,
+            content,
+            compression,
+        })
+    }
+
+    fn get_range(&self, path: &str, range: Range<u64>) -> Result<Vec<u8>, Error> {
+        let mut file = match fs::File::open(self.content_path(path)?) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(PathNotFoundError.into())
+            }
+            Err(err) => return Err(err.into()),
+        };
+        file.seek(SeekFrom::Start(range.start))?;
+
+        let mut content = Vec::with_capacity((range.end - range.start) as usize);
+        file.take(range.end - range.start)
+            .read_to_end(&mut content)?;
+        Ok(content)
+    }
+
+    fn start_storage_transaction(&self) -> Result<Box<dyn StorageTransaction + '_>, Error> {
+        Ok(Box::new(LocalFsStorageTransaction { backend: self }))
+    }
+
+    fn list_paths(
+        &self,
+        prefix: &str,
+        start_after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>, Error> {
+        let metadata_root = self.root.join("metadata");
+        // Only walk the directory containing the prefix, instead of the whole storage.
+        let (walk_from, walk_from_path) = match prefix.rfind('/') {
+            Some(idx) => (
+                metadata_root.join(checked_path(&prefix[..idx])?),
+                format!("{}/", &prefix[..idx]),
+            ),
+            None => (metadata_root, String::new()),
+        };
+        if !walk_from.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut paths = Vec::new();
+        list_paths_sorted(
+            &walk_from,
+            &walk_from_path,
+            prefix,
+            start_after,
+            limit,
+            &mut paths,
+        )?;
+        Ok(paths)
+    }
+}
+
+/// Walks the metadata directory `dir`, which holds the blobs whose path starts with `dir_path`,
+/// and appends the matching blob paths to `paths` in lexicographic order until `limit` of them
+/// have been found.
+///
+/// Subdirectories that can't contain any matching path are skipped, so listing a page of paths
+/// only reads the directories up to that page.
+fn list_paths_sorted(
+    dir: &Path,
+    dir_path: &str,
+    prefix: &str,
+    start_after: Option<&str>,
+    limit: usize,
+    paths: &mut Vec<String>,
+) -> Result<(), Error> {
+    // Directories are keyed by their path followed by `/`, so that sorting the entries by key
+    // sorts the blobs inside them by their full path.
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = match entry.file_name().into_string() {
+            Ok(name) => name,
+            Err(_) => continue,
+        };
+        if entry.file_type()?.is_dir() {
+            entries.push((format!("{}{}/", dir_path, name), Some(entry.path())));
+        } else if let Some(name) = name.strip_suffix(".json") {
+            // Temporary files of blobs being written don't end in `.json` and are skipped
+            entries.push((format!("{}{}", dir_path, name), None));
+        }
+    }
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    for (path, subdir) in entries {
+        if paths.len() >= limit {
+            break;
+        }
+        match subdir {
+            Some(subdir) => {
+                let in_prefix = path.starts_with(prefix) || prefix.starts_with(path.as_str());
+                let before_start = start_after.map_or(false, |after| {
+                    path.as_str() < after && !after.starts_with(&path)
+                });
+                if in_prefix && !before_start {
+                    list_paths_sorted(&subdir, &path, prefix, start_after, limit, paths)?;
+                }
+            }
+            None => {
+                if path.starts_with(prefix)
+                    && start_after.map_or(true, |after| path.as_str() > after)
+                {
+                    paths.push(path);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+struct LocalFsStorageTransaction<'a> {
+    backend: &'a LocalFsBackend,
+}
+
+impl LocalFsStorageTransaction<'_> {
+    /// Stores the content of a blob and then its metadata, as blobs without metadata are treated
+    /// as missing.
+    fn store(&self, path: &str, content: impl Read, metadata: &BlobMetadata) -> Result<(), Error> {
+        write_atomically(&self.backend.content_path(path)?, content)
+            .with_context(|_| format!("failed to store {}", path))?;
+        write_atomically(
+            &self.backend.metadata_path(path)?,
+            serde_json::to_vec(metadata)?.as_slice(),
+        )
+        .with_context(|_| format!("failed to store the metadata of {}", path))?;
+        Ok(())
+    }
+}
+
+impl<'a> StorageTransaction for LocalFsStorageTransaction<'a> {
+    fn store_batch(&mut self, batch: &[Blob]) -> Result<(), Error> {
+        for blob in batch {
+            let metadata = BlobMetadata {
+                mime: blob.mime.clone(),
+                compression: blob.compression.map(|alg| alg.to_string()),
+                date_updated: blob.date_updated,
+            };
+            self.store(&blob.path, blob.content.as_slice(), &metadata)?;
+        }
+        Ok(())
+    }
+
+    fn store_file(&mut self, path: &str, mime: &str, file: fs::File) -> Result<(), Error> {
+        let metadata = BlobMetadata {
+            mime: mime.into(),
+            compression: None,
+            date_updated: Utc::now(),
+        };
+        self.store(path, file, &metadata)
+    }
+
+    fn complete(self: Box<Self>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Ensures a blob path can't refer to files outside of the storage directory.
+fn checked_path(path: &str) -> Result<&Path, Error> {
+    let checked = Path::new(path);
+    let valid = checked.file_name().is_some()
+        && checked
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+    if !valid {
+        bail!("invalid storage path: {}", path);
+    }
+    Ok(checked)
+}
+
+fn write_atomically(dest: &Path, mut content: impl Read) -> Result<(), Error> {
+    let parent = dest.parent().expect("storage paths always have a parent");
+    fs::create_dir_all(parent)?;
+
+    let mut file = tempfile::NamedTempFile::new_in(parent)?;
+    io::copy(&mut content, &mut file)?;
+    file.persist(dest)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::test::assert_blob_eq;
+
+    fn blob(path: &str, content: &[u8]) -> Blob {
+        Blob {
+            path: path.into(),
+            mime: "text/plain".into(),
+            date_updated: Utc::now(),
+            content: content.into(),
+            compression: None,
+        }
+    }
+
+    fn store(backend: &LocalFsBackend, blobs: &[Blob]) -> Result<(), Error> {
+        let mut transaction = backend.start_storage_transaction()?;
+        transaction.store_batch(blobs)?;
+        transaction.complete()
+    }
+
+    #[test]
+    fn test_roundtrip() -> Result<(), Error> {
+        let dir = tempfile::tempdir()?;
+        let backend = LocalFsBackend::new(dir.path());
+
+        let mut compressed = blob("rustdoc/foo/1.0.0/foo/index.html", b"");
+        compressed.content = super::super::compress(&b"Hello world!"[..], Default::default())?;
+        compressed.compression = Some(CompressionAlgorithm::default());
+        let plain = blob("sources/foo/1.0.0/src/lib.rs", b"fn main() {}");
+        store(&backend, &[compressed.clone(), plain.clone()])?;
+
+        let actual = backend.get(&compressed.path, usize::MAX)?;
+        assert_blob_eq(&compressed, &actual);
+        assert_eq!(actual.compression, compressed.compression);
+        assert_blob_eq(&plain, &backend.get(&plain.path, usize::MAX)?);
+
+        // Storing a blob again replaces it
+        let replaced = blob(&plain.path, b"fn main() { println!() }");
+        store(&backend, std::slice::from_ref(&replaced))?;
+        assert_blob_eq(&replaced, &backend.get(&plain.path, usize::MAX)?);
+
+        assert!(backend
+            .get("sources/foo/1.0.0/src/main.rs", usize::MAX)
+            .unwrap_err()
+            .downcast_ref::<PathNotFoundError>()
+            .is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_paths() -> Result<(), Error> {
+        let dir = tempfile::tempdir()?;
+        let backend = LocalFsBackend::new(dir.path());
+        store(
+            &backend,
+            &[
+                blob("rustdoc/foo/1.0.0/b.html", b""),
+                blob("rustdoc/foo/1.0.0/a.html", b""),
+                blob("rustdoc/foo/1.0.0/c/d.html", b""),
+                blob("rustdoc/foobar/1.0.0/a.html", b""),
+                blob("sources/foo/1.0.0/src/lib.rs", b""),
+            ],
+        )?;
+
+        assert_eq!(
+            backend.list_paths("rustdoc/foo/", None, 10)?,
+            vec![
+                "rustdoc/foo/1.0.0/a.html",
+                "rustdoc/foo/1.0.0/b.html",
+                "rustdoc/foo/1.0.0/c/d.html",
+            ]
+        );
+        assert_eq!(
+            backend.list_paths("rustdoc/foo", Some("rustdoc/foo/1.0.0/a.html"), 2)?,
+            vec!["rustdoc/foo/1.0.0/b.html", "rustdoc/foo/1.0.0/c/d.html"]
+        );
+        assert_eq!(backend.list_paths("", None, 10)?.len(), 5);
+        assert!(backend.list_paths("missing/", None, 10)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_paths_in_pages() -> Result<(), Error> {
+        let dir = tempfile::tempdir()?;
+        let backend = LocalFsBackend::new(dir.path());
+        // `-` sorts before `/`, so `a-b` must be listed before the blobs inside `a/`
+        let expected = vec!["a", "a-b", "a/b", "a/b-c", "a/b/c", "a/c", "b", "c/d/e"];
+        store(
+            &backend,
+            &expected
+                .iter()
+                .rev()
+                .map(|p| blob(p, p.as_bytes()))
+                .collect::<Vec<_>>(),
+        )?;
+        // Blobs can be stored both at a path and under it, like `a/b` and `a/b/c`
+        for path in &expected {
+            assert_eq!(backend.get(path, usize::MAX)?.content, path.as_bytes());
+        }
+
+        for limit in 1..=expected.len() {
+            let mut paths = Vec::new();
+            loop {
+                let page = backend.list_paths("", paths.last().map(String::as_str), limit)?;
+                assert!(page.len() <= limit);
+                if page.is_empty() {
+                    break;
+                }
+                paths.extend(page);
+            }
+            assert_eq!(paths, expected, "limit {}", limit);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_store_batch_keeps_date_updated() -> Result<(), Error> {
+        let dir = tempfile::tempdir()?;
+        let backend = LocalFsBackend::new(dir.path());
+        let date_updated = Utc::now() - chrono::Duration::days(30);
+        store(
+            &backend,
+            &[Blob {
+                date_updated,
+                ..blob("old.html", b"")
+            }],
+        )?;
+
+        assert_eq!(
+            backend.get("old.html", usize::MAX)?.date_updated,
+            date_updated
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_too_big() -> Result<(), Error> {
+        const MAX_SIZE: usize = 1024;
+
+        let dir = tempfile::tempdir()?;
+        let backend = LocalFsBackend::new(dir.path());
+        store(
+            &backend,
+            &[
+                blob("small-blob.bin", &[0; MAX_SIZE]),
+                blob("big-blob.bin", &[0; MAX_SIZE * 2]),
+            ],
+        )?;
+
+        assert_eq!(
+            backend.get("small-blob.bin", MAX_SIZE)?.content.len(),
+            MAX_SIZE
+        );
+        assert!(backend
+            .get("big-blob.bin", MAX_SIZE)
+            .unwrap_err()
+            .downcast_ref::<io::Error>()
+            .and_then(|io| io.get_ref())
+            .and_then(|err| err.downcast_ref::<crate::error::SizeLimitReached>())
+            .is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_paths() -> Result<(), Error> {
+        let dir = tempfile::tempdir()?;
+        let backend = LocalFsBackend::new(dir.path().join("storage"));
+
+        for path in &["../escaped", "foo/../../escaped", "/etc/passwd", ""] {
+            assert!(store(&backend, &[blob(path, b"data")]).is_err(), "{}", path);
+            assert!(backend.get(path, usize::MAX).is_err(), "{}", path);
+        }
+        assert!(!dir.path().join("escaped").exists());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,269 @@
+//! Copying blobs between storage backends
+
+use super::{new_backend, Blob, StorageBackend, StorageKind};
+use crate::{db::Pool, Config};
+use failure::{bail, Error, ResultExt};
+use log::info;
+use postgres::Connection;
+use serde_json::{json, Value};
+
+/// Name of the row of the `config` table storing the progress of the running migration.
+const CHECKPOINT_CONFIG_NAME: &str = "storage_migration_checkpoint";
+
+/// Number of blobs copied in each storage transaction.
+const BATCH_SIZE: usize = 100;
+
+/// Copies the blobs starting with `prefix` from a storage backend to another one.
+///
+/// The blobs are copied as stored, without recompressing them. After each batch the copied blobs
+/// are read back from the destination and compared with the originals, and the last copied path
+/// is stored in the database: running the same migration again resumes from there.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageMigration {
+    pub from: StorageKind,
+    pub to: StorageKind,
+    pub prefix: String,
+}
+
+impl StorageMigration {
+    /// Runs the migration, returning the number of copied blobs. The progress of a previous
+    /// migration is ignored if `restart` is true.
+    pub fn run(&self, pool: Pool, config: &Config, restart: bool) -> Result<usize, Error> {
+        if self.from == self.to {
+            bail!("can't migrate the {} storage to itself", self.from);
+        }
+
+        let conn = pool.get()?;
+        let from = new_backend(self.from, pool.clone(), config)?;
+        let to = new_backend(self.to, pool.clone(), config)?;
+        self.copy(&conn, &*from, &*to, restart)
+    }
+
+    fn copy(
+        &self,
+        conn: &Connection,
+        from: &dyn StorageBackend,
+        to: &dyn StorageBackend,
+        restart: bool,
+    ) -> Result<usize, Error> {
+        let mut last_path = if restart {
+            None
+        } else {
+            self.load_checkpoint(conn)?
+        };
+        if let Some(last_path) = &last_path {
+            info!("resuming the storage migration after {}", last_path);
+        }
+
+        let mut copied = 0;
+        loop {
+            let paths = from.list_paths(&self.prefix, last_path.as_deref(), BATCH_SIZE)?;
+            if paths.is_empty() {
+                break;
+            }
+
+            let blobs = paths
+                .iter()
+                .map(|path| {
+                    from.get(path, usize::MAX)
+                        .with_context(|_| format!("failed to read {}", path))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let mut transaction = to.start_storage_transaction()?;
+            transaction.store_batch(&blobs)?;
+            transaction.complete()?;
+
+            for blob in &blobs {
+                verify(to, blob)?;
+            }
+
+            copied += blobs.len();
+            last_path = paths.last().cloned();
+            self.store_checkpoint(conn, last_path.as_deref().unwrap())?;
+            info!(
+                "copied {} blobs from the {} storage to the {} storage, last one was {}",
+                copied,
+                self.from,
+                self.to,
+                last_path.as_deref().unwrap(),
+            );
+        }
+
+        conn.execute(
+            "DELETE FROM config WHERE name = $1;",
+            &[&CHECKPOINT_CONFIG_NAME],
+        )?;
+
+        Ok(copied)
+    }
+
+    /// Returns the last path copied by this migration. Fails if the checkpoint was stored by a
+    /// different migration, to avoid resuming it from the wrong place.
+    fn load_checkpoint(&self, conn: &Connection) -> Result<Option<String>, Error> {
+        let rows = conn.query(
+            "SELECT value FROM config WHERE name = $1;",
+            &[&CHECKPOINT_CONFIG_NAME],
+        )?;
+        let checkpoint: Value = match rows.iter().next() {
+            Some(row) => row.get(0),
+            None => return Ok(None),
+        };
+
+        if checkpoint["from"] != self.from.to_string()
+            || checkpoint["to"] != self.to.to_string()
+            || checkpoint["prefix"] != self.prefix
+        {
+            bail!(
+                "another storage migration was interrupted ({}), pass --restart to ignore it",
+                checkpoint
+            );
+        }
+
+        Ok(checkpoint["last_path"].as_str().map(String::from))
+    }
+
+    fn store_checkpoint(&self, conn: &Connection, last_path: &str) -> Result<(), Error> {
+        let checkpoint = json!({
+            "from": self.from.to_string(),
+            "to": self.to.to_string(),
+            "prefix": self.prefix,
+            "last_path": last_path,
+        });
+        conn.execute(
+            "INSERT INTO config (name, value) VALUES ($1, $2)
+             ON CONFLICT (name) DO UPDATE SET value = $2;",
+            &[&CHECKPOINT_CONFIG_NAME, &checkpoint],
+        )?;
+
+        Ok(())
+    }
+}
+
+fn verify(backend: &dyn StorageBackend, expected: &Blob) -> Result<(), Error> {
+    let actual = backend
+        .get(&expected.path, usize::MAX)
+        .with_context(|_| format!("failed to read {} back after copying it", expected.path))?;
+
+    if actual.content != expected.content
+        || actual.mime != expected.mime
+        || actual.compression != expected.compression
+    {
+        bail!("{} is different after copying it", expected.path);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{compress, CompressionAlgorithm, DatabaseBackend, LocalFsBackend};
+    use crate::test::wrapper;
+    use chrono::Utc;
+
+    fn store(backend: &dyn StorageBackend, paths: &[&str]) -> Result<(), Error> {
+        let blobs = paths
+            .iter()
+            .map(|path| {
+                Ok(Blob {
+                    path: (*path).into(),
+                    mime: "text/html".into(),
+                    date_updated: Utc::now(),
+                    content: compress(path.as_bytes(), CompressionAlgorithm::Zstd)?,
+                    compression: Some(CompressionAlgorithm::Zstd),
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let mut transaction = backend.start_storage_transaction()?;
+        transaction.store_batch(&blobs)?;
+        transaction.complete()
+    }
+
+    fn migration(prefix: &str) -> StorageMigration {
+        StorageMigration {
+            from: StorageKind::Database,
+            to: StorageKind::LocalFs,
+            prefix: prefix.into(),
+        }
+    }
+
+    #[test]
+    fn test_copy_with_prefix() {
+        wrapper(|env| {
+            let db = env.db();
+            let dir = tempfile::tempdir()?;
+            let from = DatabaseBackend::new(db.pool());
+            let to = LocalFsBackend::new(dir.path());
+
+            let paths = (0..BATCH_SIZE + 10)
+                .map(|i| format!("rustdoc/foo_bar/1.0.0/{}.html", i))
+                .collect::<Vec<_>>();
+            let mut all_paths = paths.iter().map(|p| p.as_str()).collect::<Vec<_>>();
+            // `_` is not a wildcard in prefixes
+            all_paths.push("rustdoc/fooxbar/1.0.0/index.html");
+            all_paths.push("sources/foo_bar/1.0.0/src/lib.rs");
+            store(&from, &all_paths)?;
+
+            let copied = migration("rustdoc/foo_bar/").copy(&db.conn(), &from, &to, false)?;
+            assert_eq!(copied, paths.len());
+
+            for path in &paths {
+                let original = from.get(path, usize::MAX)?;
+                let copy = to.get(path, usize::MAX)?;
+                assert_eq!(original.content, copy.content);
+                assert_eq!(original.compression, copy.compression);
+                assert_eq!(original.mime, copy.mime);
+                assert_eq!(original.date_updated, copy.date_updated);
+            }
+            assert!(to
+                .get("rustdoc/fooxbar/1.0.0/index.html", usize::MAX)
+                .is_err());
+            assert!(to
+                .get("sources/foo_bar/1.0.0/src/lib.rs", usize::MAX)
+                .is_err());
+
+            // The checkpoint is removed once the migration is done
+            assert_eq!(
+                migration("rustdoc/foo_bar/").load_checkpoint(&db.conn())?,
+                None
+            );
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_resume_from_checkpoint() {
+        wrapper(|env| {
+            let db = env.db();
+            let dir = tempfile::tempdir()?;
+            let from = DatabaseBackend::new(db.pool());
+            let to = LocalFsBackend::new(dir.path());
+
+            store(&from, &["a.html", "b.html", "c.html"])?;
+
+            // Simulate a migration interrupted after copying a.html and b.html
+            let migration = migration("");
+            migration.store_checkpoint(&db.conn(), "b.html")?;
+            assert_eq!(migration.copy(&db.conn(), &from, &to, false)?, 1);
+            assert!(to.get("a.html", usize::MAX).is_err());
+            assert!(to.get("c.html", usize::MAX).is_ok());
+
+            // Another migration can't resume from the checkpoint of a different one
+            migration.store_checkpoint(&db.conn(), "b.html")?;
+            let other = StorageMigration {
+                prefix: "rustdoc/".into(),
+                ..migration.clone()
+            };
+            assert!(other.copy(&db.conn(), &from, &to, false).is_err());
+
+            // Unless it's restarted
+            assert_eq!(migration.copy(&db.conn(), &from, &to, true)?, 3);
+            assert!(to.get("a.html", usize::MAX).is_ok());
+
+            Ok(())
+        });
+    }
+}
@@ -1,24 +1,40 @@
+mod archive;
 mod database;
+mod local_fs;
+mod migrate;
 pub(crate) mod s3;
 
 pub(crate) use self::database::DatabaseBackend;
+pub use self::local_fs::LocalFsBackend;
+pub use self::migrate::StorageMigration;
 pub(crate) use self::s3::S3Backend;
-use crate::db::Pool;
+use crate::{db::Pool, Config};
 use chrono::{DateTime, Utc};
-use failure::{err_msg, Error};
+use failure::{bail, err_msg, Error, Fail};
 use path_slash::PathExt;
 use std::{
     collections::{HashMap, HashSet},
     ffi::OsStr,
     fmt, fs,
-    io::Read,
+    io::{self, Read},
+    ops::Range,
     path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
 };
 
 const MAX_CONCURRENT_UPLOADS: usize = 1000;
 
+/// A file missing from a cached archive index is looked up again in the stored index if the cached
+/// one is older than this, in case the archive was replaced since then.
+const MISSING_FILE_RECHECK_DELAY: Duration = Duration::from_secs(60);
+
 pub type CompressionAlgorithms = HashSet<CompressionAlgorithm>;
 
+/// The paths and mime types of the files stored by `Storage::store_all`, the compression
+/// algorithms used, and the number of bytes stored.
+pub(crate) type StoredFiles = (HashMap<PathBuf, String>, CompressionAlgorithms, u64);
+
 macro_rules! enum_id {
     ($vis:vis enum $name:ident { $($variant:ident = $discriminant:expr,)* }) => {
         #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
@@ -40,11 +56,11 @@ macro_rules! enum_id {
         }
 
         impl std::str::FromStr for CompressionAlgorithm {
-            type Err = ();
+            type Err = UnknownCompressionAlgorithm;
             fn from_str(s: &str) -> Result<Self, Self::Err> {
                 match s {
                     $(stringify!($variant) => Ok(Self::$variant),)*
-                    _ => Err(()),
+                    _ => Err(UnknownCompressionAlgorithm(s.into())),
                 }
             }
         }
@@ -64,9 +80,29 @@ macro_rules! enum_id {
 enum_id! {
     pub enum CompressionAlgorithm {
         Zstd = 0,
+        Brotli = 1,
+        Gzip = 2,
     }
 }
 
+impl CompressionAlgorithm {
+    /// The `Content-Encoding` of files compressed with this algorithm, used to send them to the
+    /// clients accepting it without decompressing them first.
+    pub fn content_encoding(self) -> &'static str {
+        match self {
+            CompressionAlgorithm::Zstd => "zstd",
+            CompressionAlgorithm::Brotli => "br",
+            CompressionAlgorithm::Gzip => "gzip",
+        }
+    }
+}
+
+#[derive(Debug, Fail)]
+#[fail(display = "unknown compression algorithm: {}", _0)]
+pub struct UnknownCompressionAlgorithm(String);
+
+// `enum_id!` doesn't support marking a variant with `#[default]`
+#[allow(clippy::derivable_impls)]
 impl Default for CompressionAlgorithm {
     fn default() -> Self {
         CompressionAlgorithm::Zstd
@@ -74,12 +110,12 @@ impl Default for CompressionAlgorithm {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
-pub(crate) struct Blob {
-    pub(crate) path: String,
-    pub(crate) mime: String,
-    pub(crate) date_updated: DateTime<Utc>,
-    pub(crate) content: Vec<u8>,
-    pub(crate) compression: Option<CompressionAlgorithm>,
+pub struct Blob {
+    pub path: String,
+    pub mime: String,
+    pub date_updated: DateTime<Utc>,
+    pub content: Vec<u8>,
+    pub compression: Option<CompressionAlgorithm>,
 }
 
 fn get_file_list_from_dir<P: AsRef<Path>>(path: P, files: &mut Vec<PathBuf>) -> Result<(), Error> {
@@ -117,59 +153,241 @@ pub fn get_file_list<P: AsRef<Path>>(path: P) -> Result<Vec<PathBuf>, Error> {
     Ok(files)
 }
 
-enum StorageBackend {
-    Database(DatabaseBackend),
-    S3(S3Backend),
+/// The storage backends built into docs.rs, selected with `DOCSRS_STORAGE_BACKEND`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, strum::EnumString, strum::Display)]
+#[strum(serialize_all = "kebab_case")]
+pub enum StorageKind {
+    Database,
+    S3,
+    LocalFs,
+}
+
+/// A place where blobs are stored.
+pub trait StorageBackend: fmt::Debug + Send + Sync {
+    /// Returns the blob stored at `path`, or an error if its content is bigger than `max_size`.
+    ///
+    /// The content is returned as stored, and is decompressed by `Storage::get`.
+    fn get(&self, path: &str, max_size: usize) -> Result<Blob, Error>;
+
+    /// Starts storing new blobs, which might not be visible until the transaction is completed.
+    fn start_storage_transaction(&self) -> Result<Box<dyn StorageTransaction + '_>, Error>;
+
+    /// Returns up to `limit` paths starting with `prefix`, in lexicographic order. If
+    /// `start_after` is set, only the paths sorted after it are returned.
+    fn list_paths(
+        &self,
+        prefix: &str,
+        start_after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>, Error>;
+
+    /// Returns the bytes of the blob stored at `path` in `range`, as stored.
+    fn get_range(&self, path: &str, range: Range<u64>) -> Result<Vec<u8>, Error>;
+}
+
+/// Creates one of the storage backends built into docs.rs.
+pub(crate) fn new_backend(
+    kind: StorageKind,
+    pool: Pool,
+    config: &Config,
+) -> Result<Box<dyn StorageBackend>, Error> {
+    Ok(match kind {
+        StorageKind::Database => Box::new(DatabaseBackend::new(pool)),
+        StorageKind::S3 => match s3::s3_client() {
+            Some(client) => Box::new(S3Backend::new(client, s3::S3_BUCKET_NAME)),
+            None => bail!("the S3 storage backend requires AWS credentials"),
+        },
+        StorageKind::LocalFs => match &config.local_storage_path {
+            Some(path) => Box::new(LocalFsBackend::new(path)),
+            None => bail!("the local-fs storage backend requires DOCSRS_LOCAL_STORAGE_PATH"),
+        },
+    })
 }
 
+#[derive(Debug)]
 pub struct Storage {
-    backend: StorageBackend,
+    backend: Box<dyn StorageBackend>,
+    compression: CompressionAlgorithm,
+    archive_indexes: archive::ArchiveIndexCache,
 }
 
 impl Storage {
-    pub fn new(pool: Pool) -> Self {
-        let backend = if let Some(c) = s3::s3_client() {
-            StorageBackend::S3(S3Backend::new(c, s3::S3_BUCKET_NAME))
-        } else {
-            StorageBackend::Database(DatabaseBackend::new(pool))
+    /// Uses the backend configured with `DOCSRS_STORAGE_BACKEND`. If it's not set, S3 is used if
+    /// AWS credentials are present, and the database otherwise.
+    pub fn new(pool: Pool, config: &Config) -> Result<Self, Error> {
+        let kind = match config.storage_backend {
+            Some(kind) => kind,
+            None if s3::s3_client().is_some() => StorageKind::S3,
+            None => StorageKind::Database,
         };
-        Storage { backend }
+
+        let mut storage = Self::with_backend(new_backend(kind, pool, config)?);
+        storage.compression = config.compression_algorithm;
+        Ok(storage)
     }
 
-    pub(crate) fn get(&self, path: &str, max_size: usize) -> Result<Blob, Error> {
-        let mut blob = match &self.backend {
-            StorageBackend::Database(db) => db.get(path, max_size),
-            StorageBackend::S3(s3) => s3.get(path, max_size),
-        }?;
-        if let Some(alg) = blob.compression {
-            blob.content = decompress(blob.content.as_slice(), alg, max_size)?;
-            blob.compression = None;
+    pub fn with_backend(backend: Box<dyn StorageBackend>) -> Self {
+        Storage {
+            backend,
+            compression: CompressionAlgorithm::default(),
+            archive_indexes: archive::ArchiveIndexCache::new(archive::CACHED_INDEXES),
         }
+    }
+
+    pub(crate) fn get(&self, path: &str, max_size: usize) -> Result<Blob, Error> {
+        let mut blob = self.get_raw(path, max_size)?;
+        decompress_blob(&mut blob, max_size)?;
         Ok(blob)
     }
 
-    // Store all files in `root_dir` into the backend under `prefix`.
-    //
-    // If the environment is configured with S3 credentials, this will upload to S3;
-    // otherwise, this will store files in the database.
-    //
-    // This returns (map<filename, mime type>, set<compression algorithms>).
-    pub(crate) fn store_all(
+    /// Returns the blob as stored, without decompressing it. Only the stored content has to fit
+    /// in `max_size`.
+    pub(crate) fn get_raw(&self, path: &str, max_size: usize) -> Result<Blob, Error> {
+        self.backend.get(path, max_size)
+    }
+
+    /// Returns a file stored in the archive at `archive_path`, reading only its own bytes from
+    /// the backend.
+    pub(crate) fn get_from_archive(
         &self,
-        prefix: &str,
-        root_dir: &Path,
-    ) -> Result<(HashMap<PathBuf, String>, HashSet<CompressionAlgorithm>), Error> {
-        let conn;
-        let mut trans: Box<dyn StorageTransaction> = match &self.backend {
-            StorageBackend::Database(db) => {
-                conn = db.start_connection()?;
-                Box::new(conn.start_storage_transaction()?)
+        archive_path: &str,
+        path: &str,
+        max_size: usize,
+    ) -> Result<Blob, Error> {
+        let (index, cached_for) = self.archive_index(archive_path)?;
+        let archive = LoadedArchive {
+            path: archive_path.into(),
+            index,
+        };
+        match self.get_from_loaded_archive(&archive, path, max_size) {
+            // The archive might have been replaced since its index was cached
+            Err(err) if cached_for.map_or(false, |age| is_stale_index_error(&err, age)) => {
+                self.archive_indexes.invalidate(archive_path);
+                self.get_from_archive(archive_path, path, max_size)
+            }
+            res => res,
+        }
+    }
+
+    /// Loads the index of the archive at `archive_path`, to read many files from it with
+    /// `get_from_loaded_archive` without looking up the index again.
+    pub(crate) fn load_archive(&self, archive_path: &str) -> Result<LoadedArchive, Error> {
+        Ok(LoadedArchive {
+            path: archive_path.into(),
+            index: self.fetch_archive_index(archive_path)?,
+        })
+    }
+
+    /// Returns a file stored in an archive loaded with `load_archive`.
+    pub(crate) fn get_from_loaded_archive(
+        &self,
+        archive: &LoadedArchive,
+        path: &str,
+        max_size: usize,
+    ) -> Result<Blob, Error> {
+        let entry = archive.index.find(path)?;
+        if entry.size > max_size as u64 {
+            return Err(
+                io::Error::new(io::ErrorKind::Other, crate::error::SizeLimitReached).into(),
+            );
+        }
+
+        let content = self.backend.get_range(&archive.path, entry.range())?;
+        Ok(Blob {
+            path: path.into(),
+            mime: entry.mime.clone(),
+            date_updated: archive.index.date_updated,
+            content: entry.decompress(&content, max_size)?,
+            compression: None,
+        })
+    }
+
+    /// Returns the index of the archive at `archive_path`, and for how long it was cached.
+    fn archive_index(
+        &self,
+        archive_path: &str,
+    ) -> Result<(Arc<archive::ArchiveIndex>, Option<Duration>), Error> {
+        match self.archive_indexes.get_with_age(archive_path) {
+            Some((index, age)) => Ok((index, Some(age))),
+            None => Ok((self.fetch_archive_index(archive_path)?, None)),
+        }
+    }
+
+    /// Fetches the index of the archive at `archive_path` from the backend, and caches it.
+    fn fetch_archive_index(&self, archive_path: &str) -> Result<Arc<archive::ArchiveIndex>, Error> {
+        let index = self.get(&archive::index_path(archive_path), usize::MAX)?;
+        let index = Arc::new(serde_json::from_slice(&index.content)?);
+        self.archive_indexes
+            .insert(archive_path.into(), Arc::clone(&index));
+        Ok(index)
+    }
+
+    /// Returns the paths of all the blobs starting with `prefix`, sorted.
+    pub(crate) fn list_all_paths(&self, prefix: &str) -> Result<Vec<String>, Error> {
+        const PAGE_SIZE: usize = 1000;
+
+        let mut paths: Vec<String> = Vec::new();
+        loop {
+            let page =
+                self.backend
+                    .list_paths(prefix, paths.last().map(String::as_str), PAGE_SIZE)?;
+            let done = page.len() < PAGE_SIZE;
+            paths.extend(page);
+            if done {
+                return Ok(paths);
             }
-            StorageBackend::S3(s3) => Box::new(s3.start_storage_transaction()?),
+        }
+    }
+
+    /// Stores all files in `root_dir` into a single archive at `archive_path`, which can then be
+    /// read with `get_from_archive`.
+    ///
+    /// This returns the same as `store_all`.
+    pub(crate) fn store_all_in_archive(
+        &self,
+        archive_path: &str,
+        root_dir: &Path,
+    ) -> Result<StoredFiles, Error> {
+        let (archive, index) = archive::create(root_dir)?;
+        let archive_size = archive.metadata()?.len();
+        let file_paths_and_mimes = index
+            .files
+            .iter()
+            .map(|(path, entry)| (PathBuf::from(path), entry.mime.clone()))
+            .collect();
+
+        // The archive itself can't be compressed, as files are read from it with ranges.
+        let alg = self.compression;
+        let index_blob = Blob {
+            path: archive::index_path(archive_path),
+            mime: "application/json".into(),
+            content: compress(serde_json::to_vec(&index)?.as_slice(), alg)?,
+            compression: Some(alg),
+            date_updated: Utc::now(),
         };
+        let mut trans = self.backend.start_storage_transaction()?;
+        trans.store_file(archive_path, "application/zip", archive)?;
+        trans.store_batch(std::slice::from_ref(&index_blob))?;
+        trans.complete()?;
+        self.archive_indexes.invalidate(archive_path);
+
+        let stored_size = archive_size + index_blob.content.len() as u64;
+        Ok((
+            file_paths_and_mimes,
+            std::iter::once(alg).collect(),
+            stored_size,
+        ))
+    }
+
+    // Store all files in `root_dir` into the backend under `prefix`.
+    //
+    // This returns (map<filename, mime type>, set<compression algorithms>, number of bytes stored).
+    pub(crate) fn store_all(&self, prefix: &str, root_dir: &Path) -> Result<StoredFiles, Error> {
+        let mut trans = self.backend.start_storage_transaction()?;
 
         let mut file_paths_and_mimes = HashMap::new();
         let mut algs = HashSet::with_capacity(1);
+        let mut stored_size = 0;
 
         let mut blobs = get_file_list(root_dir)?
             .into_iter()
@@ -182,7 +400,7 @@ impl Storage {
                     .map(|file| (file_path, file))
             })
             .map(|(file_path, file)| -> Result<_, Error> {
-                let alg = CompressionAlgorithm::default();
+                let alg = self.compression;
                 let content = compress(file, alg)?;
                 let bucket_path = Path::new(prefix).join(&file_path).to_slash().unwrap();
 
@@ -195,7 +413,7 @@ impl Storage {
                     mime: mime.to_string(),
                     content,
                     compression: Some(alg),
-                    // this field is ignored by the backend
+                    // the S3 backend ignores this field and uses its own modification time
                     date_updated: Utc::now(),
                 })
             });
@@ -207,25 +425,76 @@ impl Storage {
             if batch.is_empty() {
                 break;
             }
+            stored_size += batch
+                .iter()
+                .map(|blob| blob.content.len() as u64)
+                .sum::<u64>();
             trans.store_batch(&batch)?;
         }
 
         trans.complete()?;
-        Ok((file_paths_and_mimes, algs))
+        Ok((file_paths_and_mimes, algs, stored_size))
     }
 }
 
-impl std::fmt::Debug for Storage {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match &self.backend {
-            StorageBackend::Database(_) => write!(f, "database-backed storage"),
-            StorageBackend::S3(_) => write!(f, "S3-backed storage"),
-        }
+/// The index of an archive, returned by `Storage::load_archive`.
+#[derive(Debug)]
+pub(crate) struct LoadedArchive {
+    path: String,
+    index: Arc<archive::ArchiveIndex>,
+}
+
+impl LoadedArchive {
+    /// Returns the paths of all the files stored in the archive, sorted.
+    pub(crate) fn paths(&self) -> Vec<String> {
+        let mut paths = self.index.files.keys().cloned().collect::<Vec<_>>();
+        paths.sort();
+        paths
+    }
+}
+
+/// Whether reading a file from an archive might have failed because the index of the archive,
+/// cached for `age`, doesn't match the stored archive anymore.
+fn is_stale_index_error(err: &Error, age: Duration) -> bool {
+    let size_limit_reached = err
+        .downcast_ref::<io::Error>()
+        .and_then(|io| io.get_ref())
+        .and_then(|err| err.downcast_ref::<crate::error::SizeLimitReached>())
+        .is_some();
+
+    if err
+        .downcast_ref::<archive::PathNotInArchiveError>()
+        .is_some()
+    {
+        age > MISSING_FILE_RECHECK_DELAY
+    } else {
+        !size_limit_reached
     }
 }
 
-trait StorageTransaction {
+/// Path of the archive storing the documentation of a release, when archive storage is used.
+pub(crate) fn rustdoc_archive_path(name: &str, version: &str) -> String {
+    format!("rustdoc/{}/{}.zip", name, version)
+}
+
+pub trait StorageTransaction {
+    /// Stores the blobs, replacing the existing blobs with the same paths.
     fn store_batch(&mut self, batch: &[Blob]) -> Result<(), Error>;
+
+    /// Stores the content of `file` uncompressed at `path`, replacing the existing blob. Backends
+    /// able to stream the file override this instead of reading it in memory.
+    fn store_file(&mut self, path: &str, mime: &str, mut file: fs::File) -> Result<(), Error> {
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)?;
+        self.store_batch(&[Blob {
+            path: path.into(),
+            mime: mime.into(),
+            content,
+            compression: None,
+            date_updated: Utc::now(),
+        }])
+    }
+
     fn complete(self: Box<Self>) -> Result<(), Error>;
 }
 
@@ -233,6 +502,17 @@ trait StorageTransaction {
 pub fn compress(content: impl Read, algorithm: CompressionAlgorithm) -> Result<Vec<u8>, Error> {
     match algorithm {
         CompressionAlgorithm::Zstd => Ok(zstd::encode_all(content, 9)?),
+        CompressionAlgorithm::Brotli => {
+            let mut compressed = Vec::new();
+            brotli::CompressorReader::new(content, 4096, 9, 22).read_to_end(&mut compressed)?;
+            Ok(compressed)
+        }
+        CompressionAlgorithm::Gzip => {
+            let mut compressed = Vec::new();
+            flate2::read::GzEncoder::new(content, flate2::Compression::best())
+                .read_to_end(&mut compressed)?;
+            Ok(compressed)
+        }
     }
 }
 
@@ -246,15 +526,28 @@ pub fn decompress(
 
     match algorithm {
         CompressionAlgorithm::Zstd => zstd::stream::copy_decode(content, &mut buffer)?,
+        CompressionAlgorithm::Brotli => {
+            io::copy(&mut brotli::Decompressor::new(content, 4096), &mut buffer)?;
+        }
+        CompressionAlgorithm::Gzip => {
+            io::copy(&mut flate2::read::GzDecoder::new(content), &mut buffer)?;
+        }
     }
 
     Ok(buffer.into_inner())
 }
 
+/// Decompresses the content of a blob returned by `Storage::get_raw`, if it's compressed.
+pub(crate) fn decompress_blob(blob: &mut Blob, max_size: usize) -> Result<(), Error> {
+    if let Some(alg) = blob.compression.take() {
+        blob.content = decompress(blob.content.as_slice(), alg, max_size)?;
+    }
+    Ok(())
+}
+
 fn detect_mime(file_path: &Path) -> Result<&'static str, Error> {
     let mime = mime_guess::from_path(file_path)
         .first_raw()
-        .map(|m| m)
         .unwrap_or("text/plain");
     Ok(match mime {
         "text/plain" | "text/troff" | "text/x-markdown" | "text/x-rust" | "text/x-toml" => {
@@ -301,16 +594,14 @@ mod test {
         }
         wrapper(|env| {
             let db = env.db();
-            let backend = Storage {
-                backend: StorageBackend::Database(DatabaseBackend::new(db.pool())),
-            };
-            let (stored_files, _algs) = backend.store_all("", dir.path()).unwrap();
+            let backend = Storage::with_backend(Box::new(DatabaseBackend::new(db.pool())));
+            let (stored_files, _algs, _) = backend.store_all("", dir.path()).unwrap();
             assert_eq!(stored_files.len(), blobs.len());
             for blob in blobs {
                 let name = Path::new(&blob.path);
                 assert!(stored_files.contains_key(name));
 
-                let actual = backend.get(&blob.path, std::usize::MAX).unwrap();
+                let actual = backend.get(&blob.path, usize::MAX).unwrap();
                 assert_blob_eq(blob, &actual);
             }
 
@@ -335,10 +626,8 @@ mod test {
         }
         wrapper(|env| {
             let db = env.db();
-            let backend = Storage {
-                backend: StorageBackend::Database(DatabaseBackend::new(db.pool())),
-            };
-            let (stored_files, _algs) = backend.store_all("rustdoc", dir.path()).unwrap();
+            let backend = Storage::with_backend(Box::new(DatabaseBackend::new(db.pool())));
+            let (stored_files, _algs, _) = backend.store_all("rustdoc", dir.path()).unwrap();
             assert_eq!(stored_files.len(), files.len());
             for name in &files {
                 let name = Path::new(name);
@@ -353,12 +642,12 @@ mod test {
                 "text/rust"
             );
 
-            let file = backend.get("rustdoc/Cargo.toml", std::usize::MAX).unwrap();
+            let file = backend.get("rustdoc/Cargo.toml", usize::MAX).unwrap();
             assert_eq!(file.content, b"data");
             assert_eq!(file.mime, "text/toml");
             assert_eq!(file.path, "rustdoc/Cargo.toml");
 
-            let file = backend.get("rustdoc/src/main.rs", std::usize::MAX).unwrap();
+            let file = backend.get("rustdoc/src/main.rs", usize::MAX).unwrap();
             assert_eq!(file.content, b"data");
             assert_eq!(file.mime, "text/rust");
             assert_eq!(file.path, "rustdoc/src/main.rs");
@@ -366,6 +655,114 @@ mod test {
         })
     }
 
+    #[test]
+    fn test_local_fs_storage() {
+        wrapper(|env| {
+            let storage_dir = tempfile::tempdir()?;
+            env.override_config(|config| {
+                config.storage_backend = Some(StorageKind::LocalFs);
+                config.local_storage_path = Some(storage_dir.path().into());
+            });
+            let storage = env.storage();
+
+            let source = tempfile::tempdir()?;
+            fs::create_dir(source.path().join("src"))?;
+            fs::write(source.path().join("src/lib.rs"), "pub fn foo() {}")?;
+
+            let (stored_files, _algs, _) = storage.store_all("sources/foo/1.0.0", source.path())?;
+            assert!(stored_files.contains_key(Path::new("src/lib.rs")));
+
+            let file = storage.get("sources/foo/1.0.0/src/lib.rs", usize::MAX)?;
+            assert_eq!(file.content, b"pub fn foo() {}");
+            assert_eq!(file.mime, "text/rust");
+            assert!(storage_dir
+                .path()
+                .join("content/sources/foo/1.0.0/src/lib.rs.blob")
+                .is_file());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_archive_storage() {
+        wrapper(|env| {
+            let db = env.db();
+            let storage_dir = tempfile::tempdir()?;
+            let backends: Vec<Box<dyn StorageBackend>> = vec![
+                Box::new(DatabaseBackend::new(db.pool())),
+                Box::new(LocalFsBackend::new(storage_dir.path())),
+            ];
+
+            let source = tempfile::tempdir()?;
+            fs::create_dir(source.path().join("foo"))?;
+            fs::write(source.path().join("foo/index.html"), "<html>foo</html>")?;
+            fs::write(source.path().join("foo/all.html"), "<html>all</html>")?;
+
+            for backend in backends {
+                let storage = Storage::with_backend(backend);
+                let archive_path = rustdoc_archive_path("foo", "0.1.0");
+                let (stored_files, algs, stored_size) =
+                    storage.store_all_in_archive(&archive_path, source.path())?;
+                assert_eq!(stored_files.len(), 2);
+                assert_eq!(stored_files[Path::new("foo/all.html")], "text/html");
+                assert!(algs.contains(&CompressionAlgorithm::default()));
+                // The archive and its compressed index
+                let stored_blob_size = |path: &str| -> Result<u64, Error> {
+                    Ok(storage.backend.get(path, usize::MAX)?.content.len() as u64)
+                };
+                assert_eq!(
+                    stored_size,
+                    stored_blob_size(&archive_path)?
+                        + stored_blob_size(&archive::index_path(&archive_path))?
+                );
+
+                let file = storage.get_from_archive(&archive_path, "foo/all.html", usize::MAX)?;
+                assert_eq!(file.content, b"<html>all</html>");
+                assert_eq!(file.mime, "text/html");
+                assert_eq!(file.compression, None);
+
+                assert!(storage
+                    .get_from_archive(&archive_path, "foo/missing.html", usize::MAX)
+                    .is_err());
+                assert!(storage
+                    .get_from_archive(&archive_path, "foo/index.html", 4)
+                    .unwrap_err()
+                    .downcast_ref::<std::io::Error>()
+                    .and_then(|io| io.get_ref())
+                    .and_then(|err| err.downcast_ref::<crate::error::SizeLimitReached>())
+                    .is_some());
+            }
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_archive_replaced_by_another_process() -> Result<(), Error> {
+        let storage_dir = tempfile::tempdir()?;
+        let reader = Storage::with_backend(Box::new(LocalFsBackend::new(storage_dir.path())));
+        let writer = Storage::with_backend(Box::new(LocalFsBackend::new(storage_dir.path())));
+        let archive_path = rustdoc_archive_path("foo", "0.1.0");
+
+        let source = tempfile::tempdir()?;
+        fs::write(source.path().join("all.html"), "<html>all</html>")?;
+        writer.store_all_in_archive(&archive_path, source.path())?;
+        let file = reader.get_from_archive(&archive_path, "all.html", usize::MAX)?;
+        assert_eq!(file.content, b"<html>all</html>");
+
+        // The index cached by the reader doesn't match the new archive anymore
+        fs::write(source.path().join("all.html"), "<html>all the items</html>")?;
+        fs::write(source.path().join("index.html"), "<html>index</html>")?;
+        writer.store_all_in_archive(&archive_path, source.path())?;
+        let file = reader.get_from_archive(&archive_path, "all.html", usize::MAX)?;
+        assert_eq!(file.content, b"<html>all the items</html>");
+        let file = reader.get_from_archive(&archive_path, "index.html", usize::MAX)?;
+        assert_eq!(file.content, b"<html>index</html>");
+
+        Ok(())
+    }
+
     #[test]
     fn test_batched_uploads() {
         let uploads: Vec<_> = (0..=MAX_CONCURRENT_UPLOADS + 1)
@@ -390,7 +787,7 @@ mod test {
         crate::test::init_logger();
         let files = get_file_list(env::current_dir().unwrap());
         assert!(files.is_ok());
-        assert!(files.unwrap().len() > 0);
+        assert!(!files.unwrap().is_empty());
 
         let files = get_file_list(env::current_dir().unwrap().join("Cargo.toml")).unwrap();
         assert_eq!(files[0], std::path::Path::new("Cargo.toml"));
@@ -412,7 +809,7 @@ mod test {
             };
             test_roundtrip(std::slice::from_ref(&blob));
             assert_eq!(
-                decompress(data.as_slice(), *alg, std::usize::MAX).unwrap(),
+                decompress(data.as_slice(), *alg, usize::MAX).unwrap(),
                 orig.as_bytes()
             );
         }
@@ -480,12 +877,16 @@ mod test {
     fn test_compression_try_from_is_exhaustive() {
         use std::convert::TryFrom;
 
-        let a = CompressionAlgorithm::Zstd;
-        match a {
-            CompressionAlgorithm::Zstd => {
-                assert_eq!(a, CompressionAlgorithm::try_from(a as i32).unwrap());
-                assert_eq!(a, a.to_string().parse().unwrap());
+        for &a in CompressionAlgorithm::AVAILABLE {
+            match a {
+                CompressionAlgorithm::Zstd
+                | CompressionAlgorithm::Brotli
+                | CompressionAlgorithm::Gzip => {
+                    assert_eq!(a, CompressionAlgorithm::try_from(a as i32).unwrap());
+                    assert_eq!(a, a.to_string().parse().unwrap());
+                }
             }
         }
+        assert!("Lzma".parse::<CompressionAlgorithm>().is_err());
     }
 }
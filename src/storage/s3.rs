@@ -1,4 +1,4 @@
-use super::{Blob, StorageTransaction};
+use super::{Blob, StorageBackend, StorageTransaction};
 use chrono::{DateTime, NaiveDateTime, Utc};
 use failure::Error;
 use futures::stream::{FuturesUnordered, Stream};
@@ -7,9 +7,14 @@ use log::{error, warn};
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
 use rusoto_core::region::Region;
+use rusoto_core::ByteStream;
 use rusoto_credential::DefaultCredentialsProvider;
-use rusoto_s3::{GetObjectRequest, PutObjectRequest, S3Client, S3};
+use rusoto_s3::{GetObjectRequest, ListObjectsV2Request, PutObjectRequest, S3Client, S3};
 use std::convert::TryInto;
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::ops::Range;
 use tokio::runtime::Runtime;
 
 #[cfg(test)]
@@ -18,6 +23,8 @@ mod test;
 pub(crate) use test::TestS3;
 
 pub(crate) static S3_BUCKET_NAME: &str = "rust-docs-rs";
+/// Size of the chunks in which files are streamed to S3 by `store_file`.
+const UPLOAD_CHUNK_SIZE: usize = 1024 * 1024;
 static S3_RUNTIME: Lazy<Mutex<Runtime>> =
     Lazy::new(|| Mutex::new(Runtime::new().expect("Failed to create S3 runtime")));
 
@@ -33,8 +40,16 @@ impl S3Backend {
             bucket: bucket.into(),
         }
     }
+}
 
-    pub(super) fn get(&self, path: &str, max_size: usize) -> Result<Blob, Error> {
+impl fmt::Debug for S3Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "S3-backed storage")
+    }
+}
+
+impl StorageBackend for S3Backend {
+    fn get(&self, path: &str, max_size: usize) -> Result<Blob, Error> {
         let res = self
             .client
             .get_object(GetObjectRequest {
@@ -66,8 +81,54 @@ impl S3Backend {
         })
     }
 
-    pub(super) fn start_storage_transaction(&self) -> Result<S3StorageTransaction, Error> {
-        Ok(S3StorageTransaction { s3: self })
+    fn get_range(&self, path: &str, range: Range<u64>) -> Result<Vec<u8>, Error> {
+        let res = self
+            .client
+            .get_object(GetObjectRequest {
+                bucket: self.bucket.to_string(),
+                key: path.into(),
+                // HTTP ranges include their end
+                range: Some(format!("bytes={}-{}", range.start, range.end - 1)),
+                ..Default::default()
+            })
+            .sync()?;
+
+        let mut content = Vec::with_capacity((range.end - range.start) as usize);
+        res.body
+            .unwrap()
+            .into_blocking_read()
+            .read_to_end(&mut content)?;
+        Ok(content)
+    }
+
+    fn start_storage_transaction(&self) -> Result<Box<dyn StorageTransaction + '_>, Error> {
+        Ok(Box::new(S3StorageTransaction { s3: self }))
+    }
+
+    fn list_paths(
+        &self,
+        prefix: &str,
+        start_after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>, Error> {
+        // S3 returns at most 1000 keys for each request
+        let res = self
+            .client
+            .list_objects_v2(ListObjectsV2Request {
+                bucket: self.bucket.to_string(),
+                prefix: Some(prefix.into()),
+                start_after: start_after.map(String::from),
+                max_keys: Some(limit.min(1000) as i64),
+                ..Default::default()
+            })
+            .sync()?;
+
+        Ok(res
+            .contents
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|object| object.key)
+            .collect())
     }
 }
 
@@ -116,6 +177,35 @@ impl<'a> StorageTransaction for S3StorageTransaction<'a> {
         Ok(())
     }
 
+    fn store_file(&mut self, path: &str, mime: &str, mut file: fs::File) -> Result<(), Error> {
+        let len = file.metadata()?.len();
+        let chunks = std::iter::from_fn(move || {
+            let mut chunk = vec![0; UPLOAD_CHUNK_SIZE];
+            match file.read(&mut chunk) {
+                Ok(0) => None,
+                Ok(read) => {
+                    chunk.truncate(read);
+                    Some(Ok(chunk.into()))
+                }
+                Err(err) => Some(Err(err)),
+            }
+        });
+
+        self.s3
+            .client
+            .put_object(PutObjectRequest {
+                bucket: self.s3.bucket.to_string(),
+                key: path.into(),
+                body: Some(ByteStream::new(futures::stream::iter_result(chunks))),
+                content_length: Some(len.try_into()?),
+                content_type: Some(mime.into()),
+                ..Default::default()
+            })
+            .sync()?;
+        crate::web::metrics::UPLOADED_FILES_TOTAL.inc_by(1);
+        Ok(())
+    }
+
     fn complete(self: Box<Self>) -> Result<(), Error> {
         Ok(())
     }
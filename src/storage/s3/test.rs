@@ -25,7 +25,7 @@ impl TestS3 {
     }
     pub(crate) fn upload(&self, blobs: &[Blob]) -> Result<(), Error> {
         let s3 = self.0.borrow();
-        let mut transaction = Box::new(s3.start_storage_transaction()?);
+        let mut transaction = s3.start_storage_transaction()?;
         transaction.store_batch(blobs)?;
         transaction.complete()?;
         Ok(())
@@ -34,7 +34,7 @@ impl TestS3 {
         use rusoto_core::RusotoError;
         use rusoto_s3::GetObjectError;
 
-        let err = self.0.borrow().get(path, std::usize::MAX).unwrap_err();
+        let err = self.0.borrow().get(path, usize::MAX).unwrap_err();
         match err
             .downcast_ref::<RusotoError<GetObjectError>>()
             .expect("wanted GetObject")
@@ -45,7 +45,7 @@ impl TestS3 {
         };
     }
     pub(crate) fn assert_blob(&self, blob: &Blob, path: &str) {
-        let actual = self.0.borrow().get(path, std::usize::MAX).unwrap();
+        let actual = self.0.borrow().get(path, usize::MAX).unwrap();
         assert_blob_eq(blob, &actual);
     }
 
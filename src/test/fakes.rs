@@ -1,5 +1,5 @@
 use super::TestDatabase;
-use crate::docbuilder::BuildResult;
+use crate::docbuilder::{BuildResources, BuildResult, DocWarning, FileCoverage};
 use crate::index::api::RegistryCrateData;
 use crate::storage::Storage;
 use crate::utils::{Dependency, MetadataPackage, Target};
@@ -24,6 +24,8 @@ pub(crate) struct FakeRelease<'a> {
     has_examples: bool,
     /// This stores the content, while `package.readme` stores the filename
     readme: Option<&'a str>,
+    archive_storage: bool,
+    doc_coverage: Vec<FileCoverage>,
 }
 
 impl<'a> FakeRelease<'a> {
@@ -55,6 +57,9 @@ impl<'a> FakeRelease<'a> {
                 docsrs_version: "docs.rs 1.0.0 (000000000 1970-01-01)".into(),
                 build_log: "It works!".into(),
                 successful: true,
+                doc_warnings: Vec::new(),
+                omitted_doc_warnings: 0,
+                resources: BuildResources::default(),
             },
             source_files: Vec::new(),
             rustdoc_files: Vec::new(),
@@ -69,6 +74,8 @@ impl<'a> FakeRelease<'a> {
             has_docs: true,
             has_examples: false,
             readme: None,
+            archive_storage: false,
+            doc_coverage: Vec::new(),
         }
     }
 
@@ -115,6 +122,35 @@ impl<'a> FakeRelease<'a> {
         self
     }
 
+    pub(crate) fn rustc_version(mut self, new: &str) -> Self {
+        self.build_result.rustc_version = new.into();
+        self
+    }
+
+    pub(crate) fn doc_warning(mut self, warning: DocWarning) -> Self {
+        self.build_result.doc_warnings.push(warning);
+        self
+    }
+
+    pub(crate) fn omitted_doc_warnings(mut self, count: i32) -> Self {
+        self.build_result.omitted_doc_warnings = count;
+        self
+    }
+
+    pub(crate) fn build_resources(mut self, resources: BuildResources) -> Self {
+        self.build_result.resources = resources;
+        self
+    }
+
+    pub(crate) fn doc_coverage(mut self, file: &str, documented: i32, total: i32) -> Self {
+        self.doc_coverage.push(FileCoverage {
+            file: file.into(),
+            documented,
+            total,
+        });
+        self
+    }
+
     pub(crate) fn yanked(mut self, new: bool) -> Self {
         self.registry_crate_data.yanked = new;
         self
@@ -167,6 +203,11 @@ impl<'a> FakeRelease<'a> {
     }
 
     /// Returns the release_id
+    pub(crate) fn archive_storage(mut self, new: bool) -> Self {
+        self.archive_storage = new;
+        self
+    }
+
     pub(crate) fn create(self) -> Result<i32, Error> {
         use std::collections::HashSet;
         use std::fs;
@@ -180,7 +221,7 @@ impl<'a> FakeRelease<'a> {
         let mut algs = HashSet::new();
         if self.build_result.successful {
             let storage = self.storage.clone();
-            let upload_files = |prefix: &str, files: &[(&str, &[u8])], target: Option<&str>| {
+            let write_files = |prefix: &str, files: &[(&str, &[u8])], target: Option<&str>| {
                 let mut path_prefix = tempdir.path().join(prefix);
                 if let Some(target) = target {
                     path_prefix.push(target);
@@ -192,11 +233,15 @@ impl<'a> FakeRelease<'a> {
                     if let Some(parent) = Path::new(path).parent() {
                         fs::create_dir_all(path_prefix.join(parent))?;
                     }
-                    let file = path_prefix.join(&path);
+                    let file = path_prefix.join(path);
                     log::debug!("writing file {}", file.display());
                     fs::write(file, data)?;
                 }
 
+                Ok::<_, Error>(path_prefix)
+            };
+            let upload_files = |prefix: &str, files: &[(&str, &[u8])], target: Option<&str>| {
+                let path_prefix = write_files(prefix, files, target)?;
                 let prefix = format!(
                     "{}/{}/{}/{}",
                     prefix,
@@ -214,26 +259,38 @@ impl<'a> FakeRelease<'a> {
                 rustdoc_files.push((&index, b"default index content"));
             }
             for (source_path, data) in &self.source_files {
-                if source_path.starts_with("src/") {
-                    let updated = ["src", &package.name, &source_path[4..]].join("/");
+                if let Some(src_path) = source_path.strip_prefix("src/") {
+                    let updated = ["src", &package.name, src_path].join("/");
                     rustdoc_files.push((Box::leak(Box::new(updated)), data));
                 }
             }
-            let (rustdoc_meta, new_algs) = upload_files("rustdoc", &rustdoc_files, None)?;
-            algs.extend(new_algs);
-            log::debug!("added rustdoc files {}", rustdoc_meta);
-            match upload_files("source", &self.source_files, None)? {
-                (json, new_algs) => {
-                    source_meta = Some(json);
-                    algs.extend(new_algs);
+            if self.archive_storage {
+                let rustdoc_dir = write_files("rustdoc", &rustdoc_files, None)?;
+                for target in &package.targets[1..] {
+                    let platform = target.src_path.as_ref().unwrap();
+                    write_files("rustdoc", &rustdoc_files, Some(platform))?;
                 }
+                let archive_path =
+                    crate::storage::rustdoc_archive_path(&package.name, &package.version);
+                let (_, new_algs, _) = storage.store_all_in_archive(&archive_path, &rustdoc_dir)?;
+                algs.extend(new_algs);
+                log::debug!("added rustdoc archive {}", archive_path);
+            } else {
+                let (rustdoc_meta, new_algs) = upload_files("rustdoc", &rustdoc_files, None)?;
+                algs.extend(new_algs);
+                log::debug!("added rustdoc files {}", rustdoc_meta);
             }
+            let (json, new_algs) = upload_files("source", &self.source_files, None)?;
+            source_meta = Some(json);
+            algs.extend(new_algs);
             log::debug!("added source files {}", source_meta.as_ref().unwrap());
 
-            for target in &package.targets[1..] {
-                let platform = target.src_path.as_ref().unwrap();
-                upload_files("rustdoc", &rustdoc_files, Some(platform))?;
-                log::debug!("added platform files for {}", platform);
+            if !self.archive_storage {
+                for target in &package.targets[1..] {
+                    let platform = target.src_path.as_ref().unwrap();
+                    upload_files("rustdoc", &rustdoc_files, Some(platform))?;
+                    log::debug!("added platform files for {}", platform);
+                }
             }
         }
 
@@ -253,8 +310,12 @@ impl<'a> FakeRelease<'a> {
             self.has_docs,
             self.has_examples,
             algs,
+            self.archive_storage,
         )?;
         crate::db::add_build_into_database(&db.conn(), release_id, &self.build_result)?;
+        if !self.doc_coverage.is_empty() {
+            crate::db::add_doc_coverage(&db.conn(), release_id, &self.doc_coverage)?;
+        }
 
         Ok(release_id)
     }
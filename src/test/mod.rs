@@ -133,6 +133,9 @@ impl TestEnvironment {
         config.max_pool_size = 2;
         config.min_pool_idle = 0;
 
+        // Retry failed builds right away, tests checking the backoff override this.
+        config.build_backoff_seconds = 0;
+
         config
     }
 
@@ -159,7 +162,12 @@ impl TestEnvironment {
 
     pub(crate) fn storage(&self) -> Arc<Storage> {
         self.storage
-            .get_or_init(|| Arc::new(Storage::new(self.db().pool())))
+            .get_or_init(|| {
+                Arc::new(
+                    Storage::new(self.db().pool(), &self.config())
+                        .expect("failed to initialize the storage"),
+                )
+            })
             .clone()
     }
 
@@ -186,6 +194,7 @@ impl TestEnvironment {
 pub(crate) struct TestDatabase {
     pool: Pool,
     schema: String,
+    database_url: String,
 }
 
 impl TestDatabase {
@@ -207,6 +216,7 @@ impl TestDatabase {
         Ok(TestDatabase {
             pool: Pool::new_with_schema(config, &schema)?,
             schema,
+            database_url: config.database_url.clone(),
         })
     }
 
@@ -230,6 +240,21 @@ impl Drop for TestDatabase {
         {
             error!("failed to drop test schema {}: {}", self.schema, e);
         }
+
+        // The web server of the test can't be stopped and keeps the connections of the pool open,
+        // close them to not run out of connections over the whole test suite.
+        let res = Connection::connect(self.database_url.as_str(), postgres::TlsMode::None)
+            .and_then(|conn| {
+                conn.execute(
+                    "SELECT pg_terminate_backend(pid)
+                     FROM pg_stat_activity
+                     WHERE application_name = $1;",
+                    &[&self.schema],
+                )
+            });
+        if let Err(e) = res {
+            error!("failed to close the connections to {}: {}", self.schema, e);
+        }
     }
 }
 
@@ -267,4 +292,8 @@ impl TestFrontend {
     pub(crate) fn get(&self, url: &str) -> RequestBuilder {
         self.build_request(Method::GET, url)
     }
+
+    pub(crate) fn post(&self, url: &str) -> RequestBuilder {
+        self.build_request(Method::POST, url)
+    }
 }
@@ -34,7 +34,7 @@ pub fn copy_doc_dir<P: AsRef<Path>, Q: AsRef<Path>>(source: P, destination: Q) -
         } else if dup_regex.is_match(&file.file_name().into_string().unwrap()[..]) {
             continue;
         } else {
-            fs::copy(&file.path(), &destination_full_path)?;
+            fs::copy(file.path(), &destination_full_path)?;
         }
     }
     Ok(())
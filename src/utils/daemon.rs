@@ -1,92 +1,147 @@
 //! Simple daemon
 //!
-//! This daemon will start web server, track new packages and build them
+//! This daemon will start web server, track new packages and build them. Each of these roles can
+//! also run in its own process. On SIGTERM or SIGINT it stops building new crates, waits for the
+//! current build, and exits.
 
 use crate::{
     db::Pool,
     storage::Storage,
-    utils::{queue_builder, update_release_activity, GithubUpdater},
+    utils::{
+        handle_signals, queue_builder, start_heartbeat, update_release_activity, GithubUpdater,
+        Health, Shutdown,
+    },
     BuildQueue, Config, DocBuilder, DocBuilderOptions,
 };
 use chrono::{Timelike, Utc};
 use failure::Error;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
 use std::sync::Arc;
 use std::time::Duration;
 use std::{env, thread};
 
-fn start_registry_watcher(pool: Pool, build_queue: Arc<BuildQueue>) -> Result<(), Error> {
+/// How long the daemon waits for an interrupted build to clean up after itself.
+const INTERRUPTED_BUILD_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The parts of the daemon, which can run in separate processes.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    strum::EnumString,
+    strum::EnumVariantNames,
+    strum::IntoStaticStr,
+)]
+#[strum(serialize_all = "snake_case")]
+pub enum DaemonRole {
+    /// The web server
+    Web,
+    /// Queues the crates published on the registry
+    Watcher,
+    /// Builds the queued crates
+    Builder,
+    /// Periodically updates the release activity and the GitHub stats
+    Scheduler,
+}
+
+impl DaemonRole {
+    pub(crate) fn as_str(self) -> &'static str {
+        self.into()
+    }
+}
+
+fn start_registry_watcher(
+    pool: Pool,
+    build_queue: Arc<BuildQueue>,
+    shutdown: Arc<Shutdown>,
+    health: Arc<Health>,
+) -> Result<(), Error> {
+    health.report(DaemonRole::Watcher, "starting");
     thread::Builder::new()
         .name("registry index reader".to_string())
         .spawn(move || {
             // space this out to prevent it from clashing against the queue-builder thread on launch
-            thread::sleep(Duration::from_secs(30));
+            if shutdown.wait_timeout(Duration::from_secs(30)) {
+                return;
+            }
             loop {
                 let opts = opts();
                 let mut doc_builder = DocBuilder::new(opts, pool.clone(), build_queue.clone());
 
                 if doc_builder.is_locked() {
-                    debug!("Lock file exists, skipping checking new crates");
+                    debug!("Builds are paused, skipping checking new crates");
+                    health.report(DaemonRole::Watcher, "locked");
                 } else {
                     debug!("Checking new crates");
+                    health.report(DaemonRole::Watcher, "checking new crates");
                     match doc_builder.get_new_crates() {
                         Ok(n) => debug!("{} crates added to queue", n),
                         Err(e) => error!("Failed to get new crates: {}", e),
                     }
+                    health.report(DaemonRole::Watcher, "idle");
                 }
 
-                thread::sleep(Duration::from_secs(60));
+                if shutdown.wait_timeout(Duration::from_secs(60)) {
+                    return;
+                }
             }
         })?;
 
     Ok(())
 }
 
-pub fn start_daemon(
+/// Starts the queue builder, returning a receiver disconnected once the builder exits.
+fn start_builder(
     config: Arc<Config>,
     db: Pool,
     build_queue: Arc<BuildQueue>,
     storage: Arc<Storage>,
-    enable_registry_watcher: bool,
-) -> Result<(), Error> {
-    const CRATE_VARIABLES: &[&str] = &["CRATESFYI_PREFIX"];
-
-    // first check required environment variables
-    for v in CRATE_VARIABLES.iter() {
-        if env::var(v).is_err() {
-            panic!("Environment variable {} not found", v)
-        }
-    }
-
-    let dbopts = opts();
-
-    // check paths once
-    dbopts.check_paths().unwrap();
-
-    if enable_registry_watcher {
-        // check new crates every minute
-        start_registry_watcher(db.clone(), build_queue.clone())?;
-    }
-
-    // build new crates every minute
-    let cloned_db = db.clone();
-    let cloned_build_queue = build_queue.clone();
-    let cloned_storage = storage.clone();
+    shutdown: Arc<Shutdown>,
+    health: Arc<Health>,
+) -> Result<Receiver<()>, Error> {
+    health.report(DaemonRole::Builder, "starting");
+    // Dropped when the builder exits, even if it panics
+    let (builder_running, builder_exited) = mpsc::channel::<()>();
     thread::Builder::new()
         .name("build queue reader".to_string())
         .spawn(move || {
-            let doc_builder =
-                DocBuilder::new(opts(), cloned_db.clone(), cloned_build_queue.clone());
-            queue_builder(doc_builder, cloned_db, cloned_build_queue, cloned_storage).unwrap();
-        })
-        .unwrap();
+            let _running = builder_running;
+            let doc_builder = DocBuilder::new(opts(), db.clone(), build_queue.clone());
+            queue_builder(
+                doc_builder,
+                config,
+                db,
+                build_queue,
+                storage,
+                shutdown,
+                health,
+            )
+            .unwrap();
+        })?;
+    Ok(builder_exited)
+}
+
+fn start_scheduler(
+    config: &Config,
+    db: Pool,
+    shutdown: Arc<Shutdown>,
+    health: Arc<Health>,
+) -> Result<(), Error> {
+    health.report(DaemonRole::Scheduler, "idle");
 
     // update release activity everyday at 23:55
     let cloned_db = db.clone();
     cron(
         "release activity updater",
         Duration::from_secs(60),
+        shutdown.clone(),
+        health.clone(),
         move || {
             let now = Utc::now();
             if now.hour() == 23 && now.minute() == 55 {
@@ -98,10 +153,12 @@ pub fn start_daemon(
     )?;
 
     // update github stats every hour
-    let github_updater = GithubUpdater::new(&config, db.clone())?;
+    let github_updater = GithubUpdater::new(config, db)?;
     cron(
         "github stats updater",
         Duration::from_secs(60 * 60),
+        shutdown,
+        health,
         move || {
             github_updater.update_all_crates()?;
             Ok(())
@@ -110,25 +167,134 @@ pub fn start_daemon(
 
     // TODO: update ssl certificate every 3 months
 
-    // at least start web server
-    info!("Starting web server");
+    Ok(())
+}
+
+/// Runs the roles of the daemon until it receives SIGTERM or SIGINT.
+pub fn start_daemon(
+    config: Arc<Config>,
+    db: Pool,
+    build_queue: Arc<BuildQueue>,
+    storage: Arc<Storage>,
+    roles: &[DaemonRole],
+) -> Result<(), Error> {
+    if roles.is_empty() {
+        failure::bail!("the daemon has no role to run");
+    }
+    let has_role = |role| roles.contains(&role);
+
+    if has_role(DaemonRole::Watcher) || has_role(DaemonRole::Builder) {
+        const CRATE_VARIABLES: &[&str] = &["CRATESFYI_PREFIX"];
+
+        // first check required environment variables
+        for v in CRATE_VARIABLES.iter() {
+            if env::var(v).is_err() {
+                panic!("Environment variable {} not found", v)
+            }
+        }
+
+        let dbopts = opts();
+
+        // check paths once
+        dbopts.check_paths().unwrap();
+    }
+
+    let shutdown = Arc::new(Shutdown::default());
+    handle_signals(shutdown.clone())?;
+    let health = Arc::new(Health::new(db.clone(), &config.build_worker_id));
 
-    crate::Server::start(None, false, db, config, build_queue, storage)?;
+    if has_role(DaemonRole::Watcher) {
+        // check new crates every minute
+        start_registry_watcher(
+            db.clone(),
+            build_queue.clone(),
+            shutdown.clone(),
+            health.clone(),
+        )?;
+    }
+
+    let builder_exited = if has_role(DaemonRole::Builder) {
+        // build new crates every minute
+        Some(start_builder(
+            config.clone(),
+            db.clone(),
+            build_queue.clone(),
+            storage.clone(),
+            shutdown.clone(),
+            health.clone(),
+        )?)
+    } else {
+        None
+    };
+
+    if has_role(DaemonRole::Scheduler) {
+        start_scheduler(&config, db.clone(), shutdown.clone(), health.clone())?;
+    }
+
+    let server = if has_role(DaemonRole::Web) {
+        info!("Starting web server");
+        health.report(DaemonRole::Web, "serving");
+        Some(crate::Server::start(
+            None,
+            false,
+            db,
+            config.clone(),
+            build_queue.clone(),
+            storage,
+        )?)
+    } else {
+        None
+    };
+
+    start_heartbeat(health.clone())?;
+    shutdown.wait();
+
+    if let Some(builder_exited) = builder_exited {
+        let timeout = Duration::from_secs(config.shutdown_timeout_seconds);
+        info!(
+            "Waiting at most {}s for the current build",
+            timeout.as_secs()
+        );
+        if let Err(RecvTimeoutError::Timeout) = builder_exited.recv_timeout(timeout) {
+            warn!("The current build is taking too long, interrupting it");
+            build_queue.interrupt_builds();
+            if let Err(RecvTimeoutError::Timeout) =
+                builder_exited.recv_timeout(INTERRUPTED_BUILD_TIMEOUT)
+            {
+                error!("The interrupted build didn't stop, exiting anyway");
+            }
+        }
+    }
+
+    if let Some(server) = server {
+        info!("Stopping web server");
+        server.stop();
+    }
+    if let Err(e) = health.stop() {
+        error!("Failed to report the daemon as stopped: {}", e);
+    }
     Ok(())
 }
 
-fn cron<F>(name: &'static str, interval: Duration, exec: F) -> Result<(), Error>
+fn cron<F>(
+    name: &'static str,
+    interval: Duration,
+    shutdown: Arc<Shutdown>,
+    health: Arc<Health>,
+    exec: F,
+) -> Result<(), Error>
 where
     F: Fn() -> Result<(), Error> + Send + 'static,
 {
-    thread::Builder::new()
-        .name(name.into())
-        .spawn(move || loop {
-            thread::sleep(interval);
+    thread::Builder::new().name(name.into()).spawn(move || {
+        while !shutdown.wait_timeout(interval) {
+            health.report(DaemonRole::Scheduler, &format!("running {}", name));
             if let Err(err) = exec() {
                 error!("failed to run scheduled task '{}': {:?}", name, err);
             }
-        })?;
+            health.report(DaemonRole::Scheduler, "idle");
+        }
+    })?;
     Ok(())
 }
 
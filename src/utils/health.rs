@@ -0,0 +1,138 @@
+//! Health of the roles of the daemons
+//!
+//! Each daemon reports the status of the roles it runs in the `daemon_roles` table, so that the
+//! roles running in other processes or hosts can be monitored from any web server.
+
+use crate::db::Pool;
+use crate::error::Result;
+use crate::utils::DaemonRole;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use log::error;
+use parking_lot::Mutex;
+use postgres::Connection;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// How often the daemons store the status of their roles.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Roles whose daemon didn't send a heartbeat for this long are considered dead.
+const HEARTBEAT_TIMEOUT_SECONDS: i64 = 2 * 60;
+
+/// Roles of daemons which stopped more than this long ago are forgotten.
+const FORGET_AFTER_DAYS: i32 = 1;
+
+/// Status of the roles of a daemon which exited.
+const STOPPED: &str = "stopped";
+
+/// Status of the roles run by this process, stored periodically by `start_heartbeat`.
+#[derive(Debug)]
+pub struct Health {
+    db: Pool,
+    worker_id: String,
+    roles: Mutex<BTreeMap<DaemonRole, (String, DateTime<Utc>)>>,
+}
+
+impl Health {
+    pub fn new(db: Pool, worker_id: &str) -> Self {
+        Health {
+            db,
+            worker_id: worker_id.to_string(),
+            roles: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Sets what the role is currently doing, like `idle` or `building`.
+    pub fn report(&self, role: DaemonRole, status: &str) {
+        let mut roles = self.roles.lock();
+        match roles.get(&role) {
+            Some((current, _)) if current == status => {}
+            _ => {
+                roles.insert(role, (status.to_string(), Utc::now()));
+            }
+        }
+    }
+
+    /// Reports all the roles as stopped, once the daemon is about to exit.
+    pub fn stop(&self) -> Result<()> {
+        let roles = self.roles.lock().keys().copied().collect::<Vec<_>>();
+        for role in roles {
+            self.report(role, STOPPED);
+        }
+        self.store()
+    }
+
+    pub(crate) fn store(&self) -> Result<()> {
+        let conn = self.db.get()?;
+        for (role, (status, since)) in &*self.roles.lock() {
+            conn.execute(
+                "INSERT INTO daemon_roles (worker_id, role, status, status_since, last_heartbeat)
+                 VALUES ($1, $2, $3, $4, NOW())
+                 ON CONFLICT (worker_id, role) DO UPDATE
+                 SET status = $3, status_since = $4, last_heartbeat = NOW();",
+                &[&self.worker_id, &role.as_str(), status, since],
+            )?;
+        }
+        conn.execute(
+            "DELETE FROM daemon_roles WHERE last_heartbeat < NOW() - make_interval(days => $1);",
+            &[&FORGET_AFTER_DAYS],
+        )?;
+        Ok(())
+    }
+}
+
+/// Stores the status of the roles of this process every `HEARTBEAT_INTERVAL`.
+pub fn start_heartbeat(health: Arc<Health>) -> Result<()> {
+    thread::Builder::new()
+        .name("health heartbeat".to_string())
+        .spawn(move || loop {
+            if let Err(e) = health.store() {
+                error!("Failed to store the health of the daemon: {}", e);
+            }
+            thread::sleep(HEARTBEAT_INTERVAL);
+        })?;
+    Ok(())
+}
+
+/// Status of a role of a daemon, as stored in the database.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct RoleHealth {
+    pub(crate) worker_id: String,
+    pub(crate) role: String,
+    pub(crate) status: String,
+    pub(crate) status_since: DateTime<Utc>,
+    pub(crate) last_heartbeat: DateTime<Utc>,
+    /// Whether the daemon is still running the role
+    pub(crate) healthy: bool,
+}
+
+/// Returns the health of the roles of all the daemons, grouped by role.
+pub(crate) fn load_health(conn: &Connection) -> Result<Vec<RoleHealth>> {
+    let rows = conn.query(
+        "SELECT worker_id, role, status, status_since, last_heartbeat
+         FROM daemon_roles
+         ORDER BY role, worker_id;",
+        &[],
+    )?;
+
+    let now = Utc::now();
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let status: String = row.get("status");
+            let last_heartbeat: DateTime<Utc> = row.get("last_heartbeat");
+            RoleHealth {
+                worker_id: row.get("worker_id"),
+                role: row.get("role"),
+                healthy: status != STOPPED
+                    && now - last_heartbeat < ChronoDuration::seconds(HEARTBEAT_TIMEOUT_SECONDS),
+                status,
+                status_since: row.get("status_since"),
+                last_heartbeat,
+            }
+        })
+        .collect())
+}
@@ -0,0 +1,129 @@
+use parking_lot::Mutex;
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+/// A cache shared between threads, evicting the least recently used value once it holds
+/// `capacity` values.
+pub(crate) struct LruCache<K, V> {
+    capacity: usize,
+    state: Mutex<CacheState<K, V>>,
+}
+
+struct CacheState<K, V> {
+    /// Incremented on every access, to find the least recently used value
+    clock: u64,
+    entries: HashMap<K, CacheEntry<V>>,
+}
+
+struct CacheEntry<V> {
+    value: V,
+    inserted_at: Instant,
+    last_used: u64,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> LruCache<K, V> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        LruCache {
+            capacity,
+            state: Mutex::new(CacheState {
+                clock: 0,
+                entries: HashMap::new(),
+            }),
+        }
+    }
+
+    pub(crate) fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_with_age(key).map(|(value, _)| value)
+    }
+
+    /// Returns the cached value, and how long ago it was inserted.
+    pub(crate) fn get_with_age<Q>(&self, key: &Q) -> Option<(V, Duration)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let state = &mut *self.state.lock();
+        let entry = state.entries.get_mut(key)?;
+        state.clock += 1;
+        entry.last_used = state.clock;
+        Some((entry.value.clone(), entry.inserted_at.elapsed()))
+    }
+
+    /// Caches the value, evicting the least recently used one if the cache is full.
+    pub(crate) fn insert(&self, key: K, value: V) {
+        let state = &mut *self.state.lock();
+        if state.entries.len() >= self.capacity && !state.entries.contains_key(&key) {
+            let oldest = state
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                state.entries.remove(&oldest);
+            }
+        }
+        state.clock += 1;
+        let entry = CacheEntry {
+            value,
+            inserted_at: Instant::now(),
+            last_used: state.clock,
+        };
+        state.entries.insert(key, entry);
+    }
+
+    pub(crate) fn invalidate<Q>(&self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.state.lock().entries.remove(key);
+    }
+}
+
+impl<K, V> fmt::Debug for LruCache<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LruCache")
+            .field("capacity", &self.capacity)
+            .field("len", &self.state.lock().entries.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_cache() {
+        let cache = LruCache::new(3);
+
+        cache.insert("a".to_string(), 1);
+        assert_eq!(cache.get("a"), Some(1));
+        assert_eq!(cache.get("b"), None);
+
+        // The least recently used value is evicted once the cache is full
+        cache.insert("b".to_string(), 2);
+        cache.insert("c".to_string(), 3);
+        assert_eq!(cache.get("a"), Some(1));
+        cache.insert("d".to_string(), 4);
+        assert_eq!(cache.get("a"), Some(1));
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("d"), Some(4));
+
+        // Replacing a value doesn't evict another one
+        cache.insert("d".to_string(), 5);
+        assert_eq!(cache.get("d"), Some(5));
+        assert_eq!(cache.get("c"), Some(3));
+        assert_eq!(cache.get("a"), Some(1));
+
+        cache.invalidate("d");
+        assert_eq!(cache.get("d"), None);
+    }
+}
@@ -2,13 +2,18 @@
 
 pub(crate) use self::cargo_metadata::{CargoMetadata, Package as MetadataPackage};
 pub(crate) use self::copy::copy_doc_dir;
-pub use self::daemon::start_daemon;
+pub use self::daemon::{start_daemon, DaemonRole};
 pub use self::github_updater::GithubUpdater;
+pub(crate) use self::health::load_health;
+pub use self::health::{start_heartbeat, Health};
 pub use self::html::extract_head_and_body;
+pub(crate) use self::lru_cache::LruCache;
 pub use self::queue::{get_crate_priority, remove_crate_priority, set_crate_priority};
 pub use self::queue_builder::queue_builder;
+pub use self::rebuild::{queue_rebuilds, BuildStatus, RebuildFilter, RebuildThrottle};
 pub use self::release_activity_updater::update_release_activity;
 pub(crate) use self::rustc_version::parse_rustc_version;
+pub use self::shutdown::{handle_signals, Shutdown};
 
 #[cfg(test)]
 pub(crate) use self::cargo_metadata::{Dependency, Target};
@@ -17,10 +22,14 @@ mod cargo_metadata;
 mod copy;
 mod daemon;
 mod github_updater;
+mod health;
 mod html;
+mod lru_cache;
 mod pubsubhubbub;
 mod queue;
 mod queue_builder;
+mod rebuild;
 mod release_activity_updater;
 mod rustc_version;
+mod shutdown;
 pub(crate) mod sized_buffer;
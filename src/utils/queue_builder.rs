@@ -1,19 +1,31 @@
+use crate::build_queue::QueueListener;
+use crate::utils::{DaemonRole, Health, Shutdown};
 use crate::{
-    db::Pool, docbuilder::RustwideBuilder, utils::pubsubhubbub, BuildQueue, DocBuilder, Storage,
+    db::Pool, docbuilder::RustwideBuilder, utils::pubsubhubbub, BuildQueue, Config, DocBuilder,
+    Storage,
 };
 use failure::Error;
 use log::{debug, error, info, warn};
 use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::sync::Arc;
-use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+/// How long the builder waits for new crates before checking the queue again, in case it missed
+/// the notifications.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How often the builder checks whether it's shutting down while waiting for new crates.
+const SHUTDOWN_CHECK_INTERVAL: Duration = Duration::from_secs(1);
 
 // TODO: change to `fn() -> Result<!, Error>` when never _finally_ stabilizes
 pub fn queue_builder(
     mut doc_builder: DocBuilder,
+    config: Arc<Config>,
     db: Pool,
     build_queue: Arc<BuildQueue>,
     storage: Arc<Storage>,
+    shutdown: Arc<Shutdown>,
+    health: Arc<Health>,
 ) -> Result<(), Error> {
     /// Represents the current state of the builder thread.
     enum BuilderState {
@@ -28,18 +40,31 @@ pub fn queue_builder(
         QueueInProgress(usize),
     }
 
-    let mut builder = RustwideBuilder::init(db, storage)?;
+    let mut builder = RustwideBuilder::init(config, db, storage)?;
 
     let mut status = BuilderState::Fresh;
+    let mut listener = None;
 
     loop {
         if !status.is_in_progress() {
-            thread::sleep(Duration::from_secs(60));
+            health.report(
+                DaemonRole::Builder,
+                match status {
+                    BuilderState::Locked => "locked",
+                    _ => "idle",
+                },
+            );
+            wait_for_crates(&build_queue, &mut listener, &shutdown);
+        }
+
+        if shutdown.is_requested() {
+            info!("Shutting down, not building any other crate");
+            return Ok(());
         }
 
-        // check lock file
+        // check whether the builds are paused
         if doc_builder.is_locked() {
-            warn!("Lock file exits, skipping building new crates");
+            warn!("Builds are paused, skipping building new crates");
             status = BuilderState::Locked;
             continue;
         }
@@ -108,12 +133,17 @@ pub fn queue_builder(
         // Run build_packages_queue under `catch_unwind` to catch panics
         // This only panicked twice in the last 6 months but its just a better
         // idea to do this.
+        health.report(DaemonRole::Builder, "building");
         let res = catch_unwind(AssertUnwindSafe(|| {
             match doc_builder.build_next_queue_package(&mut builder) {
                 Err(e) => error!("Failed to build crate from queue: {}", e),
                 Ok(crate_built) => {
                     if crate_built {
                         status.increment();
+                    } else {
+                        // All the queued crates are claimed by other builders
+                        debug!("No crate in the queue could be claimed, going back to sleep");
+                        status = BuilderState::EmptyQueue;
                     }
                 }
             }
@@ -124,6 +154,41 @@ pub fn queue_builder(
         }
     }
 
+    /// Waits until crates are added to the queue or the builder is shut down, falling back to
+    /// sleeping if the builder can't listen for new crates.
+    fn wait_for_crates(
+        build_queue: &BuildQueue,
+        listener: &mut Option<QueueListener>,
+        shutdown: &Shutdown,
+    ) {
+        if listener.is_none() {
+            match build_queue.listen() {
+                Ok(new) => *listener = Some(new),
+                Err(e) => error!("Failed to listen for new crates in the queue: {}", e),
+            }
+        }
+
+        let start = Instant::now();
+        while start.elapsed() < IDLE_TIMEOUT && !shutdown.is_requested() {
+            match listener {
+                Some(current) => match current.wait(SHUTDOWN_CHECK_INTERVAL) {
+                    Ok(true) => {
+                        debug!("Crates were added to the queue, waking up");
+                        return;
+                    }
+                    Ok(false) => {}
+                    Err(e) => {
+                        error!("Failed to wait for new crates in the queue: {}", e);
+                        *listener = None;
+                    }
+                },
+                None => {
+                    shutdown.wait_timeout(IDLE_TIMEOUT - start.elapsed());
+                }
+            }
+        }
+    }
+
     impl BuilderState {
         fn count(&self) -> usize {
             match *self {
@@ -133,10 +198,7 @@ pub fn queue_builder(
         }
 
         fn is_in_progress(&self) -> bool {
-            match *self {
-                BuilderState::QueueInProgress(_) => true,
-                _ => false,
-            }
+            matches!(self, BuilderState::QueueInProgress(_))
         }
 
         fn increment(&mut self) {
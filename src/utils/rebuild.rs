@@ -0,0 +1,266 @@
+//! Rebuilding releases in bulk, for example after fixing a bug in the toolchain
+
+use crate::error::Result;
+use crate::BuildQueue;
+use chrono::NaiveDate;
+use log::info;
+use postgres::types::ToSql;
+use postgres::Connection;
+use std::thread;
+use std::time::Duration;
+
+/// Status of the latest build of a release.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::EnumString, strum::EnumVariantNames)]
+#[strum(serialize_all = "snake_case")]
+pub enum BuildStatus {
+    Successful,
+    Failed,
+}
+
+/// Filters selecting the releases to rebuild. A release is selected if it matches all the
+/// filters which are set. Releases which aren't libraries are never selected, as they don't have
+/// any documentation.
+#[derive(Debug, Clone, Default)]
+pub struct RebuildFilter {
+    /// Only releases last built with a nightly toolchain older than this date
+    pub rustc_older_than: Option<NaiveDate>,
+    pub build_status: Option<BuildStatus>,
+    /// Pattern the name of the crates must match, with the `LIKE` syntax
+    pub name_pattern: Option<String>,
+    /// Only releases whose documentation wasn't built for this target
+    pub missing_target: Option<String>,
+    pub released_after: Option<NaiveDate>,
+    pub released_before: Option<NaiveDate>,
+}
+
+impl RebuildFilter {
+    /// Returns the name and the version of the releases matching the filter, the most recent
+    /// releases first.
+    pub fn select(&self, conn: &Connection) -> Result<Vec<(String, String)>> {
+        // Conditions with their parameter, written as `$?`
+        let mut filters: Vec<(&str, &dyn ToSql)> = Vec::new();
+        if let Some(date) = &self.rustc_older_than {
+            // The date of the nightly, like in `rustc 1.48.0-nightly (d006f5734 2020-08-28)`
+            filters.push((
+                "SUBSTRING(releases.doc_rustc_version FROM '\\d{4}-\\d{2}-\\d{2}')::DATE < $?",
+                date,
+            ));
+        }
+        let successful = self
+            .build_status
+            .map(|status| status == BuildStatus::Successful);
+        if let Some(successful) = &successful {
+            filters.push(("releases.build_status = $?", successful));
+        }
+        if let Some(pattern) = &self.name_pattern {
+            filters.push(("crates.name LIKE $?", pattern));
+        }
+        if let Some(target) = &self.missing_target {
+            filters.push(("NOT (releases.doc_targets::JSONB ? $?)", target));
+        }
+        // `release_time` is a `TIMESTAMP`, midnight of the dates is used
+        let released_after = self.released_after.map(|date| date.and_hms(0, 0, 0));
+        if let Some(time) = &released_after {
+            filters.push(("releases.release_time >= $?", time));
+        }
+        let released_before = self.released_before.map(|date| date.and_hms(0, 0, 0));
+        if let Some(time) = &released_before {
+            filters.push(("releases.release_time < $?", time));
+        }
+
+        let mut conditions = vec!["releases.is_library = TRUE".to_string()];
+        let mut params = Vec::new();
+        for (condition, param) in filters {
+            params.push(param);
+            conditions.push(condition.replace("$?", &format!("${}", params.len())));
+        }
+
+        let rows = conn.query(
+            &format!(
+                "SELECT crates.name, releases.version
+                 FROM releases
+                 INNER JOIN crates ON crates.id = releases.crate_id
+                 WHERE {}
+                 ORDER BY releases.release_time DESC, releases.id DESC",
+                conditions.join(" AND ")
+            ),
+            &params,
+        )?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.get("name"), row.get("version")))
+            .collect())
+    }
+}
+
+/// Pace at which the releases of a rebuild are added to the queue, so that they don't starve
+/// the builds of new releases.
+#[derive(Debug, Clone)]
+pub struct RebuildThrottle {
+    pub batch_size: usize,
+    /// Time to wait between two batches
+    pub delay: Duration,
+    /// Wait before adding a batch while the queue has at least this many pending crates
+    pub max_queued: Option<usize>,
+}
+
+/// Adds the releases to the build queue with `priority`, in batches. Releases which are already
+/// queued are skipped. `progress` is called after each batch with the number of releases
+/// processed and added so far.
+///
+/// Returns the number of releases added to the queue.
+pub fn queue_rebuilds(
+    queue: &BuildQueue,
+    releases: &[(String, String)],
+    priority: i32,
+    throttle: &RebuildThrottle,
+    mut progress: impl FnMut(usize, usize),
+) -> Result<usize> {
+    let mut processed = 0;
+    let mut added = 0;
+    for (i, batch) in releases.chunks(throttle.batch_size.max(1)).enumerate() {
+        if i > 0 {
+            thread::sleep(throttle.delay);
+        }
+        if let Some(max_queued) = throttle.max_queued {
+            while queue.pending_count()? >= max_queued {
+                info!(
+                    "waiting for the queue to have less than {} pending crates",
+                    max_queued
+                );
+                thread::sleep(throttle.delay.max(Duration::from_secs(1)));
+            }
+        }
+
+        added += queue.add_crates(batch, priority)?;
+        processed += batch.len();
+        progress(processed, added);
+    }
+    Ok(added)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::wrapper;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn test_select() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("foo")
+                .version("0.1.0")
+                .rustc_version("rustc 1.45.0-nightly (a74d1862d 2020-05-14)")
+                .release_time(Utc.ymd(2020, 5, 20).and_hms(0, 0, 0))
+                .create()?;
+            env.fake_release()
+                .name("foo")
+                .version("0.2.0")
+                .rustc_version("rustc 1.48.0-nightly (d006f5734 2020-08-28)")
+                .release_time(Utc.ymd(2020, 9, 1).and_hms(0, 0, 0))
+                .add_platform("x86_64-pc-windows-msvc")
+                .create()?;
+            env.fake_release()
+                .name("bar")
+                .version("1.0.0")
+                .rustc_version("rustc 1.48.0-nightly (d006f5734 2020-08-28)")
+                .release_time(Utc.ymd(2020, 10, 1).and_hms(0, 0, 0))
+                .build_result_successful(false)
+                .create()?;
+            env.fake_release()
+                .name("baz")
+                .version("1.0.0")
+                .binary(true)
+                .create()?;
+
+            let conn = env.db().conn();
+            let select = |filter: RebuildFilter| -> Result<Vec<String>> {
+                Ok(filter
+                    .select(&conn)?
+                    .into_iter()
+                    .map(|(name, version)| format!("{}-{}", name, version))
+                    .collect())
+            };
+
+            assert_eq!(
+                select(RebuildFilter::default())?,
+                vec!["bar-1.0.0", "foo-0.2.0", "foo-0.1.0"]
+            );
+            assert_eq!(
+                select(RebuildFilter {
+                    rustc_older_than: Some(NaiveDate::from_ymd(2020, 8, 1)),
+                    ..RebuildFilter::default()
+                })?,
+                vec!["foo-0.1.0"]
+            );
+            assert_eq!(
+                select(RebuildFilter {
+                    build_status: Some(BuildStatus::Failed),
+                    ..RebuildFilter::default()
+                })?,
+                vec!["bar-1.0.0"]
+            );
+            assert_eq!(
+                select(RebuildFilter {
+                    name_pattern: Some("fo%".into()),
+                    missing_target: Some("x86_64-pc-windows-msvc".into()),
+                    ..RebuildFilter::default()
+                })?,
+                vec!["foo-0.1.0"]
+            );
+            assert_eq!(
+                select(RebuildFilter {
+                    released_after: Some(NaiveDate::from_ymd(2020, 6, 1)),
+                    released_before: Some(NaiveDate::from_ymd(2020, 10, 1)),
+                    ..RebuildFilter::default()
+                })?,
+                vec!["foo-0.2.0"]
+            );
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_queue_rebuilds() {
+        wrapper(|env| {
+            let queue = env.build_queue();
+            queue.add_crate("foo", "0.1.0", 0)?;
+
+            let releases = vec![
+                ("foo".to_string(), "0.1.0".to_string()),
+                ("foo".to_string(), "0.2.0".to_string()),
+                ("bar".to_string(), "1.0.0".to_string()),
+            ];
+            let throttle = RebuildThrottle {
+                batch_size: 2,
+                delay: Duration::from_secs(0),
+                max_queued: None,
+            };
+            let mut batches = Vec::new();
+            let added = queue_rebuilds(&queue, &releases, 10, &throttle, |processed, added| {
+                batches.push((processed, added))
+            })?;
+            assert_eq!(added, 2);
+            assert_eq!(batches, vec![(2, 1), (3, 2)]);
+
+            let queued = queue
+                .queued_crates()?
+                .into_iter()
+                .map(|krate| (krate.name, krate.version, krate.priority))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                queued,
+                vec![
+                    ("foo".to_string(), "0.1.0".to_string(), 0),
+                    ("foo".to_string(), "0.2.0".to_string(), 10),
+                    ("bar".to_string(), "1.0.0".to_string(), 10),
+                ]
+            );
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,107 @@
+//! Graceful shutdown of the daemon
+//!
+//! The first SIGTERM or SIGINT received by the daemon requests a shutdown: the builder stops
+//! claiming crates from the queue, and the daemon exits once the crate being built is done. A
+//! second signal exits immediately.
+
+use crate::error::Result;
+use futures::{Future, Stream};
+use log::{error, warn};
+use parking_lot::{Condvar, Mutex};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Shared between the threads of the daemon to know when they should stop.
+#[derive(Debug, Default)]
+pub struct Shutdown {
+    requested: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl Shutdown {
+    pub fn request(&self) {
+        *self.requested.lock() = true;
+        self.condvar.notify_all();
+    }
+
+    pub fn is_requested(&self) -> bool {
+        *self.requested.lock()
+    }
+
+    /// Sleeps for `timeout`, waking up early if a shutdown is requested. Returns whether a
+    /// shutdown was requested.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let mut requested = self.requested.lock();
+        if !*requested {
+            self.condvar.wait_for(&mut requested, timeout);
+        }
+        *requested
+    }
+
+    /// Blocks until a shutdown is requested.
+    pub fn wait(&self) {
+        let mut requested = self.requested.lock();
+        while !*requested {
+            self.condvar.wait(&mut requested);
+        }
+    }
+}
+
+/// Requests a shutdown when the process receives SIGTERM or SIGINT.
+pub fn handle_signals(shutdown: Arc<Shutdown>) -> Result<()> {
+    let signals = tokio_signal::ctrl_c().wait()?;
+    #[cfg(unix)]
+    let signals = {
+        use tokio_signal::unix::{Signal, SIGTERM};
+        signals.select(Signal::new(SIGTERM).wait()?.map(|_| ()))
+    };
+
+    thread::Builder::new()
+        .name("signal handler".to_string())
+        .spawn(move || {
+            for signal in signals.wait() {
+                if let Err(e) = signal {
+                    error!("Failed to receive signals: {}", e);
+                    return;
+                }
+                if shutdown.is_requested() {
+                    warn!("Received a second signal, exiting immediately");
+                    std::process::exit(1);
+                }
+                warn!("Received a signal, shutting down once the current build is done");
+                shutdown.request();
+            }
+        })?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn test_wait_timeout() {
+        let shutdown = Arc::new(Shutdown::default());
+        assert!(!shutdown.wait_timeout(Duration::from_millis(10)));
+
+        let requester = {
+            let shutdown = shutdown.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(100));
+                shutdown.request();
+            })
+        };
+        let start = Instant::now();
+        assert!(shutdown.wait_timeout(Duration::from_secs(10)));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        requester.join().unwrap();
+
+        // Once requested, the shutdown doesn't wait anymore
+        assert!(shutdown.is_requested());
+        assert!(shutdown.wait_timeout(Duration::from_secs(10)));
+        shutdown.wait();
+    }
+}
@@ -0,0 +1,427 @@
+//! Versioned JSON API exposing the metadata of crates and releases
+//!
+//! The responses of the `/-/api/v1` endpoints are built from dedicated structs instead of the ones
+//! used to render the HTML pages, so that the schema doesn't change when the templates do. Fields
+//! can be added to them, but existing fields must not be renamed or removed.
+
+use super::{match_version, redirect_base, MatchSemver};
+use crate::db::Pool;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use iron::headers::{AccessControlAllowOrigin, ContentType};
+use iron::{status, IronResult, Request, Response, Url};
+use postgres::Connection;
+use router::Router;
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct CrateResponse {
+    name: String,
+    releases: Vec<ReleaseSummary>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct ReleaseSummary {
+    version: String,
+    build_status: bool,
+    rustdoc_status: bool,
+    yanked: bool,
+    release_time: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct ReleaseResponse {
+    name: String,
+    version: String,
+    description: Option<String>,
+    authors: Vec<String>,
+    keywords: Vec<String>,
+    license: Option<String>,
+    repository_url: Option<String>,
+    homepage_url: Option<String>,
+    documentation_url: Option<String>,
+    release_time: DateTime<Utc>,
+    yanked: bool,
+    is_library: bool,
+    target_name: Option<String>,
+    build_status: bool,
+    rustdoc_status: bool,
+    default_target: String,
+    doc_targets: Vec<String>,
+    dependencies: Vec<Dependency>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct Dependency {
+    name: String,
+    req: String,
+    kind: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct ErrorResponse {
+    error: &'static str,
+}
+
+/// Handler for `/-/api/v1/crate/:name`, listing all the releases of a crate.
+pub(super) fn crate_handler(req: &mut Request) -> IronResult<Response> {
+    let name = cexpect!(req, extension!(req, Router).find("name"));
+    let conn = extension!(req, Pool).get()?;
+
+    let rows = ctry!(
+        req,
+        conn.query(
+            "SELECT crates.name,
+                    releases.version,
+                    releases.build_status,
+                    releases.rustdoc_status,
+                    releases.yanked,
+                    releases.release_time
+             FROM crates
+             INNER JOIN releases ON releases.crate_id = crates.id
+             WHERE crates.name = $1",
+            &[&name],
+        )
+    );
+    if rows.is_empty() {
+        return Ok(not_found("crate not found"));
+    }
+
+    let mut releases = rows
+        .iter()
+        .map(|row| ReleaseSummary {
+            version: row.get("version"),
+            build_status: row.get("build_status"),
+            rustdoc_status: row.get("rustdoc_status"),
+            yanked: row.get("yanked"),
+            release_time: DateTime::from_utc(row.get::<_, NaiveDateTime>("release_time"), Utc),
+        })
+        .collect::<Vec<_>>();
+
+    // Newest releases first, with the versions that aren't valid semver at the end.
+    releases.sort_by_cached_key(|release| semver::Version::parse(&release.version).ok());
+    releases.reverse();
+
+    Ok(json_response(
+        status::Ok,
+        &CrateResponse {
+            name: rows.get(0).get("name"),
+            releases,
+        },
+    ))
+}
+
+/// Handler for `/-/api/v1/crate/:name/:version`, returning the metadata of a release.
+///
+/// Version requirements like `latest` or `~1.2` redirect to the matching version.
+pub(super) fn release_handler(req: &mut Request) -> IronResult<Response> {
+    let router = extension!(req, Router);
+    let name = cexpect!(req, router.find("name"));
+    let req_version = router.find("version");
+    let conn = extension!(req, Pool).get()?;
+
+    match match_version(&conn, name, req_version).and_then(|m| m.assume_exact()) {
+        Some(MatchSemver::Exact((_, release_id))) => {
+            let release = ctry!(req, load_release(&conn, release_id));
+            Ok(json_response(status::Ok, &release))
+        }
+
+        Some(MatchSemver::Semver((version, _))) => {
+            let url = ctry!(
+                req,
+                Url::parse(&format!(
+                    "{}/-/api/v1/crate/{}/{}",
+                    redirect_base(req),
+                    name,
+                    version
+                )),
+            );
+
+            Ok(super::redirect(url))
+        }
+
+        None => Ok(not_found("release not found")),
+    }
+}
+
+fn load_release(conn: &Connection, release_id: i32) -> Result<ReleaseResponse, failure::Error> {
+    let rows = conn.query(
+        "SELECT crates.name,
+                releases.version,
+                releases.description,
+                releases.authors,
+                releases.keywords,
+                releases.license,
+                releases.repository_url,
+                releases.homepage_url,
+                releases.documentation_url,
+                releases.release_time,
+                releases.yanked,
+                releases.is_library,
+                releases.target_name,
+                releases.build_status,
+                releases.rustdoc_status,
+                releases.default_target,
+                releases.doc_targets,
+                releases.dependencies
+         FROM releases
+         INNER JOIN crates ON crates.id = releases.crate_id
+         WHERE releases.id = $1",
+        &[&release_id],
+    )?;
+    let row = rows
+        .iter()
+        .next()
+        .ok_or_else(|| failure::err_msg("the matched release doesn't exist"))?;
+
+    Ok(ReleaseResponse {
+        name: row.get("name"),
+        version: row.get("version"),
+        description: row.get("description"),
+        authors: strings(row.get("authors")),
+        keywords: strings(row.get("keywords")),
+        license: row.get("license"),
+        repository_url: row.get("repository_url"),
+        homepage_url: row.get("homepage_url"),
+        documentation_url: row.get("documentation_url"),
+        release_time: DateTime::from_utc(row.get::<_, NaiveDateTime>("release_time"), Utc),
+        yanked: row.get("yanked"),
+        is_library: row.get("is_library"),
+        target_name: row.get("target_name"),
+        build_status: row.get("build_status"),
+        rustdoc_status: row.get("rustdoc_status"),
+        default_target: row.get("default_target"),
+        doc_targets: strings(row.get("doc_targets")),
+        dependencies: dependencies(row.get("dependencies")),
+    })
+}
+
+/// Converts a JSON array of strings stored in the database.
+fn strings(value: Option<Value>) -> Vec<String> {
+    value
+        .as_ref()
+        .and_then(Value::as_array)
+        .map(|array| {
+            array
+                .iter()
+                .filter_map(|item| item.as_str().map(String::from))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Converts the `[name, req, kind]` arrays stored in the database. Releases added before the
+/// kind of dependencies was recorded only contain `[name, req]`.
+fn dependencies(value: Option<Value>) -> Vec<Dependency> {
+    value
+        .as_ref()
+        .and_then(Value::as_array)
+        .map(|array| {
+            array
+                .iter()
+                .filter_map(|dep| {
+                    let dep = dep.as_array()?;
+                    Some(Dependency {
+                        name: dep.first()?.as_str()?.into(),
+                        req: dep.get(1)?.as_str()?.into(),
+                        kind: dep
+                            .get(2)
+                            .and_then(Value::as_str)
+                            .unwrap_or("normal")
+                            .into(),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn not_found(error: &'static str) -> Response {
+    json_response(status::NotFound, &ErrorResponse { error })
+}
+
+fn json_response(status: status::Status, body: &impl Serialize) -> Response {
+    let mut resp = Response::with((status, serde_json::to_string(body).unwrap()));
+    resp.headers.set(ContentType::json());
+    resp.headers.set(AccessControlAllowOrigin::Any);
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::wrapper;
+    use chrono::TimeZone;
+    use reqwest::StatusCode;
+    use serde_json::json;
+
+    #[test]
+    fn test_release() {
+        wrapper(|env| {
+            let release_time = Utc.ymd(2020, 4, 16).and_hms(8, 30, 0);
+            env.fake_release()
+                .name("foo")
+                .version("0.1.0")
+                .release_time(release_time)
+                .add_platform("x86_64-pc-windows-msvc")
+                .create()?;
+
+            let resp = env.frontend().get("/-/api/v1/crate/foo/0.1.0").send()?;
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert_eq!(resp.headers()["Content-Type"], "application/json");
+            assert_eq!(
+                resp.json::<Value>()?,
+                json!({
+                    "name": "foo",
+                    "version": "0.1.0",
+                    "description": "Fake package",
+                    "authors": ["Fake Person <fake@example.com>"],
+                    "keywords": ["fake", "package"],
+                    "license": "MIT",
+                    "repository_url": "https://git.example.com",
+                    "homepage_url": "https://www.example.com",
+                    "documentation_url": "https://docs.example.com",
+                    "release_time": release_time,
+                    "yanked": false,
+                    "is_library": true,
+                    "target_name": "foo",
+                    "build_status": true,
+                    "rustdoc_status": true,
+                    "default_target": "x86_64-unknown-linux-gnu",
+                    "doc_targets": ["x86_64-pc-windows-msvc"],
+                    "dependencies": [
+                        {"name": "fake-dependency", "req": "^1.0.0", "kind": "normal"},
+                    ],
+                }),
+            );
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_release_version_requirement() {
+        wrapper(|env| {
+            env.fake_release().name("foo").version("0.1.0").create()?;
+            env.fake_release().name("foo").version("0.2.0").create()?;
+
+            let web = env.frontend();
+            for (version, expected) in &[("latest", "0.2.0"), ("~0.1", "0.1.0")] {
+                let resp = web
+                    .get(&format!("/-/api/v1/crate/foo/{}", version))
+                    .send()?;
+                assert_eq!(resp.status(), StatusCode::OK);
+                assert!(resp
+                    .url()
+                    .path()
+                    .ends_with(&format!("/-/api/v1/crate/foo/{}", expected)));
+                assert_eq!(resp.json::<Value>()?["version"], *expected);
+            }
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_crate() {
+        wrapper(|env| {
+            env.fake_release().name("foo").version("0.1.0").create()?;
+            env.fake_release()
+                .name("foo")
+                .version("0.10.0")
+                .build_result_successful(false)
+                .create()?;
+            env.fake_release()
+                .name("foo")
+                .version("0.2.0")
+                .yanked(true)
+                .create()?;
+
+            let resp = env.frontend().get("/-/api/v1/crate/foo").send()?;
+            assert_eq!(resp.status(), StatusCode::OK);
+
+            let body = resp.json::<Value>()?;
+            assert_eq!(body["name"], "foo");
+            let releases = body["releases"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|release| {
+                    (
+                        release["version"].as_str().unwrap(),
+                        release["build_status"].as_bool().unwrap(),
+                        release["yanked"].as_bool().unwrap(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                releases,
+                vec![
+                    ("0.10.0", false, false),
+                    ("0.2.0", true, true),
+                    ("0.1.0", true, false),
+                ]
+            );
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_not_found() {
+        wrapper(|env| {
+            env.fake_release().name("foo").version("0.1.0").create()?;
+
+            let web = env.frontend();
+            for url in &[
+                "/-/api/v1/crate/bar",
+                "/-/api/v1/crate/bar/0.1.0",
+                "/-/api/v1/crate/foo/0.2.0",
+            ] {
+                let resp = web.get(url).send()?;
+                assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+                assert!(resp.json::<Value>()?["error"].is_string());
+            }
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_crate_named_api() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("api")
+                .version("0.1.0")
+                .rustdoc_file("api/index.html", b"<html>api docs</html>")
+                .create()?;
+
+            // The JSON API doesn't hide the documentation of a crate named `api`
+            let resp = env.frontend().get("/api/0.1.0/api/").send()?;
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert!(resp.text()?.contains("api docs"));
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_dependencies_without_kind() {
+        assert_eq!(
+            dependencies(Some(json!([["foo", "^1.0"], ["bar", "^2.0", "dev"]]))),
+            vec![
+                Dependency {
+                    name: "foo".into(),
+                    req: "^1.0".into(),
+                    kind: "normal".into(),
+                },
+                Dependency {
+                    name: "bar".into(),
+                    req: "^2.0".into(),
+                    kind: "dev".into(),
+                },
+            ]
+        );
+        assert!(dependencies(None).is_empty());
+    }
+}
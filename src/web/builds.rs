@@ -1,20 +1,28 @@
 use crate::{
     db::Pool,
-    docbuilder::Limits,
+    docbuilder::{DocWarning, Limits},
     impl_webpage,
     web::{page::WebPage, MetaData},
 };
 use chrono::{DateTime, NaiveDateTime, Utc};
+use failure::Error;
 use iron::{
     headers::{
         AccessControlAllowOrigin, CacheControl, CacheDirective, ContentType, Expires, HttpDate,
     },
     status, IronResult, Request, Response,
 };
+use postgres::Connection;
 use router::Router;
 use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+/// Warnings of a build, grouped by file and then by lint. Warnings without a lint are grouped
+/// under an empty name.
+type GroupedWarnings = BTreeMap<String, BTreeMap<String, Vec<DocWarning>>>;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub(crate) struct Build {
     id: i32,
     rustc_version: String,
@@ -22,13 +30,29 @@ pub(crate) struct Build {
     build_status: bool,
     build_time: DateTime<Utc>,
     output: Option<String>,
+    resources: BuildResources,
+    /// Number of documentation warnings which weren't stored, as there were too many
+    omitted_doc_warnings: i32,
+}
+
+/// Resources used by a build, which are unknown for the builds made before they were recorded.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct BuildResources {
+    duration_seconds: Option<f64>,
+    /// The limits of the sandbox when the build was made
+    limits: Option<Value>,
+    peak_memory_bytes: Option<i64>,
+    targets_attempted: Option<i32>,
+    targets_succeeded: Option<i32>,
+    docs_size_bytes: Option<i64>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 struct BuildsPage {
     metadata: Option<MetaData>,
     builds: Vec<Build>,
     build_details: Option<Build>,
+    doc_warnings: GroupedWarnings,
     limits: Limits,
 }
 
@@ -58,7 +82,14 @@ pub fn build_list_handler(req: &mut Request) -> IronResult<Response> {
                 builds.cratesfyi_version,
                 builds.build_status,
                 builds.build_time,
-                builds.output
+                builds.output,
+                builds.duration_seconds,
+                builds.limits,
+                builds.peak_memory_bytes,
+                builds.targets_attempted,
+                builds.targets_succeeded,
+                builds.docs_size_bytes,
+                builds.omitted_doc_warnings
          FROM builds
          INNER JOIN releases ON releases.id = builds.rid
          INNER JOIN crates ON releases.crate_id = crates.id
@@ -82,6 +113,15 @@ pub fn build_list_handler(req: &mut Request) -> IronResult<Response> {
                 build_status: row.get("build_status"),
                 build_time: DateTime::from_utc(row.get::<_, NaiveDateTime>("build_time"), Utc),
                 output: row.get("output"),
+                resources: BuildResources {
+                    duration_seconds: row.get("duration_seconds"),
+                    limits: row.get("limits"),
+                    peak_memory_bytes: row.get("peak_memory_bytes"),
+                    targets_attempted: row.get("targets_attempted"),
+                    targets_succeeded: row.get("targets_succeeded"),
+                    docs_size_bytes: row.get("docs_size_bytes"),
+                },
+                omitted_doc_warnings: row.get("omitted_doc_warnings"),
             };
 
             if id == req_build_id {
@@ -110,12 +150,230 @@ pub fn build_list_handler(req: &mut Request) -> IronResult<Response> {
 
         Ok(resp)
     } else {
+        let doc_warnings = match &build_details {
+            Some(build) => ctry!(req, get_doc_warnings(&conn, build.id)),
+            None => GroupedWarnings::new(),
+        };
+
         BuildsPage {
-            metadata: MetaData::from_crate(&conn, &name, &version),
+            metadata: MetaData::from_crate(&conn, name, version),
             builds,
             build_details,
+            doc_warnings,
             limits,
         }
         .into_response(req)
     }
 }
+
+fn get_doc_warnings(conn: &Connection, build_id: i32) -> Result<GroupedWarnings, Error> {
+    let mut warnings = GroupedWarnings::new();
+    for row in &conn.query(
+        "SELECT target, level, lint, message, file, line
+         FROM doc_warnings
+         WHERE build_id = $1
+         ORDER BY file, lint, line, target",
+        &[&build_id],
+    )? {
+        let warning = DocWarning {
+            target: row.get("target"),
+            level: row.get("level"),
+            lint: row.get("lint"),
+            message: row.get("message"),
+            file: row.get("file"),
+            line: row.get("line"),
+        };
+        warnings
+            .entry(warning.file.clone().unwrap_or_default())
+            .or_default()
+            .entry(warning.lint.clone().unwrap_or_default())
+            .or_default()
+            .push(warning);
+    }
+
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::wrapper;
+    use kuchiki::traits::TendrilSink;
+
+    fn warning(lint: Option<&str>, file: &str, line: i32, message: &str) -> DocWarning {
+        DocWarning {
+            target: "x86_64-unknown-linux-gnu".into(),
+            level: "warning".into(),
+            lint: lint.map(|lint| lint.into()),
+            message: message.into(),
+            file: Some(file.into()),
+            line: Some(line),
+        }
+    }
+
+    #[test]
+    fn test_doc_warnings_grouped() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("foo")
+                .version("0.1.0")
+                .doc_warning(warning(
+                    Some("missing_docs"),
+                    "src/lib.rs",
+                    12,
+                    "missing docs",
+                ))
+                .doc_warning(warning(
+                    Some("broken_intra_doc_links"),
+                    "src/lib.rs",
+                    3,
+                    "unresolved link to `Bar`",
+                ))
+                .doc_warning(warning(None, "src/foo.rs", 1, "unclosed HTML tag"))
+                .create()?;
+
+            let conn = env.db().conn();
+            let build_id: i32 = conn.query("SELECT id FROM builds", &[])?.get(0).get(0);
+            let warnings = get_doc_warnings(&conn, build_id)?;
+            assert_eq!(
+                warnings.keys().cloned().collect::<Vec<_>>(),
+                vec!["src/foo.rs", "src/lib.rs"]
+            );
+            assert_eq!(
+                warnings["src/lib.rs"].keys().cloned().collect::<Vec<_>>(),
+                vec!["broken_intra_doc_links", "missing_docs"]
+            );
+            assert_eq!(warnings["src/foo.rs"][""][0].message, "unclosed HTML tag");
+
+            let page = kuchiki::parse_html().one(
+                env.frontend()
+                    .get(&format!("/crate/foo/0.1.0/builds/{}", build_id))
+                    .send()?
+                    .text()?,
+            );
+            let items = page
+                .select("ul.doc-warnings ul ul li")
+                .unwrap()
+                .map(|node| node.text_contents().trim().to_string())
+                .collect::<Vec<_>>();
+            assert_eq!(
+                items,
+                vec![
+                    "line 1: unclosed HTML tag (x86_64-unknown-linux-gnu)",
+                    "line 3: unresolved link to `Bar` (x86_64-unknown-linux-gnu)",
+                    "line 12: missing docs (x86_64-unknown-linux-gnu)",
+                ]
+            );
+
+            // The warnings are only shown along with the details of a build
+            let page = env
+                .frontend()
+                .get("/crate/foo/0.1.0/builds")
+                .send()?
+                .text()?;
+            assert!(!page.contains("doc-warnings"));
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_omitted_doc_warnings() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("foo")
+                .version("0.1.0")
+                .doc_warning(warning(
+                    Some("missing_docs"),
+                    "src/lib.rs",
+                    1,
+                    "missing docs",
+                ))
+                .omitted_doc_warnings(1234)
+                .create()?;
+
+            let conn = env.db().conn();
+            let build_id: i32 = conn.query("SELECT id FROM builds", &[])?.get(0).get(0);
+            let page = env
+                .frontend()
+                .get(&format!("/crate/foo/0.1.0/builds/{}", build_id))
+                .send()?
+                .text()?;
+            assert!(page.contains("1234 more warnings were emitted but not stored."));
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_build_resources() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("foo")
+                .version("0.1.0")
+                .build_resources(crate::docbuilder::BuildResources {
+                    duration: Some(std::time::Duration::from_secs(90)),
+                    limits: Some(Limits::default()),
+                    peak_memory: Some(1024 * 1024),
+                    targets_attempted: 3,
+                    targets_succeeded: 2,
+                    docs_size: None,
+                })
+                .create()?;
+            env.fake_release().name("foo").version("0.2.0").create()?;
+
+            let conn = env.db().conn();
+            let rows = conn.query("SELECT id FROM builds ORDER BY id", &[])?;
+            let (with_resources, without_resources): (i32, i32) =
+                (rows.get(0).get(0), rows.get(1).get(0));
+
+            let page = kuchiki::parse_html().one(
+                env.frontend()
+                    .get(&format!("/crate/foo/0.1.0/builds/{}", with_resources))
+                    .send()?
+                    .text()?,
+            );
+            let rows = page
+                .select_first(".build-resources table")
+                .unwrap()
+                .as_node()
+                .select("tr")
+                .unwrap()
+                .map(|row| {
+                    row.text_contents()
+                        .split_whitespace()
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                rows,
+                vec![
+                    "Duration 1.5 minutes",
+                    "Peak memory usage 1 MB",
+                    "Targets built 2 of 3",
+                ]
+            );
+            // The limits of the build are shown along with the resources
+            assert_eq!(page.select(".build-resources table").unwrap().count(), 2);
+
+            // Builds made before the resources were recorded don't show them
+            let page = env
+                .frontend()
+                .get(&format!("/crate/foo/0.2.0/builds/{}", without_resources))
+                .send()?
+                .text()?;
+            assert!(!page.contains("build-resources"));
+
+            let builds: Value = env
+                .frontend()
+                .get("/crate/foo/0.1.0/builds.json")
+                .send()?
+                .json()?;
+            assert_eq!(builds[0]["resources"]["targets_succeeded"], 2);
+            assert_eq!(builds[0]["resources"]["limits"]["targets"], 10);
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,107 @@
+//! Checking the docs.rs metadata of a manifest before publishing a crate
+
+use crate::docbuilder::check_metadata;
+use iron::headers::ContentType;
+use iron::{status, IronResult, Request, Response};
+use std::io::Read;
+
+/// Manifests larger than this are rejected, crates.io doesn't accept them either.
+const MAX_MANIFEST_SIZE: u64 = 512 * 1024;
+
+/// Handler for `POST /crate/check-metadata`, receiving the content of a `Cargo.toml` and
+/// responding with the warnings about its `[package.metadata.docs.rs]` table, along with the
+/// targets and the flags docs.rs would use to build the documentation.
+///
+/// ```text
+/// curl --data-binary @Cargo.toml https://docs.rs/crate/check-metadata
+/// ```
+pub(super) fn check_metadata_handler(req: &mut Request) -> IronResult<Response> {
+    let mut manifest = Vec::new();
+    ctry!(
+        req,
+        req.body
+            .by_ref()
+            .take(MAX_MANIFEST_SIZE + 1)
+            .read_to_end(&mut manifest)
+    );
+    if manifest.len() as u64 > MAX_MANIFEST_SIZE {
+        return Ok(Response::with((
+            status::PayloadTooLarge,
+            "the manifest is too large",
+        )));
+    }
+    let manifest = match String::from_utf8(manifest) {
+        Ok(manifest) => manifest,
+        Err(_) => {
+            return Ok(Response::with((
+                status::BadRequest,
+                "the manifest is not valid UTF-8",
+            )))
+        }
+    };
+
+    let mut resp = Response::with((status::Ok, check_metadata(&manifest).to_string()));
+    resp.headers.set(ContentType::plaintext());
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test::wrapper;
+
+    #[test]
+    fn test_check_metadata() {
+        wrapper(|env| {
+            let manifest = r#"
+                [package]
+                name = "dummy"
+
+                [package.metadata.docs.rs]
+                all_features = true
+                targets = ["x86_64-pc-windows-msvc"]
+                rustdoc-args = ["--cfg", "docsrs"]
+            "#;
+            let resp = env
+                .frontend()
+                .post("/crate/check-metadata")
+                .body(manifest)
+                .send()?;
+            assert!(resp.status().is_success());
+            let text = resp.text()?;
+
+            assert!(text.contains(
+                "warning: unknown key `package.metadata.docs.rs.all_features`, \
+                 did you mean `all-features`?"
+            ));
+            assert!(text.contains("default target: x86_64-pc-windows-msvc\n"));
+            assert!(text.contains(
+                "    cargo doc --lib --no-deps --message-format=json \
+                 --target x86_64-pc-windows-msvc\n"
+            ));
+            assert!(text.contains("RUSTDOCFLAGS=-Z unstable-options"));
+            assert!(text.contains("--cap-lints warn --cfg docsrs\n"));
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_check_metadata_invalid_body() {
+        wrapper(|env| {
+            let web = env.frontend();
+            let resp = web
+                .post("/crate/check-metadata")
+                .body(vec![0xff, 0xfe])
+                .send()?;
+            assert_eq!(resp.status(), 400);
+
+            let resp = web
+                .post("/crate/check-metadata")
+                .body(vec![b'#'; 1024 * 1024])
+                .send()?;
+            assert_eq!(resp.status(), 413);
+
+            Ok(())
+        });
+    }
+}
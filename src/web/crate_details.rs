@@ -33,7 +33,7 @@ pub struct CrateDetails {
     keywords: Option<Value>,
     have_examples: bool, // need to check this manually
     pub target_name: String,
-    releases: Vec<Release>,
+    pub(crate) releases: Vec<Release>,
     github: bool, // is crate hosted in github
     github_stars: Option<i32>,
     github_forks: Option<i32>,
@@ -44,18 +44,19 @@ pub struct CrateDetails {
     pub(crate) doc_targets: Vec<String>,
     license: Option<String>,
     documentation_url: Option<String>,
+    #[serde(skip)]
+    pub(crate) archive_storage: bool,
+    doc_coverage: Option<DocCoverage>,
 }
 
 fn optional_markdown<S>(markdown: &Option<String>, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
-    if let Some(ref markdown) = markdown {
-        Some(render_markdown(&markdown))
-    } else {
-        None
-    }
-    .serialize(serializer)
+    markdown
+        .as_ref()
+        .map(|markdown| render_markdown(markdown))
+        .serialize(serializer)
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize)]
@@ -64,6 +65,44 @@ pub struct Release {
     pub build_status: bool,
     pub yanked: bool,
     pub is_library: bool,
+    #[serde(skip)]
+    pub archive_storage: bool,
+}
+
+/// Documentation coverage of a release, summed over all its files
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct DocCoverage {
+    pub(crate) documented: i64,
+    pub(crate) total: i64,
+    /// Percentage of documented items
+    pub(crate) percent: f64,
+}
+
+impl DocCoverage {
+    /// Returns the coverage of a release, if it was measured and the crate has any items.
+    pub(crate) fn for_release(conn: &Connection, release_id: i32) -> Option<DocCoverage> {
+        let rows = conn
+            .query(
+                "SELECT SUM(documented) AS documented, SUM(total) AS total
+                 FROM doc_coverage
+                 WHERE release_id = $1",
+                &[&release_id],
+            )
+            .unwrap();
+        let row = rows.iter().next()?;
+
+        let documented: i64 = row.get::<_, Option<i64>>("documented")?;
+        let total: i64 = row.get::<_, Option<i64>>("total")?;
+        if total == 0 {
+            return None;
+        }
+
+        Some(DocCoverage {
+            documented,
+            total,
+            percent: documented as f64 * 100.0 / total as f64,
+        })
+    }
 }
 
 impl CrateDetails {
@@ -97,7 +136,8 @@ impl CrateDetails {
                 releases.doc_targets,
                 releases.license,
                 releases.documentation_url,
-                releases.default_target
+                releases.default_target,
+                releases.archive_storage
             FROM releases
             INNER JOIN crates ON releases.crate_id = crates.id
             WHERE crates.name = $1 AND releases.version = $2;";
@@ -118,14 +158,14 @@ impl CrateDetails {
             let versions: Vec<String> = krate.get("versions");
             let mut versions: Vec<semver::Version> = versions
                 .iter()
-                .filter_map(|version| semver::Version::parse(&version).ok())
+                .filter_map(|version| semver::Version::parse(version).ok())
                 .collect();
 
             versions.sort();
             versions.reverse();
             versions
                 .iter()
-                .map(|version| map_to_release(&conn, crate_id, version.to_string()))
+                .map(|version| map_to_release(conn, crate_id, version.to_string()))
                 .collect()
         };
 
@@ -147,7 +187,7 @@ impl CrateDetails {
                         .filter_map(|item| item.as_str().map(|s| s.to_owned()))
                         .collect()
                 })
-                .unwrap_or_else(Vec::new)
+                .unwrap_or_default()
         };
 
         let mut crate_details = CrateDetails {
@@ -180,6 +220,8 @@ impl CrateDetails {
             doc_targets,
             license: krate.get("license"),
             documentation_url: krate.get("documentation_url"),
+            archive_storage: krate.get("archive_storage"),
+            doc_coverage: DocCoverage::for_release(conn, release_id),
         };
 
         if let Some(repository_url) = crate_details.repository_url.clone() {
@@ -246,19 +288,21 @@ fn map_to_release(conn: &Connection, crate_id: i32, version: String) -> Release
         .query(
             "SELECT build_status,
                     yanked,
-                    is_library
+                    is_library,
+                    archive_storage
              FROM releases
              WHERE releases.crate_id = $1 and releases.version = $2;",
             &[&crate_id, &version],
         )
         .unwrap();
 
-    let (build_status, yanked, is_library) =
+    let (build_status, yanked, is_library, archive_storage) =
         rows.iter().next().map_or_else(Default::default, |row| {
             (
                 row.get("build_status"),
                 row.get("yanked"),
                 row.get("is_library"),
+                row.get("archive_storage"),
             )
         });
 
@@ -267,6 +311,7 @@ fn map_to_release(conn: &Connection, crate_id: i32, version: String) -> Release
         build_status,
         yanked,
         is_library,
+        archive_storage,
     }
 }
 
@@ -287,9 +332,9 @@ pub fn crate_details_handler(req: &mut Request) -> IronResult<Response> {
 
     let conn = extension!(req, Pool).get()?;
 
-    match match_version(&conn, &name, req_version).and_then(|m| m.assume_exact()) {
+    match match_version(&conn, name, req_version).and_then(|m| m.assume_exact()) {
         Some(MatchSemver::Exact((version, _))) => {
-            let details = cexpect!(req, CrateDetails::new(&conn, &name, &version));
+            let details = cexpect!(req, CrateDetails::new(&conn, name, &version));
 
             CrateDetailsPage { details }.into_response(req)
         }
@@ -359,11 +404,11 @@ mod tests {
                 .yanked(true)
                 .create()?;
 
-            assert_last_successful_build_equals(&db, "foo", "0.0.1", None)?;
-            assert_last_successful_build_equals(&db, "foo", "0.0.2", None)?;
-            assert_last_successful_build_equals(&db, "foo", "0.0.3", Some("0.0.2"))?;
-            assert_last_successful_build_equals(&db, "foo", "0.0.4", None)?;
-            assert_last_successful_build_equals(&db, "foo", "0.0.5", Some("0.0.2"))?;
+            assert_last_successful_build_equals(db, "foo", "0.0.1", None)?;
+            assert_last_successful_build_equals(db, "foo", "0.0.2", None)?;
+            assert_last_successful_build_equals(db, "foo", "0.0.3", Some("0.0.2"))?;
+            assert_last_successful_build_equals(db, "foo", "0.0.4", None)?;
+            assert_last_successful_build_equals(db, "foo", "0.0.5", Some("0.0.2"))?;
             Ok(())
         });
     }
@@ -389,9 +434,9 @@ mod tests {
                 .yanked(true)
                 .create()?;
 
-            assert_last_successful_build_equals(&db, "foo", "0.0.1", None)?;
-            assert_last_successful_build_equals(&db, "foo", "0.0.2", None)?;
-            assert_last_successful_build_equals(&db, "foo", "0.0.3", None)?;
+            assert_last_successful_build_equals(db, "foo", "0.0.1", None)?;
+            assert_last_successful_build_equals(db, "foo", "0.0.2", None)?;
+            assert_last_successful_build_equals(db, "foo", "0.0.3", None)?;
             Ok(())
         });
     }
@@ -414,10 +459,10 @@ mod tests {
                 .create()?;
             env.fake_release().name("foo").version("0.0.4").create()?;
 
-            assert_last_successful_build_equals(&db, "foo", "0.0.1", None)?;
-            assert_last_successful_build_equals(&db, "foo", "0.0.2", Some("0.0.4"))?;
-            assert_last_successful_build_equals(&db, "foo", "0.0.3", None)?;
-            assert_last_successful_build_equals(&db, "foo", "0.0.4", None)?;
+            assert_last_successful_build_equals(db, "foo", "0.0.1", None)?;
+            assert_last_successful_build_equals(db, "foo", "0.0.2", Some("0.0.4"))?;
+            assert_last_successful_build_equals(db, "foo", "0.0.3", None)?;
+            assert_last_successful_build_equals(db, "foo", "0.0.4", None)?;
             Ok(())
         });
     }
@@ -462,48 +507,56 @@ mod tests {
                         build_status: true,
                         yanked: false,
                         is_library: true,
+                        archive_storage: false,
                     },
                     Release {
                         version: "0.12.0".to_string(),
                         build_status: true,
                         yanked: false,
                         is_library: true,
+                        archive_storage: false,
                     },
                     Release {
                         version: "0.3.0".to_string(),
                         build_status: false,
                         yanked: false,
                         is_library: true,
+                        archive_storage: false,
                     },
                     Release {
                         version: "0.2.0".to_string(),
                         build_status: true,
                         yanked: true,
                         is_library: true,
+                        archive_storage: false,
                     },
                     Release {
                         version: "0.2.0-alpha".to_string(),
                         build_status: true,
                         yanked: false,
                         is_library: true,
+                        archive_storage: false,
                     },
                     Release {
                         version: "0.1.1".to_string(),
                         build_status: true,
                         yanked: false,
                         is_library: true,
+                        archive_storage: false,
                     },
                     Release {
                         version: "0.1.0".to_string(),
                         build_status: true,
                         yanked: false,
                         is_library: true,
+                        archive_storage: false,
                     },
                     Release {
                         version: "0.0.1".to_string(),
                         build_status: false,
                         yanked: false,
                         is_library: false,
+                        archive_storage: false,
                     },
                 ]
             );
@@ -610,4 +663,38 @@ mod tests {
             Ok(())
         });
     }
+
+    #[test]
+    fn test_doc_coverage() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("foo")
+                .version("0.1.0")
+                .doc_coverage("src/lib.rs", 6, 8)
+                .doc_coverage("src/bar.rs", 1, 4)
+                .create()?;
+            env.fake_release().name("foo").version("0.2.0").create()?;
+
+            let details = CrateDetails::new(&env.db().conn(), "foo", "0.1.0").unwrap();
+            assert_eq!(
+                details.doc_coverage,
+                Some(DocCoverage {
+                    documented: 7,
+                    total: 12,
+                    percent: 7.0 * 100.0 / 12.0,
+                })
+            );
+
+            let page =
+                kuchiki::parse_html().one(env.frontend().get("/crate/foo/0.1.0").send()?.text()?);
+            let coverage = page.select_first(".doc-coverage").unwrap();
+            assert_eq!(coverage.text_contents().trim(), "58.3% documented");
+
+            // Releases built without measuring the coverage don't show it
+            let page = env.frontend().get("/crate/foo/0.2.0").send()?.text()?;
+            assert!(!page.contains("doc-coverage"));
+
+            Ok(())
+        });
+    }
 }
@@ -0,0 +1,700 @@
+//! Differences between the public API of two versions of a crate
+//!
+//! The public items of a release are listed from the `all.html` page generated by rustdoc for
+//! its default target. When the rustdoc JSON output was also built for both versions, it's used
+//! to detect the items whose signature changed. The items of the most recently compared releases
+//! are kept in memory.
+
+use super::{
+    crate_details::{CrateDetails, Release},
+    error::Nope,
+    match_version, redirect_base,
+    rustdoc::rustdoc_file,
+    MatchSemver, MetaData,
+};
+use crate::{db::Pool, impl_webpage, utils::LruCache, web::page::WebPage, Config, Storage};
+use failure::Error;
+use iron::{status, IronError, IronResult, Request, Response, Url};
+use kuchiki::traits::TendrilSink;
+use postgres::Connection;
+use router::Router;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Number of releases whose items are kept in memory by `ApiItemsCache`.
+pub(super) const CACHED_RELEASES: usize = 50;
+
+/// A public item of a crate, like `struct foo::Bar`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+struct ApiItem {
+    /// Kind of the item, as used in the names of rustdoc pages, like `struct` or `fn`
+    kind: String,
+    path: String,
+    /// Path of the page of the item, relative to the documentation of the crate
+    href: String,
+}
+
+/// The public items of a release, keyed by their kind and path.
+type ApiItems = BTreeMap<(String, String), ApiItem>;
+
+/// A representation of the signature of the public items of a release, keyed like `ApiItems`.
+type Signatures = BTreeMap<(String, String), String>;
+
+/// The signatures of the public items of a release, read from rustdoc's JSON output.
+#[derive(Debug)]
+struct RustdocSignatures {
+    /// Version of the format of the JSON output. Signatures read from different formats can't be
+    /// compared, as the representation of the same item changes between formats.
+    format_version: Option<u64>,
+    items: Signatures,
+}
+
+/// Lists the items linked from an `all.html` page generated by rustdoc.
+fn parse_all_items(html: &str) -> ApiItems {
+    let dom = kuchiki::parse_html().one(html);
+    let mut items = ApiItems::new();
+
+    for link in dom.select("li > a").unwrap() {
+        let href = match link.attributes.borrow().get("href") {
+            Some(href) => href.to_string(),
+            None => continue,
+        };
+        let mut segments = href.split('/').collect::<Vec<_>>();
+        // Items pages are named `kind.Name.html`, other links are ignored
+        let page = segments.pop().unwrap_or_default();
+        let parts = page.split('.').collect::<Vec<_>>();
+        if parts.len() != 3
+            || parts[2] != "html"
+            || parts[0].is_empty()
+            || segments.iter().any(|s| s.is_empty() || *s == "..")
+        {
+            continue;
+        }
+
+        segments.push(parts[1]);
+        let item = ApiItem {
+            kind: parts[0].to_string(),
+            path: segments.join("::"),
+            href,
+        };
+        items.insert((item.kind.clone(), item.path.clone()), item);
+    }
+
+    items
+}
+
+/// Kind of an item in rustdoc's JSON output, as used in the names of rustdoc pages.
+fn html_kind(json_kind: &str) -> &str {
+    match json_kind {
+        "function" => "fn",
+        "typedef" | "type_alias" => "type",
+        "proc_attribute" => "attr",
+        "proc_derive" => "derive",
+        other => other,
+    }
+}
+
+/// Removes the ids and the lists of children of an item from its JSON representation, which
+/// change between versions even when the signature of the item doesn't.
+///
+/// The public fields and variants are part of the signature of their struct, union or enum, so
+/// their ids are replaced with their own representation, looked up in `index`.
+fn strip_ids(value: &mut Value, index: &Map<String, Value>) {
+    const IGNORED_KEYS: &[&str] = &[
+        "id",
+        "items",
+        "impls",
+        "implementations",
+        "implementors",
+        "links",
+    ];
+    const MEMBER_KEYS: &[&str] = &["fields", "variants"];
+    match value {
+        Value::Object(map) => {
+            for key in IGNORED_KEYS {
+                map.remove(*key);
+            }
+            for key in MEMBER_KEYS {
+                if let Some(Value::Array(members)) = map.get_mut(*key) {
+                    for member in members.iter_mut() {
+                        *member = resolve_member(member, index);
+                    }
+                }
+            }
+            map.values_mut().for_each(|value| strip_ids(value, index));
+        }
+        Value::Array(values) => values.iter_mut().for_each(|value| strip_ids(value, index)),
+        _ => {}
+    }
+}
+
+/// Returns the name and the representation of the field or variant with the id `member`.
+fn resolve_member(member: &Value, index: &Map<String, Value>) -> Value {
+    // Older versions of the JSON output use strings as ids, newer ones use integers
+    let id = match member {
+        Value::String(id) => id.clone(),
+        Value::Number(id) => id.to_string(),
+        // Fields hidden from the documentation are `null`
+        other => return other.clone(),
+    };
+    match index.get(&id) {
+        Some(item) => json!({ "name": item["name"], "inner": item["inner"] }),
+        None => Value::Null,
+    }
+}
+
+/// Returns the signatures of the public items listed in rustdoc's JSON output.
+fn parse_signatures(json: &[u8]) -> Result<RustdocSignatures, Error> {
+    let krate: Value = serde_json::from_slice(json)?;
+    let mut signatures = RustdocSignatures {
+        format_version: krate["format_version"].as_u64(),
+        items: BTreeMap::new(),
+    };
+
+    let (paths, index) = match (krate["paths"].as_object(), krate["index"].as_object()) {
+        (Some(paths), Some(index)) => (paths, index),
+        _ => return Ok(signatures),
+    };
+    for (id, summary) in paths {
+        // Only the items of the crate itself, not of its dependencies
+        if summary["crate_id"] != 0 {
+            continue;
+        }
+        let (kind, path) = match (summary["kind"].as_str(), summary["path"].as_array()) {
+            (Some(kind), Some(path)) => (kind, path),
+            _ => continue,
+        };
+        let mut inner = match index.get(id) {
+            Some(item) => item["inner"].clone(),
+            None => continue,
+        };
+        strip_ids(&mut inner, index);
+
+        // The first segment of the path is the name of the crate
+        let path = path
+            .iter()
+            .skip(1)
+            .filter_map(Value::as_str)
+            .collect::<Vec<_>>()
+            .join("::");
+        signatures
+            .items
+            .insert((html_kind(kind).to_string(), path), inner.to_string());
+    }
+
+    Ok(signatures)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct ApiDiff {
+    added: Vec<ApiItem>,
+    removed: Vec<ApiItem>,
+    changed: Vec<ApiItem>,
+    /// Whether the signatures of the items were compared, in addition to their presence
+    signatures_compared: bool,
+    /// Whether the signatures weren't compared because the JSON output of the two versions was
+    /// generated in different formats
+    json_formats_differ: bool,
+}
+
+impl ApiDiff {
+    fn new(from: &ApiItems, to: &ApiItems, signatures: Option<(&Signatures, &Signatures)>) -> Self {
+        let added = to
+            .iter()
+            .filter(|(key, _)| !from.contains_key(key))
+            .map(|(_, item)| item.clone())
+            .collect();
+        let removed = from
+            .iter()
+            .filter(|(key, _)| !to.contains_key(key))
+            .map(|(_, item)| item.clone())
+            .collect();
+        let changed = match signatures {
+            Some((from_signatures, to_signatures)) => to
+                .iter()
+                .filter(|(key, _)| from.contains_key(key))
+                .filter(
+                    |(key, _)| match (from_signatures.get(key), to_signatures.get(key)) {
+                        (Some(from), Some(to)) => from != to,
+                        _ => false,
+                    },
+                )
+                .map(|(_, item)| item.clone())
+                .collect(),
+            None => Vec::new(),
+        };
+
+        ApiDiff {
+            added,
+            removed,
+            changed,
+            signatures_compared: signatures.is_some(),
+            json_formats_differ: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct DiffPage {
+    /// The newer version, `to`
+    metadata: MetaData,
+    from: MetaData,
+    /// The other releases which the newer version can be compared with
+    releases: Vec<Release>,
+    diff: ApiDiff,
+}
+
+impl_webpage! {
+    DiffPage = "crate/diff.html",
+}
+
+/// The public items of a release, and the signatures of its items if the rustdoc JSON output was
+/// built for its default target.
+#[derive(Debug)]
+pub(super) struct ReleaseApi {
+    items: ApiItems,
+    signatures: Option<RustdocSignatures>,
+}
+
+/// Name, version and latest build of a release.
+pub(super) type ReleaseKey = (String, String, Option<i32>);
+
+/// The items of the most recently compared releases, so that the `all.html` page and the rustdoc
+/// JSON output of a release aren't fetched and parsed again by every diff including it.
+///
+/// Releases are keyed by their latest build, so the items of a rebuilt release are loaded again.
+pub(super) type ApiItemsCache = LruCache<ReleaseKey, Arc<ReleaseApi>>;
+
+fn latest_build(conn: &Connection, name: &str, version: &str) -> Result<Option<i32>, Error> {
+    let rows = conn.query(
+        "SELECT MAX(builds.id)
+         FROM builds
+         INNER JOIN releases ON releases.id = builds.rid
+         INNER JOIN crates ON releases.crate_id = crates.id
+         WHERE crates.name = $1 AND releases.version = $2",
+        &[&name, &version],
+    )?;
+    Ok(rows.get(0).get(0))
+}
+
+/// Loads the items of a release from the cache, or from the storage if they aren't cached.
+fn load_items(
+    details: &CrateDetails,
+    conn: &Connection,
+    cache: &ApiItemsCache,
+    storage: &Storage,
+    config: &Config,
+) -> Result<Arc<ReleaseApi>, Error> {
+    let key = (
+        details.metadata.name.clone(),
+        details.metadata.version.clone(),
+        latest_build(conn, &details.metadata.name, &details.metadata.version)?,
+    );
+    if let Some(api) = cache.get(&key) {
+        return Ok(api);
+    }
+
+    let req_path = [
+        "rustdoc",
+        &details.metadata.name,
+        &details.metadata.version,
+        &details.target_name,
+        "all.html",
+    ];
+    let all_items = rustdoc_file(&req_path, details.archive_storage, storage, config)?;
+    let items = parse_all_items(&String::from_utf8_lossy(&all_items.0.content));
+
+    let json_path = format!(
+        "json/{}/{}/{}/{}.json",
+        details.metadata.name,
+        details.metadata.version,
+        details.metadata.default_target,
+        details.target_name,
+    );
+    let signatures = match storage.get(&json_path, config.max_file_size) {
+        Ok(json) => Some(parse_signatures(&json.content)?),
+        Err(_) => None,
+    };
+
+    let api = Arc::new(ReleaseApi { items, signatures });
+    cache.insert(key, api.clone());
+    Ok(api)
+}
+
+/// Handler for `/crate/:name/diff/:from/:to`, listing the public items added, removed and
+/// changed between two versions.
+pub fn api_diff_handler(req: &mut Request) -> IronResult<Response> {
+    let router = extension!(req, Router);
+    let name = cexpect!(req, router.find("name"));
+    let req_from = cexpect!(req, router.find("from"));
+    let req_to = cexpect!(req, router.find("to"));
+    let conn = extension!(req, Pool).get()?;
+
+    let mut versions = Vec::with_capacity(2);
+    let mut redirect = false;
+    for req_version in &[req_from, req_to] {
+        match match_version(&conn, name, Some(req_version)).and_then(|m| m.assume_exact()) {
+            Some(MatchSemver::Exact((version, _))) => versions.push(version),
+            Some(MatchSemver::Semver((version, _))) => {
+                redirect = true;
+                versions.push(version);
+            }
+            None => return Err(IronError::new(Nope::CrateNotFound, status::NotFound)),
+        }
+    }
+    if redirect {
+        let url = ctry!(
+            req,
+            Url::parse(&format!(
+                "{}/crate/{}/diff/{}/{}",
+                redirect_base(req),
+                name,
+                versions[0],
+                versions[1],
+            )),
+        );
+        return Ok(super::redirect(url));
+    }
+
+    let from = cexpect!(req, CrateDetails::new(&conn, name, &versions[0]));
+    let to = cexpect!(req, CrateDetails::new(&conn, name, &versions[1]));
+    if !from.metadata.rustdoc_status || !to.metadata.rustdoc_status {
+        return Err(IronError::new(Nope::ResourceNotFound, status::NotFound));
+    }
+
+    let storage = extension!(req, Storage);
+    let config = extension!(req, Config);
+    let cache = extension!(req, ApiItemsCache);
+    let from_api = match load_items(&from, &conn, cache, storage, config) {
+        Ok(api) => api,
+        Err(_) => return Err(IronError::new(Nope::ResourceNotFound, status::NotFound)),
+    };
+    let to_api = match load_items(&to, &conn, cache, storage, config) {
+        Ok(api) => api,
+        Err(_) => return Err(IronError::new(Nope::ResourceNotFound, status::NotFound)),
+    };
+    let (signatures, json_formats_differ) = match (&from_api.signatures, &to_api.signatures) {
+        (Some(from), Some(to)) if from.format_version == to.format_version => {
+            (Some((&from.items, &to.items)), false)
+        }
+        (Some(_), Some(_)) => (None, true),
+        _ => (None, false),
+    };
+
+    let metadata = to.metadata;
+    let releases = to
+        .releases
+        .into_iter()
+        .filter(|release| release.build_status && release.version != metadata.version)
+        .collect();
+
+    DiffPage {
+        metadata,
+        from: from.metadata,
+        releases,
+        diff: ApiDiff {
+            json_formats_differ,
+            ..ApiDiff::new(&from_api.items, &to_api.items, signatures)
+        },
+    }
+    .into_response(req)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{assert_redirect, wrapper};
+
+    const ALL_V1: &[u8] = br#"<html><body>
+        <ul class="crate"><li><a href="../dummy/index.html">dummy</a></li></ul>
+        <h3 id="structs">Structs</h3>
+        <ul class="structs docblock">
+            <li><a href="struct.Foo.html">Foo</a></li>
+            <li><a href="inner/struct.Bar.html">inner::Bar</a></li>
+        </ul>
+        <h3 id="functions">Functions</h3>
+        <ul class="functions docblock"><li><a href="fn.run.html">run</a></li></ul>
+    </body></html>"#;
+
+    const ALL_V2: &[u8] = br#"<html><body>
+        <h3 id="structs">Structs</h3>
+        <ul class="all-items">
+            <li><a href="struct.Foo.html">Foo</a></li>
+            <li><a href="inner/struct.Baz.html">inner::Baz</a></li>
+        </ul>
+        <h3 id="functions">Functions</h3>
+        <ul class="all-items"><li><a href="fn.run.html">run</a></li></ul>
+        <h3 id="macros">Macros</h3>
+        <ul class="all-items"><li><a href="macro.run.html">run</a></li></ul>
+    </body></html>"#;
+
+    fn rustdoc_json(run_output: &str) -> String {
+        rustdoc_json_with_field(run_output, "u8")
+    }
+
+    fn rustdoc_json_with_field(run_output: &str, field_type: &str) -> String {
+        rustdoc_json_with_format(run_output, field_type, 1)
+    }
+
+    fn rustdoc_json_with_format(run_output: &str, field_type: &str, format_version: u64) -> String {
+        format!(
+            r#"{{
+                "root": "0:0",
+                "format_version": {},
+                "index": {{
+                    "0:1": {{"crate_id": 0, "name": "Foo", "inner": {{"struct_type": "plain", "fields": ["0:5"], "impls": []}}}},
+                    "0:2": {{"crate_id": 0, "name": "run", "inner": {{"decl": {{"inputs": [], "output": {}}}}}}},
+                    "0:5": {{"crate_id": 0, "id": "0:5", "name": "x", "inner": {{"kind": "primitive", "inner": "{}"}}}},
+                    "1:3": {{"crate_id": 1, "name": "Other", "inner": {{}}}}
+                }},
+                "paths": {{
+                    "0:1": {{"crate_id": 0, "path": ["dummy", "Foo"], "kind": "struct"}},
+                    "0:2": {{"crate_id": 0, "path": ["dummy", "run"], "kind": "function"}},
+                    "1:3": {{"crate_id": 1, "path": ["std", "Other"], "kind": "struct"}}
+                }}
+            }}"#,
+            format_version, run_output, field_type
+        )
+    }
+
+    #[test]
+    fn test_parse_all_items() {
+        let items = parse_all_items(&String::from_utf8_lossy(ALL_V1));
+        assert_eq!(
+            items.values().cloned().collect::<Vec<_>>(),
+            vec![
+                ApiItem {
+                    kind: "fn".into(),
+                    path: "run".into(),
+                    href: "fn.run.html".into(),
+                },
+                ApiItem {
+                    kind: "struct".into(),
+                    path: "Foo".into(),
+                    href: "struct.Foo.html".into(),
+                },
+                ApiItem {
+                    kind: "struct".into(),
+                    path: "inner::Bar".into(),
+                    href: "inner/struct.Bar.html".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_signatures() {
+        let signatures = parse_signatures(rustdoc_json("null").as_bytes()).unwrap();
+        assert_eq!(signatures.format_version, Some(1));
+        let signatures = signatures.items;
+        assert_eq!(
+            signatures.keys().cloned().collect::<Vec<_>>(),
+            vec![
+                ("fn".to_string(), "run".to_string()),
+                ("struct".to_string(), "Foo".to_string()),
+            ]
+        );
+        // The public fields are part of the signature, but not the implementations
+        let foo = ("struct".to_string(), "Foo".to_string());
+        assert_eq!(
+            signatures[&foo],
+            r#"{"fields":[{"inner":{"inner":"u8","kind":"primitive"},"name":"x"}],"struct_type":"plain"}"#
+        );
+
+        let changed_field = rustdoc_json_with_field("null", "u16");
+        let changed_signatures = parse_signatures(changed_field.as_bytes()).unwrap().items;
+        assert_ne!(signatures[&foo], changed_signatures[&foo]);
+    }
+
+    #[test]
+    fn test_diff_page() {
+        wrapper(|env| {
+            for &(version, all) in &[("0.1.0", ALL_V1), ("0.2.0", ALL_V2)] {
+                env.fake_release()
+                    .name("dummy")
+                    .version(version)
+                    .rustdoc_file("dummy/all.html", all)
+                    .create()?;
+            }
+
+            let web = env.frontend();
+            let page =
+                kuchiki::parse_html().one(web.get("/crate/dummy/diff/0.1.0/0.2.0").send()?.text()?);
+            let links = |section: &str| {
+                page.select(&format!("#{} li a", section))
+                    .unwrap()
+                    .map(|link| link.attributes.borrow().get("href").unwrap().to_string())
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(
+                links("added"),
+                vec![
+                    "/dummy/0.2.0/dummy/macro.run.html",
+                    "/dummy/0.2.0/dummy/inner/struct.Baz.html",
+                ]
+            );
+            assert_eq!(
+                links("removed"),
+                vec!["/dummy/0.1.0/dummy/inner/struct.Bar.html"]
+            );
+            // Signatures can't be compared without the rustdoc JSON output
+            assert!(page.select_first("#changed").is_err());
+
+            assert_redirect(
+                "/crate/dummy/diff/~0.1/0.2.0",
+                "/crate/dummy/diff/0.1.0/0.2.0",
+                web,
+            )?;
+            assert_eq!(
+                web.get("/crate/dummy/diff/0.1.0/0.3.0").send()?.status(),
+                404
+            );
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_diff_page_with_signatures() {
+        wrapper(|env| {
+            for &(version, all, output) in &[
+                ("0.1.0", ALL_V1, "null"),
+                ("0.2.0", ALL_V2, r#"{"kind": "primitive", "inner": "bool"}"#),
+            ] {
+                env.fake_release()
+                    .name("dummy")
+                    .version(version)
+                    .rustdoc_file("dummy/all.html", all)
+                    .create()?;
+                env.db().conn().execute(
+                    "INSERT INTO files (path, mime, content) VALUES ($1, 'application/json', $2)",
+                    &[
+                        &format!("json/dummy/{}/x86_64-unknown-linux-gnu/dummy.json", version),
+                        &rustdoc_json(output).into_bytes(),
+                    ],
+                )?;
+            }
+
+            let page = kuchiki::parse_html().one(
+                env.frontend()
+                    .get("/crate/dummy/diff/0.1.0/0.2.0")
+                    .send()?
+                    .text()?,
+            );
+            let changed = page
+                .select("#changed li a")
+                .unwrap()
+                .map(|link| link.attributes.borrow().get("href").unwrap().to_string())
+                .collect::<Vec<_>>();
+            assert_eq!(
+                changed,
+                vec![
+                    "/dummy/0.1.0/dummy/fn.run.html",
+                    "/dummy/0.2.0/dummy/fn.run.html"
+                ]
+            );
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_diff_page_with_different_json_formats() {
+        wrapper(|env| {
+            for &(version, all, output, format_version) in &[
+                ("0.1.0", ALL_V1, "null", 1),
+                (
+                    "0.2.0",
+                    ALL_V2,
+                    r#"{"kind": "primitive", "inner": "bool"}"#,
+                    2,
+                ),
+            ] {
+                env.fake_release()
+                    .name("dummy")
+                    .version(version)
+                    .rustdoc_file("dummy/all.html", all)
+                    .create()?;
+                env.db().conn().execute(
+                    "INSERT INTO files (path, mime, content) VALUES ($1, 'application/json', $2)",
+                    &[
+                        &format!("json/dummy/{}/x86_64-unknown-linux-gnu/dummy.json", version),
+                        &rustdoc_json_with_format(output, "u8", format_version).into_bytes(),
+                    ],
+                )?;
+            }
+
+            let page = kuchiki::parse_html().one(
+                env.frontend()
+                    .get("/crate/dummy/diff/0.1.0/0.2.0")
+                    .send()?
+                    .text()?,
+            );
+            // Only the added and removed items are listed
+            assert_eq!(page.select("#added li").unwrap().count(), 2);
+            assert_eq!(page.select("#removed li").unwrap().count(), 1);
+            assert!(page.select_first("#changed").is_err());
+            assert!(page
+                .select_first("#json-formats-differ")
+                .unwrap()
+                .text_contents()
+                .contains("different formats"));
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_diff_page_is_cached_until_rebuild() {
+        wrapper(|env| {
+            let json_path = |version: &str| {
+                format!("json/dummy/{}/x86_64-unknown-linux-gnu/dummy.json", version)
+            };
+            for &version in &["0.1.0", "0.2.0"] {
+                env.fake_release()
+                    .name("dummy")
+                    .version(version)
+                    .rustdoc_file("dummy/all.html", ALL_V1)
+                    .create()?;
+                env.db().conn().execute(
+                    "INSERT INTO files (path, mime, content) VALUES ($1, 'application/json', $2)",
+                    &[&json_path(version), &rustdoc_json("null").into_bytes()],
+                )?;
+            }
+
+            let changed = || -> Result<usize, Error> {
+                let page = kuchiki::parse_html().one(
+                    env.frontend()
+                        .get("/crate/dummy/diff/0.1.0/0.2.0")
+                        .send()?
+                        .text()?,
+                );
+                Ok(page.select("#changed li").unwrap().count())
+            };
+            assert_eq!(changed()?, 0);
+
+            // The items of 0.2.0 are cached, so replacing its JSON output doesn't change the diff
+            env.db().conn().execute(
+                "UPDATE files SET content = $2 WHERE path = $1",
+                &[
+                    &json_path("0.2.0"),
+                    &rustdoc_json_with_field("null", "u16").into_bytes(),
+                ],
+            )?;
+            assert_eq!(changed()?, 0);
+
+            // Until the release is built again
+            env.db().conn().execute(
+                "INSERT INTO builds (rid, rustc_version, cratesfyi_version, build_status)
+                 SELECT releases.id, 'rustc', 'docs.rs', TRUE
+                 FROM releases WHERE releases.version = '0.2.0'",
+                &[],
+            )?;
+            assert_eq!(changed()?, 1);
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,327 @@
+//! Downloading the documentation of a release as a single file
+
+use super::{error::Nope, match_version, redirect_base, MatchSemver};
+use crate::{
+    db::Pool,
+    docbuilder::essential_files,
+    storage::{rustdoc_archive_path, LoadedArchive},
+    utils, Config, Storage,
+};
+use iron::{
+    headers::{CacheControl, CacheDirective, ContentType},
+    response::WriteBody,
+    status, IronError, IronResult, Request, Response, Url,
+};
+use log::warn;
+use router::Router;
+use std::io::{self, Write};
+use std::sync::Arc;
+
+/// Handler for `/crate/:name/:version/download`, sending the documentation of all the targets
+/// of a release as a `.tar.zst` file.
+///
+/// The files of the shared rustdoc resources are included next to the documentation, so that it
+/// can be browsed offline.
+pub fn download_handler(req: &mut Request) -> IronResult<Response> {
+    let router = extension!(req, Router);
+    let name = cexpect!(req, router.find("name"));
+    let req_version = router.find("version");
+    let conn = extension!(req, Pool).get()?;
+
+    let release_id = match match_version(&conn, name, req_version).and_then(|m| m.assume_exact()) {
+        Some(MatchSemver::Exact((_, release_id))) => release_id,
+        Some(MatchSemver::Semver((version, _))) => {
+            let url = ctry!(
+                req,
+                Url::parse(&format!(
+                    "{}/crate/{}/{}/download",
+                    redirect_base(req),
+                    name,
+                    version
+                )),
+            );
+            return Ok(super::redirect(url));
+        }
+        None => return Err(IronError::new(Nope::CrateNotFound, status::NotFound)),
+    };
+
+    let rows = ctry!(
+        req,
+        conn.query(
+            "SELECT crates.name,
+                    releases.version,
+                    releases.rustdoc_status,
+                    releases.archive_storage,
+                    releases.doc_rustc_version
+             FROM releases
+             INNER JOIN crates ON crates.id = releases.crate_id
+             WHERE releases.id = $1",
+            &[&release_id],
+        )
+    );
+    let row = cexpect!(req, rows.iter().next());
+    if !row.get::<_, bool>("rustdoc_status") {
+        return Err(IronError::new(Nope::ResourceNotFound, status::NotFound));
+    }
+    let name: String = row.get("name");
+    let version: String = row.get("version");
+
+    let storage = extension!(req, Storage).clone();
+    let mut archive = None;
+    let mut files = if row.get("archive_storage") {
+        // The index of the archive is only loaded once, to read all the files from it.
+        let loaded = ctry!(
+            req,
+            storage.load_archive(&rustdoc_archive_path(&name, &version))
+        );
+        let files = loaded
+            .paths()
+            .into_iter()
+            .map(|path| TarballFile {
+                source: FileSource::Archive(path.clone()),
+                path,
+                optional: false,
+            })
+            .collect::<Vec<_>>();
+        archive = Some(loaded);
+        files
+    } else {
+        let prefix = format!("rustdoc/{}/{}/", name, version);
+        ctry!(req, storage.list_all_paths(&prefix))
+            .into_iter()
+            .map(|stored| TarballFile {
+                path: stored[prefix.len()..].to_string(),
+                source: FileSource::Storage(stored),
+                optional: false,
+            })
+            .collect()
+    };
+    if files.is_empty() {
+        return Err(IronError::new(Nope::ResourceNotFound, status::NotFound));
+    }
+
+    // Releases built before the shared resources were versioned don't need them.
+    if let Ok(rustc_version) = utils::parse_rustc_version(row.get::<_, String>("doc_rustc_version"))
+    {
+        files.extend(
+            essential_files(&rustc_version)
+                .into_iter()
+                .map(|file| TarballFile {
+                    source: FileSource::Storage(file.clone()),
+                    path: file,
+                    optional: true,
+                }),
+        );
+    }
+
+    let root = format!("{}-{}", name, version);
+    let mut resp = Response::with((
+        status::Ok,
+        Box::new(Tarball {
+            storage,
+            config: extension!(req, Config).clone(),
+            root: root.clone(),
+            archive,
+            files,
+        }) as Box<dyn WriteBody>,
+    ));
+    resp.headers
+        .set(ContentType("application/zstd".parse().unwrap()));
+    resp.headers.set_raw(
+        "Content-Disposition",
+        vec![format!("attachment; filename=\"{}-docs.tar.zst\"", root).into_bytes()],
+    );
+    resp.headers.set(CacheControl(vec![
+        CacheDirective::Public,
+        CacheDirective::MaxAge(super::STATIC_FILE_CACHE_DURATION as u32),
+    ]));
+
+    Ok(resp)
+}
+
+enum FileSource {
+    /// The path of a file stored individually
+    Storage(String),
+    /// The path of a file in the archive of the tarball
+    Archive(String),
+}
+
+struct TarballFile {
+    source: FileSource,
+    /// Path of the file in the tarball, relative to its root directory
+    path: String,
+    /// Whether to skip the file if it can't be loaded, instead of failing the download
+    optional: bool,
+}
+
+/// A tarball streamed to the client, loading the files from the storage one at a time.
+struct Tarball {
+    storage: Arc<Storage>,
+    config: Arc<Config>,
+    root: String,
+    /// The archive storing the documentation of the release, if archive storage is used
+    archive: Option<LoadedArchive>,
+    files: Vec<TarballFile>,
+}
+
+impl WriteBody for Tarball {
+    fn write_body(&mut self, res: &mut dyn Write) -> io::Result<()> {
+        let mut tar = tar::Builder::new(zstd::stream::write::Encoder::new(res, 9)?);
+
+        for file in &self.files {
+            let max_size = self.config.max_file_size;
+            let blob = match &file.source {
+                FileSource::Storage(path) => self.storage.get(path, max_size),
+                FileSource::Archive(path) => {
+                    let archive = self
+                        .archive
+                        .as_ref()
+                        .expect("archived files are only listed with their archive");
+                    self.storage
+                        .get_from_loaded_archive(archive, path, max_size)
+                }
+            };
+            let blob = match blob {
+                Ok(blob) => blob,
+                Err(_) if file.optional => continue,
+                Err(err) => {
+                    // The response was already started, so the download can only be aborted.
+                    warn!(
+                        "failed to add {} to the {} tarball: {}",
+                        file.path, self.root, err
+                    );
+                    return Err(io::Error::new(io::ErrorKind::Other, err.compat()));
+                }
+            };
+
+            let mut header = tar::Header::new_gnu();
+            header.set_size(blob.content.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(blob.date_updated.timestamp() as u64);
+            tar.append_data(
+                &mut header,
+                format!("{}/{}", self.root, file.path),
+                blob.content.as_slice(),
+            )?;
+        }
+
+        tar.into_inner()?.finish()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test::wrapper;
+    use std::collections::BTreeMap;
+    use std::io::Read;
+
+    fn unpack(body: &[u8]) -> Result<BTreeMap<String, String>, failure::Error> {
+        let decompressed = zstd::decode_all(body)?;
+        let mut archive = tar::Archive::new(decompressed.as_slice());
+
+        let mut files = BTreeMap::new();
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let mut content = String::new();
+            entry.read_to_string(&mut content)?;
+            files.insert(entry.path()?.to_string_lossy().into_owned(), content);
+        }
+        Ok(files)
+    }
+
+    #[test]
+    fn test_download() {
+        wrapper(|env| {
+            for &(version, archive_storage) in &[("0.1.0", false), ("0.2.0", true)] {
+                env.fake_release()
+                    .name("dummy")
+                    .version(version)
+                    .archive_storage(archive_storage)
+                    .rustdoc_file("dummy/index.html", b"<html>dummy</html>")
+                    .rustdoc_file("search-index.js", b"var searchIndex = {};")
+                    .add_platform("x86_64-pc-windows-msvc")
+                    .create()?;
+            }
+
+            let web = env.frontend();
+            for version in &["0.1.0", "0.2.0"] {
+                let resp = web
+                    .get(&format!("/crate/dummy/{}/download", version))
+                    .send()?;
+                assert!(resp.status().is_success());
+                assert_eq!(
+                    resp.headers()["Content-Disposition"],
+                    format!("attachment; filename=\"dummy-{}-docs.tar.zst\"", version).as_str()
+                );
+
+                let files = unpack(&resp.bytes()?)?;
+                let root = format!("dummy-{}", version);
+                assert_eq!(
+                    files.keys().cloned().collect::<Vec<_>>(),
+                    vec![
+                        format!("{}/dummy/index.html", root),
+                        format!("{}/search-index.js", root),
+                        format!("{}/x86_64-pc-windows-msvc/dummy/index.html", root),
+                        format!("{}/x86_64-pc-windows-msvc/search-index.js", root),
+                    ]
+                );
+                assert_eq!(
+                    files[&format!("{}/dummy/index.html", root)],
+                    "<html>dummy</html>"
+                );
+            }
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_download_includes_shared_resources() {
+        wrapper(|env| {
+            env.fake_release().name("dummy").version("0.1.0").create()?;
+
+            // Only the shared resources of the rustc version used by the release are included
+            let rustc_version = "19700101-2.0.0-nightly-000000000";
+            env.db().conn().execute(
+                "INSERT INTO files (path, mime, content) VALUES
+                     ($1, 'text/css', 'body {}'), ('rustdoc-other.css', 'text/css', '')",
+                &[&format!("rustdoc-{}.css", rustc_version)],
+            )?;
+
+            let resp = env.frontend().get("/crate/dummy/0.1.0/download").send()?;
+            let files = unpack(&resp.bytes()?)?;
+            assert_eq!(
+                files[&format!("dummy-0.1.0/rustdoc-{}.css", rustc_version)],
+                "body {}"
+            );
+            assert!(!files.contains_key("dummy-0.1.0/rustdoc-other.css"));
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_download_redirects_and_not_found() {
+        wrapper(|env| {
+            env.fake_release().name("dummy").version("0.1.0").create()?;
+            env.fake_release()
+                .name("dummy")
+                .version("0.2.0")
+                .build_result_successful(false)
+                .create()?;
+
+            let web = env.frontend();
+            let resp = web.get("/crate/dummy/~0.1/download").send()?;
+            assert!(resp.url().path().ends_with("/crate/dummy/0.1.0/download"));
+
+            assert_eq!(web.get("/crate/dummy/0.2.0/download").send()?.status(), 404);
+            assert_eq!(
+                web.get("/crate/missing/0.1.0/download").send()?.status(),
+                404
+            );
+
+            Ok(())
+        })
+    }
+}
@@ -1,7 +1,9 @@
 use crate::config::Config;
 use crate::db::Pool;
 use crate::storage::Storage;
+use crate::web::diff::ApiItemsCache;
 use crate::web::page::TemplateData;
+use crate::web::webhook::IndexSync;
 use crate::BuildQueue;
 use iron::{BeforeMiddleware, IronResult, Request};
 use std::sync::Arc;
@@ -13,6 +15,8 @@ pub(super) struct InjectExtensions {
     pub(super) config: Arc<Config>,
     pub(super) storage: Arc<Storage>,
     pub(super) template_data: Arc<TemplateData>,
+    pub(super) index_sync: Arc<IndexSync>,
+    pub(super) api_items_cache: Arc<ApiItemsCache>,
 }
 
 impl BeforeMiddleware for InjectExtensions {
@@ -24,6 +28,9 @@ impl BeforeMiddleware for InjectExtensions {
         req.extensions.insert::<Storage>(self.storage.clone());
         req.extensions
             .insert::<TemplateData>(self.template_data.clone());
+        req.extensions.insert::<IndexSync>(self.index_sync.clone());
+        req.extensions
+            .insert::<ApiItemsCache>(self.api_items_cache.clone());
 
         Ok(())
     }
@@ -42,3 +49,5 @@ key!(Pool => Pool);
 key!(Config => Arc<Config>);
 key!(Storage => Arc<Storage>);
 key!(TemplateData => Arc<TemplateData>);
+key!(IndexSync => Arc<IndexSync>);
+key!(ApiItemsCache => Arc<ApiItemsCache>);
@@ -1,7 +1,8 @@
 //! Database based file handler
 
-use crate::storage::{Blob, Storage};
+use crate::storage::{decompress_blob, Blob, CompressionAlgorithm, Storage};
 use crate::{error::Result, Config};
+use iron::headers::{AcceptEncoding, ContentEncoding, Encoding};
 use iron::{status, Handler, IronError, IronResult, Request, Response};
 
 #[derive(Debug)]
@@ -10,13 +11,42 @@ pub(crate) struct File(pub(crate) Blob);
 impl File {
     /// Gets file from database
     pub fn from_path(storage: &Storage, path: &str, config: &Config) -> Result<File> {
-        let max_size = if path.ends_with(".html") {
-            config.max_file_size_html
-        } else {
-            config.max_file_size
-        };
+        Ok(File(storage.get(path, max_size(path, config))?))
+    }
+
+    /// Gets a file from the archive at `archive_path`, storing the documentation of a release.
+    pub fn from_archive(
+        storage: &Storage,
+        archive_path: &str,
+        path: &str,
+        config: &Config,
+    ) -> Result<File> {
+        Ok(File(storage.get_from_archive(
+            archive_path,
+            path,
+            max_size(path, config),
+        )?))
+    }
+
+    /// Gets a file to be served unchanged to the client of `req`.
+    ///
+    /// If the client accepts the encoding of the stored file it's kept compressed, and `serve`
+    /// sends it with a `Content-Encoding` header. Otherwise it's decompressed like `from_path`
+    /// does, so the content must not be read by the caller.
+    pub fn for_request(
+        req: &Request,
+        storage: &Storage,
+        path: &str,
+        config: &Config,
+    ) -> Result<File> {
+        let max_size = max_size(path, config);
+        let mut blob = storage.get_raw(path, max_size)?;
+        match blob.compression {
+            Some(alg) if accepts_encoding(req, alg) => {}
+            _ => decompress_blob(&mut blob, max_size)?,
+        }
 
-        Ok(File(storage.get(path, max_size)?))
+        Ok(File(blob))
     }
 
     /// Consumes File and creates a iron response
@@ -32,6 +62,13 @@ impl File {
             .headers
             .set(ContentType(self.0.mime.parse().unwrap()));
         response.headers.set(CacheControl(cache));
+        if let Some(alg) = self.0.compression {
+            response.headers.set(ContentEncoding(vec![encoding(alg)]));
+        }
+        // The same URL is served compressed or not depending on the client
+        response
+            .headers
+            .set_raw("Vary", vec![b"Accept-Encoding".to_vec()]);
         // FIXME: This is so horrible
         response.headers.set(LastModified(HttpDate(
             time::strptime(
@@ -49,6 +86,38 @@ impl File {
     }
 }
 
+fn max_size(path: &str, config: &Config) -> usize {
+    if path.ends_with(".html") {
+        config.max_file_size_html
+    } else {
+        config.max_file_size
+    }
+}
+
+fn encoding(alg: CompressionAlgorithm) -> Encoding {
+    match alg {
+        CompressionAlgorithm::Gzip => Encoding::Gzip,
+        _ => Encoding::EncodingExt(alg.content_encoding().into()),
+    }
+}
+
+/// Checks whether the `Accept-Encoding` header of the request allows files compressed with
+/// `alg`, either explicitly or with the `*` wildcard.
+fn accepts_encoding(req: &Request, alg: CompressionAlgorithm) -> bool {
+    let accepted = match req.headers.get::<AcceptEncoding>() {
+        Some(AcceptEncoding(accepted)) => accepted,
+        None => return false,
+    };
+
+    let encoding = encoding(alg);
+    let wildcard = Encoding::EncodingExt("*".into());
+    accepted
+        .iter()
+        .find(|item| item.item == encoding)
+        .or_else(|| accepted.iter().find(|item| item.item == wildcard))
+        .map_or(false, |item| item.quality.0 > 0)
+}
+
 /// Database based file handler for iron
 ///
 /// This is similar to staticfile crate, but its using getting files from database.
@@ -59,7 +128,7 @@ impl Handler for DatabaseFileHandler {
         let path = req.url.path().join("/");
         let storage = extension!(req, Storage);
         let config = extension!(req, Config);
-        if let Ok(file) = File::from_path(&storage, &path, &config) {
+        if let Ok(file) = File::for_request(req, storage, &path, config) {
             Ok(file.serve())
         } else {
             Err(IronError::new(
@@ -73,6 +142,7 @@ impl Handler for DatabaseFileHandler {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::storage::decompress;
     use crate::test::wrapper;
     use chrono::Utc;
 
@@ -153,4 +223,68 @@ mod tests {
             Ok(())
         })
     }
+
+    #[test]
+    fn test_content_encoding_negotiation() {
+        wrapper(|env| {
+            env.override_config(|config| {
+                config.compression_algorithm = CompressionAlgorithm::Brotli;
+            });
+            env.fake_release()
+                .name("dummy")
+                .version("0.1.0")
+                .rustdoc_file("dummy/data.js", b"var data = {};")
+                .create()?;
+
+            let url = "/dummy/0.1.0/dummy/data.js";
+            let web = env.frontend();
+
+            // The stored file is sent as-is to clients accepting brotli
+            let resp = web.get(url).header("Accept-Encoding", "gzip, br").send()?;
+            assert_eq!(resp.headers()["Content-Encoding"], "br");
+            assert_eq!(resp.headers()["Vary"], "Accept-Encoding");
+            let body = resp.bytes()?;
+            assert_eq!(
+                decompress(&*body, CompressionAlgorithm::Brotli, usize::MAX)?,
+                b"var data = {};"
+            );
+
+            // And decompressed for the other ones
+            for accept in &["gzip", "br;q=0, *", "identity"] {
+                let resp = web.get(url).header("Accept-Encoding", *accept).send()?;
+                assert!(
+                    resp.headers().get("Content-Encoding").is_none(),
+                    "{}",
+                    accept
+                );
+                assert_eq!(resp.headers()["Vary"], "Accept-Encoding");
+                assert_eq!(&*resp.bytes()?, b"var data = {};");
+            }
+            let resp = web.get(url).send()?;
+            assert!(resp.headers().get("Content-Encoding").is_none());
+
+            // The wildcard accepts any encoding
+            let resp = web.get(url).header("Accept-Encoding", "*").send()?;
+            assert_eq!(resp.headers()["Content-Encoding"], "br");
+
+            // Rustdoc pages are always decompressed to be rendered
+            let resp = web
+                .get("/dummy/0.1.0/dummy/index.html")
+                .header("Accept-Encoding", "br")
+                .send()?;
+            assert!(resp.headers().get("Content-Encoding").is_none());
+
+            // The algorithm is recorded for the release
+            let algs: Vec<i32> = env
+                .db()
+                .conn()
+                .query("SELECT algorithm FROM compression_rels", &[])?
+                .iter()
+                .map(|row| row.get(0))
+                .collect();
+            assert_eq!(algs, vec![CompressionAlgorithm::Brotli as i32]);
+
+            Ok(())
+        });
+    }
 }
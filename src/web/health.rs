@@ -0,0 +1,63 @@
+//! Health of the roles of the daemons, for monitoring
+
+use crate::db::Pool;
+use crate::utils::load_health;
+use iron::headers::ContentType;
+use iron::{status, IronResult, Request, Response};
+
+/// Handler for `/about/health`, listing the status of the roles run by each daemon. Roles whose
+/// daemon stopped or didn't report its status recently are marked as unhealthy.
+pub(super) fn health_handler(req: &mut Request) -> IronResult<Response> {
+    let conn = extension!(req, Pool).get()?;
+    let roles = ctry!(req, load_health(&conn));
+
+    let mut resp = Response::with((status::Ok, ctry!(req, serde_json::to_string(&roles))));
+    resp.headers.set(ContentType::json());
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test::wrapper;
+    use crate::utils::{DaemonRole, Health};
+    use serde_json::Value;
+
+    #[test]
+    fn test_health() {
+        wrapper(|env| {
+            let health = Health::new(env.db().pool(), "builder-1");
+            health.report(DaemonRole::Builder, "building");
+            health.report(DaemonRole::Watcher, "idle");
+            health.store()?;
+
+            let stopped = Health::new(env.db().pool(), "builder-2");
+            stopped.report(DaemonRole::Builder, "idle");
+            stopped.stop()?;
+
+            let resp = env.frontend().get("/about/health").send()?;
+            assert!(resp.status().is_success());
+            let roles: Vec<Value> = resp.json()?;
+            let roles = roles
+                .iter()
+                .map(|role| {
+                    (
+                        role["worker_id"].as_str().unwrap(),
+                        role["role"].as_str().unwrap(),
+                        role["status"].as_str().unwrap(),
+                        role["healthy"].as_bool().unwrap(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                roles,
+                vec![
+                    ("builder-1", "builder", "building", true),
+                    ("builder-2", "builder", "stopped", false),
+                    ("builder-1", "watcher", "idle", true),
+                ]
+            );
+
+            Ok(())
+        })
+    }
+}
@@ -5,9 +5,10 @@ use iron::prelude::*;
 use iron::status::Status;
 use once_cell::sync::Lazy;
 use prometheus::{
-    opts, register_counter, register_int_counter, register_int_gauge, Encoder, IntCounter,
-    IntGauge, TextEncoder, __register_gauge, register_int_counter_vec, IntCounterVec,
-    __register_counter_vec, histogram_opts, register_histogram_vec, HistogramVec,
+    __register_counter_vec, __register_gauge, exponential_buckets, histogram_opts, linear_buckets,
+    opts, register_counter, register_histogram, register_histogram_vec, register_int_counter,
+    register_int_counter_vec, register_int_gauge, Encoder, Histogram, HistogramVec, IntCounter,
+    IntCounterVec, IntGauge, TextEncoder,
 };
 use std::time::{Duration, Instant};
 
@@ -70,6 +71,45 @@ pub static UPLOADED_FILES_TOTAL: Lazy<IntCounter> = Lazy::new(|| {
     .unwrap()
 });
 
+pub static BUILD_DURATION: Lazy<Histogram> = Lazy::new(|| {
+    register_histogram!(
+        "docsrs_build_duration_seconds",
+        "The wall-clock time of the builds",
+        // 5 seconds to 85 minutes
+        exponential_buckets(5.0, 2.0, 11).unwrap()
+    )
+    .unwrap()
+});
+
+pub static BUILD_PEAK_MEMORY: Lazy<Histogram> = Lazy::new(|| {
+    register_histogram!(
+        "docsrs_build_peak_memory_bytes",
+        "The peak memory usage of the sandbox during the builds",
+        // 64 MB to 8 GB
+        exponential_buckets(64.0 * 1024.0 * 1024.0, 2.0, 8).unwrap()
+    )
+    .unwrap()
+});
+
+pub static BUILD_DOCS_SIZE: Lazy<Histogram> = Lazy::new(|| {
+    register_histogram!(
+        "docsrs_build_docs_size_bytes",
+        "The size of the documentation uploaded by the builds",
+        // 64 KB to 16 GB
+        exponential_buckets(64.0 * 1024.0, 4.0, 10).unwrap()
+    )
+    .unwrap()
+});
+
+pub static BUILD_TARGETS: Lazy<Histogram> = Lazy::new(|| {
+    register_histogram!(
+        "docsrs_build_targets",
+        "The number of targets successfully built by the builds",
+        linear_buckets(1.0, 1.0, 10).unwrap()
+    )
+    .unwrap()
+});
+
 pub static ROUTES_VISITED: Lazy<IntCounterVec> = Lazy::new(|| {
     register_int_counter_vec!(
         "docsrs_routes_visited",
@@ -164,7 +204,7 @@ pub fn metrics_handler(req: &mut Request) -> IronResult<Response> {
 
         let process = Process::myself().unwrap();
         OPEN_FILE_DESCRIPTORS.set(process.fd().unwrap().len() as i64);
-        CURRENTLY_RUNNING_THREADS.set(process.stat().unwrap().num_threads as i64);
+        CURRENTLY_RUNNING_THREADS.set(process.stat().unwrap().num_threads);
     }
 
     let mut buffer = Vec::new();
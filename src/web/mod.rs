@@ -65,19 +65,25 @@ macro_rules! extension {
     }};
 }
 
+mod api;
 mod builds;
+mod check_metadata;
 mod crate_details;
+mod diff;
+mod download;
 mod error;
 mod extensions;
 mod file;
+mod health;
 pub(crate) mod metrics;
 mod releases;
 mod routes;
 mod rustdoc;
 mod sitemap;
 mod source;
+mod webhook;
 
-use crate::{config::Config, db::Pool, impl_webpage, BuildQueue, Storage};
+use crate::{config::Config, db::Pool, impl_webpage, BuildQueue, DocBuilderOptions, Storage};
 use chrono::{DateTime, Utc};
 use extensions::InjectExtensions;
 use failure::Error;
@@ -89,6 +95,7 @@ use iron::{
     status::Status,
     Chain, Handler, Iron, IronError, IronResult, Listening, Request, Response, Url,
 };
+use once_cell::sync::Lazy;
 use page::TemplateData;
 use postgres::Connection;
 use router::NoRoute;
@@ -96,6 +103,7 @@ use semver::{Version, VersionReq};
 use serde::Serialize;
 use staticfile::Static;
 use std::{borrow::Cow, env, fmt, net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
+use webhook::IndexSync;
 
 /// Duration of static files for staticfile and DatabaseFileHandler (in seconds)
 const STATIC_FILE_CACHE_DURATION: u64 = 60 * 60 * 24 * 30 * 12; // 12 months
@@ -129,12 +137,24 @@ impl CratesfyiHandler {
         build_queue: Arc<BuildQueue>,
         storage: Arc<Storage>,
     ) -> CratesfyiHandler {
+        let prefix = PathBuf::from(
+            env::var("CRATESFYI_PREFIX")
+                .expect("the CRATESFYI_PREFIX environment variable is not set"),
+        );
+        let index_sync = Arc::new(IndexSync::new(
+            DocBuilderOptions::from_prefix(prefix.clone()),
+            pool.clone(),
+            build_queue.clone(),
+        ));
+
         let inject_extensions = InjectExtensions {
             build_queue,
             pool,
             config,
             storage,
             template_data,
+            index_sync,
+            api_items_cache: Arc::new(diff::ApiItemsCache::new(diff::CACHED_RELEASES)),
         };
 
         let routes = routes::build_routes();
@@ -143,13 +163,8 @@ impl CratesfyiHandler {
         let shared_resources =
             Self::chain(inject_extensions.clone(), rustdoc::SharedResourceHandler);
         let router_chain = Self::chain(inject_extensions.clone(), routes.iron_router());
-        let prefix = PathBuf::from(
-            env::var("CRATESFYI_PREFIX")
-                .expect("the CRATESFYI_PREFIX environment variable is not set"),
-        )
-        .join("public_html");
-        let static_handler =
-            Static::new(prefix).cache(Duration::from_secs(STATIC_FILE_CACHE_DURATION));
+        let static_handler = Static::new(prefix.join("public_html"))
+            .cache(Duration::from_secs(STATIC_FILE_CACHE_DURATION));
 
         CratesfyiHandler {
             shared_resource_handler: Box::new(shared_resources),
@@ -175,7 +190,7 @@ impl Handler for CratesfyiHandler {
             } else {
                 Err(e)
             }
-        };
+        }
 
         // try serving shared rustdoc resources first, then router, then db/static file handler
         // return 404 if none of them return Ok
@@ -371,15 +386,14 @@ fn match_version(conn: &Connection, name: &str, version: Option<&str>) -> Option
 fn render_markdown(text: &str) -> String {
     use comrak::{markdown_to_html, ComrakOptions};
 
-    let options = {
-        let mut options = ComrakOptions::default();
-        options.safe = true;
-        options.ext_superscript = true;
-        options.ext_table = true;
-        options.ext_autolink = true;
-        options.ext_tasklist = true;
-        options.ext_strikethrough = true;
-        options
+    let options = ComrakOptions {
+        safe: true,
+        ext_superscript: true,
+        ext_table: true,
+        ext_autolink: true,
+        ext_tasklist: true,
+        ext_strikethrough: true,
+        ..ComrakOptions::default()
     };
 
     markdown_to_html(text, &options)
@@ -431,6 +445,10 @@ impl Server {
         metrics::NON_LIBRARY_BUILDS.inc_by(0);
         metrics::UPLOADED_FILES_TOTAL.inc_by(0);
         metrics::FAILED_DB_CONNECTIONS.inc_by(0);
+        Lazy::force(&metrics::BUILD_DURATION);
+        Lazy::force(&metrics::BUILD_PEAK_MEMORY);
+        Lazy::force(&metrics::BUILD_DOCS_SIZE);
+        Lazy::force(&metrics::BUILD_TARGETS);
 
         let cratesfyi = CratesfyiHandler::new(pool, config, template_data, build_queue, storage);
         let inner = Iron::new(cratesfyi)
@@ -444,6 +462,14 @@ impl Server {
         self.inner.socket
     }
 
+    /// Stops the server without waiting for it, unlike dropping it.
+    ///
+    /// Iron can't stop listening to the socket, the connections are only refused once the
+    /// process exits.
+    pub fn stop(mut self) {
+        let _ = self.inner.close();
+    }
+
     /// Iron is bugged, and it never closes the server even when the listener is dropped. To
     /// avoid never-ending tests this method forgets about the server, leaking it and allowing the
     /// program to end.
@@ -499,7 +525,7 @@ fn redirect_base(req: &Request) -> String {
     let scheme = req
         .headers
         .get_raw("cloudfront-forwarded-proto")
-        .and_then(|values| values.get(0))
+        .and_then(|values| values.first())
         .and_then(|value| std::str::from_utf8(value).ok())
         .filter(|proto| *proto == "http" || *proto == "https")
         .unwrap_or_else(|| req.url.scheme());
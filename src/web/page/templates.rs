@@ -84,7 +84,7 @@ fn load_rustc_resource_suffix(conn: &Connection) -> Result<String> {
 
     if let Some(Ok(vers)) = res.get(0).get_opt::<_, Value>("value") {
         if let Some(vers_str) = vers.as_str() {
-            return Ok(crate::utils::parse_rustc_version(vers_str)?);
+            return crate::utils::parse_rustc_version(vers_str);
         }
     }
 
@@ -188,7 +188,7 @@ impl ReturnValue {
 
 impl tera::Function for ReturnValue {
     fn call(&self, args: &HashMap<String, Value>) -> TeraResult<Value> {
-        debug_assert!(args.is_empty(), format!("{} takes no args", self.name));
+        debug_assert!(args.is_empty(), "{} takes no args", self.name);
         Ok(self.value.clone())
     }
 }
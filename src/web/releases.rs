@@ -2,7 +2,10 @@
 
 use crate::{
     build_queue::QueuedCrate,
-    db::Pool,
+    db::{
+        builder_lock::{get_builder_lock, BuilderLock},
+        Pool,
+    },
     impl_webpage,
     web::{error::Nope, match_version, page::WebPage, redirect_base},
     BuildQueue,
@@ -130,7 +133,7 @@ fn get_releases_by_author(
         WHERE authors.slug = $1
         ORDER BY crates.github_stars DESC
         LIMIT $2 OFFSET $3";
-    let query = conn.query(&query, &[&author, &limit, &offset]).unwrap();
+    let query = conn.query(query, &[&author, &limit, &offset]).unwrap();
 
     let mut author_name = None;
     let packages = query
@@ -179,7 +182,7 @@ fn get_releases_by_owner(
                  WHERE owners.login = $1
                  ORDER BY crates.github_stars DESC
                  LIMIT $2 OFFSET $3";
-    let query = conn.query(&query, &[&author, &limit, &offset]).unwrap();
+    let query = conn.query(query, &[&author, &limit, &offset]).unwrap();
 
     let mut author_name = None;
     let packages = query
@@ -553,7 +556,7 @@ pub fn search_handler(req: &mut Request) -> IronResult<Response> {
             // since we never pass a version into `match_version` here, we'll never get
             // `MatchVersion::Exact`, so the distinction between `Exact` and `Semver` doesn't
             // matter
-            if let Some(matchver) = match_version(&conn, &query, None) {
+            if let Some(matchver) = match_version(&conn, query, None) {
                 let (version, id) = matchver.version.into_parts();
                 let query = matchver.corrected_name.unwrap_or_else(|| query.to_string());
 
@@ -601,7 +604,7 @@ pub fn search_handler(req: &mut Request) -> IronResult<Response> {
             }
         }
 
-        let (_, results) = get_search_results(&conn, &query, 1, RELEASES_IN_RELEASES);
+        let (_, results) = get_search_results(&conn, query, 1, RELEASES_IN_RELEASES);
         let title = if results.is_empty() {
             format!("No results found for '{}'", query)
         } else {
@@ -655,6 +658,9 @@ pub fn activity_handler(req: &mut Request) -> IronResult<Response> {
 struct BuildQueuePage {
     description: &'static str,
     queue: Vec<QueuedCrate>,
+    failed: Vec<QueuedCrate>,
+    /// Set when the builds are paused
+    lock: Option<BuilderLock>,
 }
 
 impl_webpage! {
@@ -662,17 +668,23 @@ impl_webpage! {
 }
 
 pub fn build_queue_handler(req: &mut Request) -> IronResult<Response> {
-    let mut queue = ctry!(req, extension!(req, BuildQueue).queued_crates());
+    let build_queue = extension!(req, BuildQueue);
+    let mut queue = ctry!(req, build_queue.queued_crates());
+    let failed = ctry!(req, build_queue.failed_crates());
     for krate in queue.iter_mut() {
         // The priority here is inverted: in the database if a crate has a higher priority it
         // will be built after everything else, which is counter-intuitive for people not
         // familiar with docs.rs's inner workings.
         krate.priority = -krate.priority;
     }
+    let conn = extension!(req, Pool).get()?;
+    let lock = ctry!(req, get_builder_lock(&conn));
 
     BuildQueuePage {
         description: "List of crates scheduled to build",
         queue,
+        failed,
+        lock,
     }
     .into_response(req)
 }
@@ -680,6 +692,7 @@ pub fn build_queue_handler(req: &mut Request) -> IronResult<Response> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::db::builder_lock::set_builder_lock;
     use crate::test::{assert_success, wrapper, TestEnvironment};
     use chrono::TimeZone;
     use failure::Error;
@@ -742,8 +755,7 @@ mod tests {
             let near_matches = ["Regex", "rEgex", "reGex", "regEx", "regeX"];
 
             for name in near_matches.iter() {
-                let (num_results, mut results) =
-                    dbg!(get_search_results(&db.conn(), *name, 1, 100));
+                let (num_results, mut results) = dbg!(get_search_results(&db.conn(), name, 1, 100));
                 assert_eq!(num_results, 3);
 
                 for name in releases.iter() {
@@ -1149,6 +1161,81 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_releases_queue_paused() {
+        wrapper(|env| {
+            let web = env.frontend();
+
+            let page = kuchiki::parse_html().one(web.get("/releases/queue").send()?.text()?);
+            assert!(page.select_first(".builds-paused").is_err());
+
+            set_builder_lock(&env.db().conn(), "alice", Some("upgrading the toolchain"))?;
+            let page = kuchiki::parse_html().one(web.get("/releases/queue").send()?.text()?);
+            let notice = page
+                .select_first(".builds-paused")
+                .expect("missing notice")
+                .text_contents();
+            assert!(notice.contains("Builds are paused"));
+            assert!(notice.contains("by alice: upgrading the toolchain."));
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_releases_queue_failures() {
+        wrapper(|env| {
+            env.override_config(|config| {
+                config.build_attempts = 2;
+            });
+            let queue = env.build_queue();
+            let web = env.frontend();
+
+            queue.add_crate("foo", "1.0.0", 0)?;
+            for _ in 0..2 {
+                queue.process_next_crate(|krate| {
+                    assert_eq!("foo", krate.name);
+                    failure::bail!("foo is broken");
+                })?;
+            }
+            queue.add_crate("bar", "0.1.0", 0)?;
+            queue.process_next_crate(|krate| {
+                assert_eq!("bar", krate.name);
+                failure::bail!("bar is broken");
+            })?;
+
+            let page = kuchiki::parse_html().one(web.get("/releases/queue").send()?.text()?);
+
+            let queued = page
+                .select(".queue-list > li")
+                .expect("missing list items")
+                .collect::<Vec<_>>();
+            assert_eq!(queued.len(), 1);
+            assert!(queued[0].text_contents().contains("failed 1 time"));
+            assert!(queued[0].text_contents().contains("next attempt after"));
+            assert_eq!(
+                queued[0]
+                    .as_node()
+                    .select_first("pre")
+                    .expect("missing error")
+                    .text_contents(),
+                "bar is broken"
+            );
+
+            let failed = page
+                .select(".failed-list > li")
+                .expect("missing list items")
+                .collect::<Vec<_>>();
+            assert_eq!(failed.len(), 1);
+            assert!(failed[0].text_contents().contains("foo 1.0.0"));
+            assert!(failed[0].text_contents().contains("failed 2 times"));
+            assert!(failed[0].text_contents().contains("foo is broken"));
+            assert!(!failed[0].text_contents().contains("next attempt after"));
+
+            Ok(())
+        });
+    }
+
     #[test]
     fn authors_page() {
         wrapper(|env| {
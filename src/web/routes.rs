@@ -21,7 +21,14 @@ pub(super) fn build_routes() -> Routes {
 
     routes.internal_page("/", super::releases::home_page);
 
+    routes.post_page("/_/index-webhook", super::webhook::index_webhook_handler);
+    routes.post_page(
+        "/crate/check-metadata",
+        super::check_metadata::check_metadata_handler,
+    );
+
     routes.internal_page("/about", super::sitemap::about_handler);
+    routes.internal_page("/about/health", super::health::health_handler);
     routes.internal_page("/about/metrics", super::metrics::metrics_handler);
 
     routes.internal_page("/releases", super::releases::recent_releases_handler);
@@ -60,6 +67,12 @@ pub(super) fn build_routes() -> Routes {
         super::releases::releases_failures_by_stars_handler,
     );
 
+    routes.internal_page("/-/api/v1/crate/:name", super::api::crate_handler);
+    routes.internal_page(
+        "/-/api/v1/crate/:name/:version",
+        super::api::release_handler,
+    );
+
     routes.internal_page("/crate/:name", super::crate_details::crate_details_handler);
     routes.internal_page(
         "/crate/:name/:version",
@@ -77,6 +90,11 @@ pub(super) fn build_routes() -> Routes {
         "/crate/:name/:version/builds/:id",
         super::builds::build_list_handler,
     );
+    routes.internal_page(
+        "/crate/:name/:version/download",
+        super::download::download_handler,
+    );
+    routes.internal_page("/crate/:name/diff/:from/:to", super::diff::api_diff_handler);
     routes.internal_page(
         "/crate/:name/:version/source",
         SimpleRedirect::new(|url| url.set_path(&format!("{}/", url.path()))),
@@ -93,6 +111,10 @@ pub(super) fn build_routes() -> Routes {
         "/crate/:name/:version/target-redirect/*",
         super::rustdoc::target_redirect_handler,
     );
+    routes.internal_page(
+        "/crate/:name/:version/:target/json",
+        super::rustdoc::rustdoc_json_handler,
+    );
 
     routes.rustdoc_page("/:crate", super::rustdoc::rustdoc_redirector_handler);
     routes.rustdoc_page("/:crate/", super::rustdoc::rustdoc_redirector_handler);
@@ -142,6 +164,8 @@ pub(super) fn build_routes() -> Routes {
 pub(super) struct Routes {
     /// Normal GET routes.
     get: Vec<(String, Box<dyn Handler>)>,
+    /// POST routes receiving webhooks from external services, or data sent by users.
+    post: Vec<(String, Box<dyn Handler>)>,
     /// GET routes serving rustdoc content. The BlockBlacklistedPrefixes middleware is added
     /// automatically to all of them.
     rustdoc_get: Vec<(String, Box<dyn Handler>)>,
@@ -154,6 +178,7 @@ impl Routes {
     fn new() -> Self {
         Self {
             get: Vec::new(),
+            post: Vec::new(),
             rustdoc_get: Vec::new(),
             page_prefixes: HashSet::new(),
         }
@@ -168,6 +193,9 @@ impl Routes {
         for (pattern, handler) in self.get.drain(..) {
             router.get(&pattern, handler, calculate_id(&pattern));
         }
+        for (pattern, handler) in self.post.drain(..) {
+            router.post(&pattern, handler, calculate_id(&pattern));
+        }
 
         // All rustdoc pages have the prefixes of other docs.rs pages blacklisted. This prevents,
         // for example, a crate named "about" from hijacking /about/0.1.0/index.html.
@@ -195,11 +223,11 @@ impl Routes {
     /// an user. The router adds these extra things when adding a new internal page:
     ///
     /// - The first component of the page's URL will be registered as a "page prefix". Page
-    /// prefixes are blacklisted by rustdoc pages, to prevent a crate named the same as a prefix
-    /// from hijacking docs.rs's own URLs.
+    ///   prefixes are blacklisted by rustdoc pages, to prevent a crate named the same as a prefix
+    ///   from hijacking docs.rs's own URLs.
     ///
     /// - If the page URL doesn't end with a slash, a redirect from the URL with the trailing slash
-    /// to the one without is automatically added.
+    ///   to the one without is automatically added.
     fn internal_page(&mut self, pattern: &str, handler: impl Handler) {
         self.get.push((
             pattern.to_string(),
@@ -213,7 +241,8 @@ impl Routes {
                 pattern.to_string(),
                 Box::new(RequestRecorder::new(
                     SimpleRedirect::new(|url| {
-                        url.set_path(&url.path().trim_end_matches('/').to_string())
+                        let path = url.path().trim_end_matches('/').to_owned();
+                        url.set_path(&path)
                     }),
                     pattern,
                 )),
@@ -231,6 +260,20 @@ impl Routes {
         }
     }
 
+    /// A POST page, like webhooks called by external services or endpoints receiving data sent
+    /// by users. Like internal pages, the first component of its URL is registered as a page
+    /// prefix.
+    fn post_page(&mut self, pattern: &str, handler: impl Handler) {
+        self.post.push((
+            pattern.to_string(),
+            Box::new(RequestRecorder::new(handler, pattern)),
+        ));
+
+        if let Some(first_component) = pattern.trim_matches('/').split('/').next() {
+            self.page_prefixes.insert(first_component.to_string());
+        }
+    }
+
     /// A rustdoc page is a page serving generated documentation. It's similar to a static
     /// resource, but path prefixes are automatically blacklisted (see internal pages to learn more
     /// about page prefixes).
@@ -259,7 +302,7 @@ impl Handler for SimpleRedirect {
         (self.url_mangler)(&mut url);
         Ok(iron::Response::with((
             iron::status::Found,
-            iron::modifiers::Redirect(iron::Url::parse(&url.to_string()).unwrap()),
+            iron::modifiers::Redirect(iron::Url::parse(url.as_str()).unwrap()),
         )))
     }
 }
@@ -284,7 +327,7 @@ impl BlockBlacklistedPrefixes {
 
 impl Handler for BlockBlacklistedPrefixes {
     fn handle(&self, req: &mut iron::Request) -> iron::IronResult<iron::Response> {
-        if let Some(prefix) = req.url.path().get(0) {
+        if let Some(prefix) = req.url.path().first() {
             if self.blacklist.contains(*prefix) {
                 return Err(iron::IronError::new(
                     super::error::Nope::CrateNotFound,
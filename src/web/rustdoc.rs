@@ -2,10 +2,16 @@
 
 use crate::{
     db::Pool,
-    impl_webpage, utils,
+    impl_webpage,
+    storage::rustdoc_archive_path,
+    utils,
     web::{
-        crate_details::CrateDetails, error::Nope, file::File, match_version, metrics,
-        page::WebPage, redirect_base, MatchSemver,
+        crate_details::{CrateDetails, DocCoverage},
+        error::Nope,
+        file::File,
+        match_version, metrics,
+        page::WebPage,
+        redirect_base, MatchSemver,
     },
     Config, Storage,
 };
@@ -109,7 +115,7 @@ pub fn rustdoc_redirector_handler(req: &mut Request) -> IronResult<Response> {
 
             let path = req.url.path();
             let path = path.join("/");
-            match File::from_path(&storage, &path, &config) {
+            match File::for_request(req, storage, &path, config) {
                 Ok(f) => return Ok(f.serve()),
                 Err(..) => return Err(IronError::new(Nope::ResourceNotFound, status::NotFound)),
             }
@@ -278,7 +284,7 @@ pub fn rustdoc_html_server_handler(req: &mut Request) -> IronResult<Response> {
 
     // if visiting the full path to the default target, remove the target from the path
     // expects a req_path that looks like `[/:target]/.*`
-    if req_path.get(0).copied() == Some(&krate.metadata.default_target) {
+    if req_path.first().copied() == Some(&krate.metadata.default_target) {
         return redirect(&name, &version, &req_path[1..]);
     }
 
@@ -297,16 +303,24 @@ pub fn rustdoc_html_server_handler(req: &mut Request) -> IronResult<Response> {
         req_path.push("index.html");
     }
 
-    // Attempt to load the file from the database
-    let file = if let Ok(file) = File::from_path(&storage, &path, &config) {
+    // Attempt to load the file from the database. Only html files are rewritten below, the
+    // other ones are served as stored if the client accepts their compression.
+    let load = |req_path: &[&str]| {
+        let path = req_path.join("/");
+        if krate.archive_storage || path.ends_with(".html") {
+            rustdoc_file(req_path, krate.archive_storage, storage, config)
+        } else {
+            File::for_request(req, storage, &path, config)
+        }
+    };
+    let file = if let Ok(file) = load(&req_path) {
         file
     } else {
         // If it fails, we try again with /index.html at the end
         path.push_str("/index.html");
         req_path.push("index.html");
 
-        File::from_path(&storage, &path, &config)
-            .map_err(|_| IronError::new(Nope::ResourceNotFound, status::NotFound))?
+        load(&req_path).map_err(|_| IronError::new(Nope::ResourceNotFound, status::NotFound))?
     };
 
     // Serve non-html files directly
@@ -352,7 +366,13 @@ pub fn rustdoc_html_server_handler(req: &mut Request) -> IronResult<Response> {
             "/{}/{}/{}",
             name,
             latest_version,
-            path_for_version(&latest_path, &krate.doc_targets, &storage, &config)
+            path_for_version(
+                &latest_path,
+                &krate.doc_targets,
+                latest_release.archive_storage,
+                storage,
+                config
+            )
         )
     } else {
         format!("/crate/{}/{}", name, latest_version)
@@ -388,6 +408,23 @@ pub fn rustdoc_html_server_handler(req: &mut Request) -> IronResult<Response> {
     .into_response(req)
 }
 
+/// Loads a file of the documentation of a release, from its archive if `archive_storage` is set.
+///
+/// `req_path` is assumed to have the `rustdoc/crate/version/...` format.
+pub(super) fn rustdoc_file(
+    req_path: &[&str],
+    archive_storage: bool,
+    storage: &Storage,
+    config: &Config,
+) -> Result<File, failure::Error> {
+    if archive_storage {
+        let archive_path = rustdoc_archive_path(req_path[1], req_path[2]);
+        File::from_archive(storage, &archive_path, &req_path[3..].join("/"), config)
+    } else {
+        File::from_path(storage, &req_path.join("/"), config)
+    }
+}
+
 /// Checks whether the given path exists.
 /// The crate's `target_name` is used to confirm whether a platform triple is part of the path.
 ///
@@ -401,11 +438,12 @@ pub fn rustdoc_html_server_handler(req: &mut Request) -> IronResult<Response> {
 fn path_for_version(
     req_path: &[&str],
     known_platforms: &[String],
+    archive_storage: bool,
     storage: &Storage,
     config: &Config,
 ) -> String {
     // Simple case: page exists in the latest version, so just change the version number
-    if File::from_path(storage, &req_path.join("/"), config).is_ok() {
+    if rustdoc_file(req_path, archive_storage, storage, config).is_ok() {
         // NOTE: this adds 'index.html' if it wasn't there before
         return req_path[3..].join("/");
     }
@@ -436,6 +474,60 @@ fn path_for_version(
     }
 }
 
+/// Serves the documentation of a release for a target in rustdoc's JSON format, for the crates
+/// which asked for it.
+pub fn rustdoc_json_handler(req: &mut Request) -> IronResult<Response> {
+    let router = extension!(req, Router);
+    let name = cexpect!(req, router.find("name"));
+    let req_version = router.find("version");
+    let target = cexpect!(req, router.find("target"));
+    let conn = extension!(req, Pool).get()?;
+
+    let release_id = match match_version(&conn, name, req_version).and_then(|m| m.assume_exact()) {
+        Some(MatchSemver::Exact((_, release_id))) => release_id,
+        Some(MatchSemver::Semver((version, _))) => {
+            let url = ctry!(
+                req,
+                Url::parse(&format!(
+                    "{}/crate/{}/{}/{}/json",
+                    redirect_base(req),
+                    name,
+                    version,
+                    target
+                )),
+            );
+            return Ok(super::redirect(url));
+        }
+        None => return Err(IronError::new(Nope::CrateNotFound, status::NotFound)),
+    };
+
+    let rows = ctry!(
+        req,
+        conn.query(
+            "SELECT crates.name, releases.version, releases.target_name
+             FROM releases
+             INNER JOIN crates ON crates.id = releases.crate_id
+             WHERE releases.id = $1",
+            &[&release_id],
+        )
+    );
+    let row = cexpect!(req, rows.iter().next());
+    let path = format!(
+        "json/{}/{}/{}/{}.json",
+        row.get::<_, String>("name"),
+        row.get::<_, String>("version"),
+        target,
+        row.get::<_, String>("target_name"),
+    );
+
+    let storage = extension!(req, Storage);
+    let config = extension!(req, Config);
+    match File::for_request(req, storage, &path, config) {
+        Ok(file) => Ok(file.serve()),
+        Err(_) => Err(IronError::new(Nope::ResourceNotFound, status::NotFound)),
+    }
+}
+
 pub fn target_redirect_handler(req: &mut Request) -> IronResult<Response> {
     let router = extension!(req, Router);
     let name = cexpect!(req, router.find("name"));
@@ -447,7 +539,7 @@ pub fn target_redirect_handler(req: &mut Request) -> IronResult<Response> {
     let config = extension!(req, Config);
     let base = redirect_base(req);
 
-    let crate_details = match CrateDetails::new(&conn, &name, &version) {
+    let crate_details = match CrateDetails::new(&conn, name, version) {
         Some(krate) => krate,
         None => return Err(IronError::new(Nope::ResourceNotFound, status::NotFound)),
     };
@@ -469,7 +561,13 @@ pub fn target_redirect_handler(req: &mut Request) -> IronResult<Response> {
         file_path
     };
 
-    let path = path_for_version(&file_path, &crate_details.doc_targets, &storage, &config);
+    let path = path_for_version(
+        &file_path,
+        &crate_details.doc_targets,
+        crate_details.archive_storage,
+        storage,
+        config,
+    );
     let url = format!(
         "{base}/{name}/{version}/{path}",
         base = base,
@@ -485,23 +583,31 @@ pub fn target_redirect_handler(req: &mut Request) -> IronResult<Response> {
     Ok(resp)
 }
 
+/// Serves a badge showing whether the documentation of a crate was built. With
+/// `?type=coverage`, the badge shows the documentation coverage of the crate instead.
 pub fn badge_handler(req: &mut Request) -> IronResult<Response> {
     use badge::{Badge, BadgeOptions};
     use iron::headers::ContentType;
     use params::{Params, Value};
 
-    let version = {
+    let (version, coverage) = {
         let params = ctry!(req, req.get_ref::<Params>());
-        match params.find(&["version"]) {
-            Some(&Value::String(ref version)) => version.clone(),
+        let version = match params.find(&["version"]) {
+            Some(Value::String(version)) => version.clone(),
             _ => "*".to_owned(),
-        }
+        };
+        let coverage = match params.find(&["type"]) {
+            Some(Value::String(kind)) => kind == "coverage",
+            _ => false,
+        };
+        (version, coverage)
     };
+    let subject = if coverage { "docs coverage" } else { "docs" };
 
     let name = cexpect!(req, extension!(req, Router).find("crate"));
     let conn = extension!(req, Pool).get()?;
 
-    let options = match match_version(&conn, &name, Some(&version)).and_then(|m| m.assume_exact()) {
+    let options = match match_version(&conn, name, Some(&version)).and_then(|m| m.assume_exact()) {
         Some(MatchSemver::Exact((version, id))) => {
             let rows = ctry!(
                 req,
@@ -512,15 +618,36 @@ pub fn badge_handler(req: &mut Request) -> IronResult<Response> {
                     &[&id]
                 ),
             );
-            if !rows.is_empty() && rows.get(0).get(0) {
+            let rustdoc_status = !rows.is_empty() && rows.get(0).get(0);
+            if coverage && rustdoc_status {
+                match DocCoverage::for_release(&conn, id) {
+                    Some(coverage) => BadgeOptions {
+                        subject: subject.to_owned(),
+                        status: format!("{:.0}%", coverage.percent.floor()),
+                        color: if coverage.percent >= 80.0 {
+                            "#4d76ae"
+                        } else if coverage.percent >= 50.0 {
+                            "#dfb317"
+                        } else {
+                            "#e05d44"
+                        }
+                        .to_owned(),
+                    },
+                    None => BadgeOptions {
+                        subject: subject.to_owned(),
+                        status: "unknown".to_owned(),
+                        color: "#9f9f9f".to_owned(),
+                    },
+                }
+            } else if rustdoc_status {
                 BadgeOptions {
-                    subject: "docs".to_owned(),
+                    subject: subject.to_owned(),
                     status: version,
                     color: "#4d76ae".to_owned(),
                 }
             } else {
                 BadgeOptions {
-                    subject: "docs".to_owned(),
+                    subject: subject.to_owned(),
                     status: version,
                     color: "#e05d44".to_owned(),
                 }
@@ -529,16 +656,17 @@ pub fn badge_handler(req: &mut Request) -> IronResult<Response> {
 
         Some(MatchSemver::Semver((version, _))) => {
             let base_url = format!("{}/{}/badge.svg", redirect_base(req), name);
-            let url = ctry!(
-                req,
-                iron::url::Url::parse_with_params(&base_url, &[("version", version)]),
-            );
+            let mut params = vec![("version", version)];
+            if coverage {
+                params.push(("type", "coverage".to_owned()));
+            }
+            let url = ctry!(req, iron::url::Url::parse_with_params(&base_url, &params));
             let iron_url = ctry!(req, Url::from_generic_url(url));
             return Ok(super::redirect(iron_url));
         }
 
         None => BadgeOptions {
-            subject: "docs".to_owned(),
+            subject: subject.to_owned(),
             status: "no builds".to_owned(),
             color: "#e05d44".to_owned(),
         },
@@ -572,7 +700,7 @@ impl Handler for SharedResourceHandler {
             let storage = extension!(req, Storage);
             let config = extension!(req, Config);
 
-            if let Ok(file) = File::from_path(&storage, filename, &config) {
+            if let Ok(file) = File::for_request(req, storage, filename, config) {
                 return Ok(file.serve());
             }
         }
@@ -721,26 +849,22 @@ mod test {
             let web = env.frontend();
 
             // check it works at all
-            let redirect = latest_version_redirect("/dummy/0.1.0/dummy/", &web)?;
+            let redirect = latest_version_redirect("/dummy/0.1.0/dummy/", web)?;
             assert_eq!(redirect, "/dummy/0.2.0/dummy/index.html");
 
             // check it keeps the subpage
-            let redirect = latest_version_redirect("/dummy/0.1.0/dummy/blah/", &web)?;
+            let redirect = latest_version_redirect("/dummy/0.1.0/dummy/blah/", web)?;
             assert_eq!(redirect, "/dummy/0.2.0/dummy/blah/index.html");
-            let redirect = latest_version_redirect("/dummy/0.1.0/dummy/blah/blah.html", &web)?;
+            let redirect = latest_version_redirect("/dummy/0.1.0/dummy/blah/blah.html", web)?;
             assert_eq!(redirect, "/dummy/0.2.0/dummy/blah/blah.html");
 
             // check it searches for removed pages
             let redirect =
-                latest_version_redirect("/dummy/0.1.0/dummy/struct.will-be-deleted.html", &web)?;
+                latest_version_redirect("/dummy/0.1.0/dummy/struct.will-be-deleted.html", web)?;
             // This must be a double redirect to deal with crates that failed to build in the
             // latest version
             assert_eq!(redirect, "/dummy/0.2.0/?search=will-be-deleted");
-            assert_redirect(
-                &redirect,
-                "/dummy/0.2.0/dummy/?search=will-be-deleted",
-                &web,
-            )?;
+            assert_redirect(&redirect, "/dummy/0.2.0/dummy/?search=will-be-deleted", web)?;
 
             Ok(())
         })
@@ -916,6 +1040,40 @@ mod test {
         })
     }
 
+    #[test]
+    fn coverage_badge() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("foo")
+                .version("0.1.0")
+                .doc_coverage("src/lib.rs", 7, 8)
+                .create()?;
+            env.fake_release().name("bar").version("0.1.0").create()?;
+
+            let web = env.frontend();
+            let badge = web
+                .get("/foo/badge.svg?version=0.1.0&type=coverage")
+                .send()?
+                .text()?;
+            assert!(badge.contains("docs coverage"));
+            assert!(badge.contains("87%"));
+
+            let badge = web
+                .get("/bar/badge.svg?version=0.1.0&type=coverage")
+                .send()?
+                .text()?;
+            assert!(badge.contains("unknown"));
+
+            assert_redirect(
+                "/foo/badge.svg?type=coverage",
+                "/foo/badge.svg?version=0.1.0&type=coverage",
+                web,
+            )?;
+
+            Ok(())
+        })
+    }
+
     #[test]
     fn badges_are_urlencoded() {
         wrapper(|env| {
@@ -928,7 +1086,7 @@ mod test {
             assert_redirect(
                 "/zstd/badge.svg",
                 "/zstd/badge.svg?version=0.5.1%2Bzstd.1.4.4",
-                &frontend,
+                frontend,
             )?;
             Ok(())
         })
@@ -1348,6 +1506,54 @@ mod test {
         });
     }
 
+    #[test]
+    fn test_archive_storage() {
+        wrapper(|env| {
+            env.fake_release()
+                .name("dummy")
+                .version("0.1.0")
+                .archive_storage(true)
+                .rustdoc_file("dummy/index.html", b"<html><body>dummy</body></html>")
+                .rustdoc_file("dummy/struct.Foo.html", b"<html><body>Foo</body></html>")
+                .rustdoc_file("search-index.js", b"var searchIndex = {};")
+                .add_platform("x86_64-pc-windows-msvc")
+                .create()?;
+            env.fake_release()
+                .name("dummy")
+                .version("0.2.0")
+                .archive_storage(true)
+                .rustdoc_file("dummy/index.html", b"<html><body>dummy</body></html>")
+                .create()?;
+
+            let web = env.frontend();
+            assert_success("/dummy/0.1.0/dummy/", web)?;
+            assert_success("/dummy/0.1.0/dummy/struct.Foo.html", web)?;
+            assert_success("/dummy/0.1.0/x86_64-pc-windows-msvc/dummy/", web)?;
+            assert_eq!(
+                web.get("/dummy/0.1.0/search-index.js").send()?.text()?,
+                "var searchIndex = {};"
+            );
+            assert_eq!(
+                web.get("/dummy/0.1.0/dummy/struct.Bar.html")
+                    .send()?
+                    .status(),
+                404
+            );
+
+            // Files missing from the latest version link to a search
+            assert_eq!(
+                latest_version_redirect("/dummy/0.1.0/dummy/struct.Foo.html", web)?,
+                "/dummy/0.2.0/?search=Foo"
+            );
+            assert_eq!(
+                latest_version_redirect("/dummy/0.1.0/dummy/", web)?,
+                "/dummy/0.2.0/dummy/index.html"
+            );
+
+            Ok(())
+        })
+    }
+
     #[test]
     fn test_target_redirect_not_found() {
         wrapper(|env| {
@@ -1493,4 +1699,46 @@ mod test {
             Ok(())
         })
     }
+
+    #[test]
+    fn test_rustdoc_json() {
+        wrapper(|env| {
+            env.fake_release().name("dummy").version("0.1.0").create()?;
+            env.db().conn().execute(
+                "INSERT INTO files (path, mime, content) VALUES
+                     ('json/dummy/0.1.0/x86_64-unknown-linux-gnu/dummy.json', 'application/json', $1)",
+                &[&br#"{"root":"0:0"}"#.to_vec()],
+            )?;
+
+            let web = env.frontend();
+            let resp = web
+                .get("/crate/dummy/0.1.0/x86_64-unknown-linux-gnu/json")
+                .send()?;
+            assert!(resp.status().is_success());
+            assert_eq!(resp.headers()["Content-Type"], "application/json");
+            assert_eq!(resp.text()?, r#"{"root":"0:0"}"#);
+
+            assert_redirect(
+                "/crate/dummy/~0.1/x86_64-unknown-linux-gnu/json",
+                "/crate/dummy/0.1.0/x86_64-unknown-linux-gnu/json",
+                web,
+            )?;
+
+            // The JSON output is only built for the crates asking for it
+            assert_eq!(
+                web.get("/crate/dummy/0.1.0/x86_64-pc-windows-msvc/json")
+                    .send()?
+                    .status(),
+                404
+            );
+            assert_eq!(
+                web.get("/crate/missing/0.1.0/x86_64-unknown-linux-gnu/json")
+                    .send()?
+                    .status(),
+                404
+            );
+
+            Ok(())
+        })
+    }
 }
@@ -85,9 +85,9 @@ impl FileList {
                     }
 
                     // look only files for req_path
-                    if path.starts_with(&req_path) {
+                    if path.starts_with(req_path) {
                         // remove req_path from path to reach files in this directory
-                        let path = path.replace(&req_path, "");
+                        let path = path.replace(req_path, "");
                         let path_splited: Vec<&str> = path.split('/').collect();
 
                         // if path have '/' it is a directory
@@ -192,7 +192,7 @@ pub fn source_browser_handler(req: &mut Request) -> IronResult<Response> {
     // try to get actual file first
     // skip if request is a directory
     let file = if !file_path.ends_with('/') {
-        DbFile::from_path(&storage, &file_path, &config).ok()
+        DbFile::from_path(storage, &file_path, config).ok()
     } else {
         None
     };
@@ -213,7 +213,7 @@ pub fn source_browser_handler(req: &mut Request) -> IronResult<Response> {
         (None, false)
     };
 
-    let file_list = FileList::from_path(&conn, &name, &version, &req_path)
+    let file_list = FileList::from_path(&conn, name, version, &req_path)
         .ok_or_else(|| IronError::new(Nope::NoResults, Status::NotFound))?;
 
     SourcePage {
@@ -0,0 +1,273 @@
+//! Webhooks sent to docs.rs by external services
+
+use crate::{db::Pool, error::Result, BuildQueue, Config, DocBuilder, DocBuilderOptions};
+use hmac::{Hmac, Mac, NewMac};
+use iron::{headers::Connection, status, IronResult, Request, Response};
+use log::{debug, error, info};
+use once_cell::sync::OnceCell;
+use sha2::Sha256;
+use std::io::Read;
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread;
+
+/// Header containing the hex encoded HMAC-SHA256 of the payload, prefixed with `sha256=`.
+const SIGNATURE_HEADER: &str = "X-Hub-Signature-256";
+
+/// GitHub caps webhook payloads at 25 MB, larger requests are not coming from it.
+const MAX_PAYLOAD_SIZE: u64 = 25 * 1024 * 1024;
+
+/// Handler for `/_/index-webhook`, triggering a sync of the registry index in the background.
+///
+/// The payload of the webhook is ignored, but its signature is validated against
+/// `DOCSRS_INDEX_WEBHOOK_SECRET`. All requests are rejected if the secret is not set.
+pub(super) fn index_webhook_handler(req: &mut Request) -> IronResult<Response> {
+    let secret = match &extension!(req, Config).index_webhook_secret {
+        Some(secret) => secret.clone(),
+        None => {
+            return Ok(reject_unread(
+                status::Forbidden,
+                "the index webhook is not configured",
+            ))
+        }
+    };
+
+    let mut payload = Vec::new();
+    ctry!(
+        req,
+        req.body
+            .by_ref()
+            .take(MAX_PAYLOAD_SIZE + 1)
+            .read_to_end(&mut payload)
+    );
+    if payload.len() as u64 > MAX_PAYLOAD_SIZE {
+        return Ok(reject_unread(
+            status::PayloadTooLarge,
+            "the webhook payload is too large",
+        ));
+    }
+
+    let signature = req
+        .headers
+        .get_raw(SIGNATURE_HEADER)
+        .and_then(|values| values.first())
+        .and_then(|value| std::str::from_utf8(value).ok());
+
+    let valid = signature
+        .map(|signature| verify_signature(secret.as_bytes(), &payload, signature))
+        .unwrap_or(false);
+    if !valid {
+        return Ok(Response::with((
+            status::Forbidden,
+            "invalid or missing webhook signature",
+        )));
+    }
+
+    ctry!(req, extension!(req, IndexSync).trigger());
+
+    Ok(Response::with((status::Accepted, "index sync scheduled")))
+}
+
+/// Rejects a request whose body wasn't entirely read. The connection is closed, as the rest of the
+/// body would otherwise be parsed as the next request.
+fn reject_unread(status: status::Status, message: &'static str) -> Response {
+    let mut response = Response::with((status, message));
+    response.headers.set(Connection::close());
+    response
+}
+
+fn verify_signature(secret: &[u8], payload: &[u8], signature: &str) -> bool {
+    let signature = match signature
+        .strip_prefix("sha256=")
+        .and_then(|signature| hex::decode(signature).ok())
+    {
+        Some(signature) => signature,
+        None => return false,
+    };
+
+    let mut mac = Hmac::<Sha256>::new_varkey(secret).expect("HMAC accepts keys of any size");
+    mac.update(payload);
+    mac.verify(&signature).is_ok()
+}
+
+/// Syncs the registry index on a dedicated thread, started when the first sync is requested.
+///
+/// At most one sync is pending at any time: triggers received while a sync is already pending
+/// are merged into it, as it will pick up all the new commits of the index anyway.
+#[derive(Debug)]
+pub(crate) struct IndexSync {
+    options: DocBuilderOptions,
+    pool: Pool,
+    build_queue: Arc<BuildQueue>,
+    sender: OnceCell<SyncSender<()>>,
+}
+
+impl IndexSync {
+    pub(crate) fn new(
+        options: DocBuilderOptions,
+        pool: Pool,
+        build_queue: Arc<BuildQueue>,
+    ) -> Self {
+        Self {
+            options,
+            pool,
+            build_queue,
+            sender: OnceCell::new(),
+        }
+    }
+
+    /// Requests a sync of the registry index, without waiting for it to happen.
+    pub(crate) fn trigger(&self) -> Result<()> {
+        let sender = self.sender.get_or_try_init(|| self.start_thread())?;
+        match sender.try_send(()) {
+            Ok(()) | Err(TrySendError::Full(())) => Ok(()),
+            Err(TrySendError::Disconnected(())) => failure::bail!("the index sync thread exited"),
+        }
+    }
+
+    fn start_thread(&self) -> Result<SyncSender<()>> {
+        let (sender, receiver) = mpsc::sync_channel(1);
+
+        let options = self.options.clone();
+        let pool = self.pool.clone();
+        let build_queue = self.build_queue.clone();
+        thread::Builder::new()
+            .name("index webhook sync".to_string())
+            .spawn(move || {
+                for () in receiver {
+                    match sync_index(&options, &pool, &build_queue) {
+                        Ok(n) => info!("index webhook: {} crates added to queue", n),
+                        Err(e) => error!("Failed to sync the registry index: {}", e),
+                    }
+                }
+            })?;
+
+        Ok(sender)
+    }
+}
+
+fn sync_index(
+    options: &DocBuilderOptions,
+    pool: &Pool,
+    build_queue: &Arc<BuildQueue>,
+) -> Result<usize> {
+    options.check_paths()?;
+
+    let mut doc_builder = DocBuilder::new(options.clone(), pool.clone(), build_queue.clone());
+    if doc_builder.is_locked() {
+        debug!("Builds are paused, skipping checking new crates");
+        return Ok(0);
+    }
+
+    doc_builder.get_new_crates()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::wrapper;
+    use reqwest::StatusCode;
+
+    const SECRET: &str = "It's a Secret to Everybody";
+    const PAYLOAD: &str = "Hello, World!";
+    // Example signature from GitHub's webhook documentation
+    const SIGNATURE: &str =
+        "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+
+    #[test]
+    fn test_verify_signature() {
+        assert!(verify_signature(
+            SECRET.as_bytes(),
+            PAYLOAD.as_bytes(),
+            SIGNATURE
+        ));
+        assert!(!verify_signature(
+            b"another secret",
+            PAYLOAD.as_bytes(),
+            SIGNATURE
+        ));
+        assert!(!verify_signature(
+            SECRET.as_bytes(),
+            b"another payload",
+            SIGNATURE
+        ));
+        assert!(!verify_signature(
+            SECRET.as_bytes(),
+            PAYLOAD.as_bytes(),
+            SIGNATURE.trim_start_matches("sha256=")
+        ));
+        assert!(!verify_signature(
+            SECRET.as_bytes(),
+            PAYLOAD.as_bytes(),
+            "sha256=not hex"
+        ));
+    }
+
+    #[test]
+    fn test_webhook_signature_is_checked() {
+        wrapper(|env| {
+            env.override_config(|config| {
+                config.index_webhook_secret = Some(SECRET.into());
+            });
+            let web = env.frontend();
+
+            let missing = web.post("/_/index-webhook").body(PAYLOAD).send()?;
+            assert_eq!(missing.status(), StatusCode::FORBIDDEN);
+
+            let invalid = web
+                .post("/_/index-webhook")
+                .header(SIGNATURE_HEADER, "sha256=00")
+                .body(PAYLOAD)
+                .send()?;
+            assert_eq!(invalid.status(), StatusCode::FORBIDDEN);
+
+            let valid = web
+                .post("/_/index-webhook")
+                .header(SIGNATURE_HEADER, SIGNATURE)
+                .body(PAYLOAD)
+                .send()?;
+            assert_eq!(valid.status(), StatusCode::ACCEPTED);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_webhook_rejected_without_secret() {
+        wrapper(|env| {
+            let web = env.frontend();
+
+            let unsigned = web.post("/_/index-webhook").body(PAYLOAD).send()?;
+            assert_eq!(unsigned.status(), StatusCode::FORBIDDEN);
+
+            // Even a signature computed with an empty key is not accepted
+            let mut mac = Hmac::<Sha256>::new_varkey(b"").unwrap();
+            mac.update(PAYLOAD.as_bytes());
+            let signature = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+            let signed = web
+                .post("/_/index-webhook")
+                .header(SIGNATURE_HEADER, signature)
+                .body(PAYLOAD)
+                .send()?;
+            assert_eq!(signed.status(), StatusCode::FORBIDDEN);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_webhook_payload_too_large() {
+        wrapper(|env| {
+            env.override_config(|config| {
+                config.index_webhook_secret = Some(SECRET.into());
+            });
+            let web = env.frontend();
+
+            let payload = vec![b'a'; MAX_PAYLOAD_SIZE as usize + 1];
+            let resp = web.post("/_/index-webhook").body(payload).send()?;
+            assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+            Ok(())
+        });
+    }
+}